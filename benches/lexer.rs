@@ -0,0 +1,56 @@
+//! Lexer throughput over a large generated AtomC corpus.
+//! Run with `cargo bench --bench lexer`, the size in MB can be given as argument:
+//! `cargo bench --bench lexer -- 64`
+use compiler::lexer::{Lexer, TokenKind};
+use std::time::Instant;
+
+/// Pieces of AtomC the corpus is made of. `{n}` is replaced with a counter
+/// so identifiers and constants vary
+const SNIPPETS: &[&str] = &[
+    "struct Point{n} {\n    int x;\n    int y;\n    double weights[16];\n};\n",
+    "int sum{n}(int v[], int n) {\n    int i, s;\n    s = 0;\n    for (i = 0; i < n; i = i + 1) {\n        s = s + v[i];\n    }\n    return s;\n}\n",
+    "/* Computes the average of the values\n   read by the function {n} */\ndouble avg{n}(double a, double b) {\n    return (a + b) / 2.0e0;\n}\n",
+    "void show{n}(char c) {\n    // print the char and a message\n    put_c(c);\n    put_s(\"value:\\t{n}\\n\");\n    if (c == '\\n' || c != 'x' && !(c >= 'a')) put_i(0x{n});\n}\n",
+    "int counter{n};\ndouble ratio{n};\nchar name{n}[32];\n",
+    "void main{n}() {\n    int a;\n    while (a <= {n}0) {\n        a = a * 3 - 1;\n        if (a > 1000) break;\n        else a = a + 1.5;\n    }\n}\n",
+];
+
+fn corpus(size: usize) -> String {
+    let mut text = String::with_capacity(size + 512);
+    let mut n = 0usize;
+    while text.len() < size {
+        let snippet = SNIPPETS[n % SNIPPETS.len()];
+        text.push_str(&snippet.replace("{n}", &(n % 4096).to_string()));
+        n += 1;
+    }
+    text
+}
+
+fn main() {
+    let mb = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse::<usize>().ok())
+        .unwrap_or(32);
+    let text = corpus(mb << 20);
+    let runs = 5;
+    let mut best = f64::MAX;
+    let mut tokens = 0;
+    for _ in 0..runs {
+        let start = Instant::now();
+        let token_vec = Lexer::new(&text, "corpus.c").get_tokens();
+        let elapsed = start.elapsed().as_secs_f64();
+        assert!(token_vec.iter().all(|t| t.kind != TokenKind::Error));
+        tokens = token_vec.len();
+        best = best.min(elapsed);
+    }
+    let bytes = text.len() as f64;
+    println!(
+        "lexer: {:.1} MB, {} tokens, best of {} runs: {:.3} s, {:.1} MB/s, {:.1} Mtokens/s",
+        bytes / 1e6,
+        tokens,
+        runs,
+        best,
+        bytes / 1e6 / best,
+        tokens as f64 / 1e6 / best
+    );
+}
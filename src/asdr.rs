@@ -1,96 +1,143 @@
-#[allow(dead_code)]
-use crate::lexer::{Token, TokenType};
+use crate::ast::*;
+use crate::intern::Name;
+use crate::lexer::{unescape, Token, TokenKind, TokenValue};
 
-pub struct SyntaxAnalyser {
-    pub token_vec: Vec<Token>,
-    pub current_token_idx: usize,
-    pub consumed_token: Option<Token>,
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Assoc {
+    Left,
+    Right,
 }
-impl Default for SyntaxAnalyser {
-    fn default() -> Self {
-        Self {
-            token_vec: vec![],
-            current_token_idx: 0,
-            consumed_token: None,
-            
-        }
+/// A binary operator of the precedence climbing parser
+struct BinaryOp {
+    kind: TokenKind,
+    /// Binding power, operators with a higher power bind tighter
+    power: u8,
+    assoc: Assoc,
+    /// Name of the operator in diagnostics
+    what: &'static str,
+}
+const fn op(kind: TokenKind, power: u8, assoc: Assoc, what: &'static str) -> BinaryOp {
+    BinaryOp {
+        kind,
+        power,
+        assoc,
+        what,
     }
 }
-impl SyntaxAnalyser {
+/// The binary operators of AtomC, from the loosest to the tightest.
+/// A new operator only needs a line here and a `BinOp`
+static BINARY_OPS: [BinaryOp; 23] = [
+    op(TokenKind::Assign, 1, Assoc::Right, "assign"),
+    op(TokenKind::AddAssign, 1, Assoc::Right, "assign"),
+    op(TokenKind::SubAssign, 1, Assoc::Right, "assign"),
+    op(TokenKind::MulAssign, 1, Assoc::Right, "assign"),
+    op(TokenKind::DivAssign, 1, Assoc::Right, "assign"),
+    op(TokenKind::Or, 2, Assoc::Left, "or"),
+    op(TokenKind::And, 3, Assoc::Left, "and"),
+    op(TokenKind::BitOr, 4, Assoc::Left, "bitwise or"),
+    op(TokenKind::BitXor, 5, Assoc::Left, "bitwise xor"),
+    op(TokenKind::BitAnd, 6, Assoc::Left, "bitwise and"),
+    op(TokenKind::Equal, 7, Assoc::Left, "equals"),
+    op(TokenKind::NotEq, 7, Assoc::Left, "equals"),
+    op(TokenKind::Less, 8, Assoc::Left, "relation"),
+    op(TokenKind::LessEq, 8, Assoc::Left, "relation"),
+    op(TokenKind::Greater, 8, Assoc::Left, "relation"),
+    op(TokenKind::GreaterEq, 8, Assoc::Left, "relation"),
+    op(TokenKind::Shl, 9, Assoc::Left, "shift"),
+    op(TokenKind::Shr, 9, Assoc::Left, "shift"),
+    op(TokenKind::Add, 10, Assoc::Left, "addition / subtraction"),
+    op(TokenKind::Sub, 10, Assoc::Left, "addition / subtraction"),
+    op(TokenKind::Mul, 11, Assoc::Left, "multiplication / division"),
+    op(TokenKind::Div, 11, Assoc::Left, "multiplication / division"),
+    op(TokenKind::Mod, 11, Assoc::Left, "multiplication / division"),
+];
+fn binary_op(kind: TokenKind) -> Option<&'static BinaryOp> {
+    BINARY_OPS.iter().find(|op| op.kind == kind)
+}
+
+/// Syntax analyser. Checks the grammar of AtomC and builds the `Unit` of the program
+#[derive(Default)]
+pub struct SyntaxAnalyser<'a> {
+    pub token_vec: Vec<Token<'a>>,
+    pub current_token_idx: usize,
+    pub consumed_token: Option<Token<'a>>,
+}
+impl<'a> SyntaxAnalyser<'a> {
     /// New function. Takes a Vec<Token> and sets the current token as the first one.
-    /// If the provided Vec<Token> is empty set the consumed token to None
-    pub fn new(token_vec: Vec<Token>) -> Self {
-        if token_vec.is_empty() {
-            Default::default()
-        }
+    pub fn new(token_vec: Vec<Token<'a>>) -> Self {
         Self {
             token_vec,
             current_token_idx: 0,
             consumed_token: None,
         }
     }
-    /// Start function. Use this function to analyse the syntax of the Vec<Token> provided in the constructor
-    pub fn analyse_syntax(&mut self) -> bool {
-        let x = self.rule_unit();
-        //dbg!(&x);
-        x
+    /// Start function. Use this function to analyse the syntax of the Vec<Token> provided in the constructor.
+    /// Returns the parsed tree
+    pub fn analyse_syntax(&mut self) -> Unit {
+        self.rule_unit()
     }
 
     /// Error function. Takes a message. Prints the line of the current_token and the message provided
-    fn token_error(&self, msg: &str) {
-        // eprintln!(
-        //     "Error in line:{}, {}",
-        //     self.token_vec[self.current_token_idx].line, msg
-        // );
+    fn token_error(&self, msg: &str) -> ! {
         panic!(
-            "Error in line:{}, {}",
-            self.token_vec[self.current_token_idx].line, msg
+            "Error in {}, {}",
+            self.token_vec[self.current_token_idx].location(),
+            msg
         );
     }
 
     /// Consumes the current token if it matches the code provided and moves forward
-    fn consume(&mut self, code: u8) -> bool {
-        let current_code = self.token_vec[self.current_token_idx]
-            .token_type
-            .discriminant_value();
-        if current_code == code {
-            self.consumed_token = Some(self.token_vec[self.current_token_idx].clone());
+    fn consume(&mut self, kind: TokenKind) -> bool {
+        if self.token_vec[self.current_token_idx].kind == kind {
+            self.consumed_token = Some(self.token_vec[self.current_token_idx]);
             self.current_token_idx += 1;
             return true;
         }
         false
     }
-
-    /// Sets current token and idx to the given idx
-    fn set_current_token(&mut self, idx: usize) {
-        self.current_token_idx = idx;
+    /// Kind of the token `n` positions after the current one, `End` past the last token
+    fn peek(&self, n: usize) -> TokenKind {
+        self.token_vec
+            .get(self.current_token_idx + n)
+            .map_or(TokenKind::End, |t| t.kind)
+    }
+    /// Location of the last consumed token
+    fn loc(&self) -> Loc {
+        Loc::of(&self.token_vec[self.current_token_idx - 1])
+    }
+    /// Name of the last consumed token, which must be an `Id`
+    fn consumed_name(&self) -> Name {
+        self.consumed_token.unwrap().value.get_id().unwrap()
     }
 
     /// unit: ( declStruct | declFunc | declVar )* END ;
-    /// Checks structure, functions or variables
-    fn rule_unit(&mut self) -> bool {
-        loop {
-            let temp_idx = self.current_token_idx;
-            if {
-                self.current_token_idx = temp_idx;
-                self.rule_decl_struct()
-            } || {
-                self.current_token_idx = temp_idx;
-                self.rule_decl_func()
-            } || {
-                self.current_token_idx = temp_idx;
-                self.rule_decl_var()
-            } {
+    /// Checks structure, functions or variables. The declaration is chosen by lookahead:
+    /// `struct ID {` starts a struct, `type ID (`, `type *` and `void` start a function
+    fn rule_unit(&mut self) -> Unit {
+        let mut unit = Unit::default();
+        while !self.consume(TokenKind::End) {
+            if self.peek(0) == TokenKind::Struct && self.peek(2) == TokenKind::Lacc {
+                unit.items.push(Item::Struct(self.rule_decl_struct()));
+                continue;
+            }
+            let base = match self.rule_type_base() {
+                Some(base) => base,
+                None if self.consume(TokenKind::Void) => TypeBase::Void,
+                None => self.token_error(
+                    "Top level error: Expected function / struct / variable definition",
+                ),
+            };
+            let is_func = base == TypeBase::Void
+                || self.peek(0) == TokenKind::Mul
+                || (self.peek(0) == TokenKind::Id && self.peek(1) == TokenKind::Lpar);
+            if is_func {
+                unit.items.push(Item::Func(self.rule_decl_func(base)));
             } else {
-                break;
+                let vars = self.rule_decl_var(base);
+                unit.items.extend(vars.into_iter().map(Item::Var));
             }
         }
-        if self.consume(TokenType::End.discriminant_value()) {
-            return true;
-        } else {
-            self.token_error("Top level error: Expected function / struct / variable definition");
-        }
-        false
+        unit
     }
 
     /// declStruct: STRUCT ID LACC declVar* RACC SEMICOLON ;
@@ -98,583 +145,603 @@ impl SyntaxAnalyser {
     /// struct Something {
     /// int x;
     /// };
-    fn rule_decl_struct(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.consume(TokenType::Struct.discriminant_value()) {
-            if self.consume(TokenType::Id("".to_string()).discriminant_value()) {
-                if self.consume(TokenType::Lacc.discriminant_value()) {
-                    loop {
-                        if self.rule_decl_var() {
-                        } else {
-                            //TODO  Should i reset self.current_token_idx here?
-                            break;
-                        }
-                    }
-                    if self.consume(TokenType::Racc.discriminant_value()) {
-                        if self.consume(TokenType::Semicolon.discriminant_value()) {
-                            return true;
-                        } else {
-                            self.token_error("Expected semicolon `;` after struct declaration");
-                        }
-                    } else {
-                        self.token_error("Expected closing bracket `}` at the end of the struct");
-                    }
-                } // No error if no `{`
-            } else {
-                self.token_error("Expected struct identifier");
-            }
+    /// Called on `struct ID {`
+    fn rule_decl_struct(&mut self) -> StructDecl {
+        self.consume(TokenKind::Struct);
+        self.consume(TokenKind::Id);
+        let name = self.consumed_name();
+        let loc = self.loc();
+        self.consume(TokenKind::Lacc);
+        let mut members = vec![];
+        while let Some(base) = self.rule_type_base() {
+            members.extend(self.rule_decl_var(base));
         }
-        self.current_token_idx = start_token_idx;
-        false
+        if !self.consume(TokenKind::Racc) {
+            self.token_error("Expected closing bracket `}` at the end of the struct");
+        }
+        if !self.consume(TokenKind::Semicolon) {
+            self.token_error("Expected semicolon `;` after struct declaration");
+        }
+        StructDecl { name, members, loc }
     }
-    /// declVar:  typeBase ID arrayDecl? ( COMMA ID arrayDecl? )* SEMICOLON ;
+    /// declVar:  typeBase ID arrayDecl* ( ASSIGN init )?
+    ///                     ( COMMA ID arrayDecl* ( ASSIGN init )? )* SEMICOLON ;
     /// Examples:
     /// int x;
     /// int x, y[];
-    fn rule_decl_var(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_type_base() {
-            if self.consume(TokenType::Id("".to_string()).discriminant_value()) {
-                self.rule_array_decl();
-                loop {
-                    if self.consume(TokenType::Comma.discriminant_value()) {
-                        if self.consume(TokenType::Id("".to_string()).discriminant_value()) {
-                            self.rule_array_decl();
-                        } else {
-                            self.token_error("Expected variable identifier after comma `,` ");
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                if self.consume(TokenType::Semicolon.discriminant_value()) {
-                    return true;
-                } else {
-                    self.token_error("Expected semicolon `;` after the variable declaration");
-                }
+    /// int a[] = {1, 2}, n = 2;
+    /// Called after the typeBase
+    fn rule_decl_var(&mut self, base: TypeBase) -> Vec<VarDecl> {
+        let mut vars = vec![];
+        if !self.consume(TokenKind::Id) {
+            self.token_error("Expected identifier");
+        }
+        vars.push(self.rule_var_init(base));
+        while self.consume(TokenKind::Comma) {
+            if self.consume(TokenKind::Id) {
+                vars.push(self.rule_var_init(base));
             } else {
-                self.token_error("Expected identifier");
+                self.token_error("Expected variable identifier after comma `,` ");
             }
         }
-        self.current_token_idx = start_token_idx;
-        false
+        if !self.consume(TokenKind::Semicolon) {
+            self.token_error("Expected semicolon `;` after the variable declaration");
+        }
+        vars
+    }
+    /// The variable named by the consumed `ID`, with its arrayDecls
+    fn rule_var(&mut self, base: TypeBase) -> VarDecl {
+        let name = self.consumed_name();
+        let loc = self.loc();
+        let mut ty = TypeSpec::new(base);
+        while let Some(dim) = self.rule_array_decl() {
+            ty.dims.push(dim);
+        }
+        VarDecl {
+            name,
+            ty,
+            loc,
+            init: None,
+            sym: None,
+        }
+    }
+    /// The variable with its optional `= init`
+    fn rule_var_init(&mut self, base: TypeBase) -> VarDecl {
+        let mut v = self.rule_var(base);
+        if self.consume(TokenKind::Assign) {
+            v.init = Some(self.rule_init());
+        }
+        v
+    }
+    /// init: expr | LACC ( init ( COMMA init )* )? RACC ;
+    /// Examples:
+    /// 5
+    /// {1, {2.5, 'c'}, "text"}
+    fn rule_init(&mut self) -> Init {
+        if !self.consume(TokenKind::Lacc) {
+            return match self.rule_expr() {
+                Some(e) => Init::Expr(e),
+                None => self.token_error("Expected an initializer after `=`"),
+            };
+        }
+        let loc = self.loc();
+        let mut items = vec![];
+        if !self.consume(TokenKind::Racc) {
+            items.push(self.rule_init());
+            while self.consume(TokenKind::Comma) {
+                items.push(self.rule_init());
+            }
+            if !self.consume(TokenKind::Racc) {
+                self.token_error("Expected `}` at the end of the initializer list");
+            }
+        }
+        Init::List(items, loc)
     }
     /// typeBase: INT | DOUBLE | CHAR | STRUCT ID ;
     /// Type declaration
-    fn rule_type_base(&mut self) -> bool {
-        let _start_token_idx = self.current_token_idx;
-        if self.consume(TokenType::Int.discriminant_value())
-            || self.consume(TokenType::Double.discriminant_value())
-            || self.consume(TokenType::Char.discriminant_value())
-            || (self.consume(TokenType::Struct.discriminant_value()) && {
-                if self.consume(TokenType::Id("".to_string()).discriminant_value()) {
-                    true
-                } else {
-                    self.token_error("Missing / invalid struct identifier");
-                    false
-                }
-            })
-        {
-            return true;
+    fn rule_type_base(&mut self) -> Option<TypeBase> {
+        if self.consume(TokenKind::Int) {
+            return Some(TypeBase::Int);
         }
-
-        // if self.consume(TokenType::Struct.discriminant_value()) {
-        //     if self.consume(TokenType::Id("".to_string()).discriminant_value()) {
-        //         return true;
-        //     } else {
-        //         self.token_error("Missing / invalid struct identifier");
-        //     }
-        //     // Reset token if sequence not satisfied
-        //     self.set_current_token(start_token_idx);
-        // }
-        false
+        if self.consume(TokenKind::Double) {
+            return Some(TypeBase::Double);
+        }
+        if self.consume(TokenKind::Char) {
+            return Some(TypeBase::Char);
+        }
+        if self.consume(TokenKind::Struct) {
+            if self.consume(TokenKind::Id) {
+                return Some(TypeBase::Struct(self.consumed_name()));
+            } else {
+                self.token_error("Missing / invalid struct identifier");
+            }
+        }
+        None
     }
     /// arrayDecl: LBRACKET expr? RBRACKET ;
     /// Examples:
     /// [23]
-    fn rule_array_decl(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.consume(TokenType::Lbracket.discriminant_value()) {
-            self.rule_expr();
-            if self.consume(TokenType::Rbracket.discriminant_value()) {
-                return true;
+    /// [N * 2 + 1]
+    /// Returns the size expression, `Some(None)` for `[]`
+    fn rule_array_decl(&mut self) -> Option<Option<Expr>> {
+        if self.consume(TokenKind::Lbracket) {
+            let size = self.rule_expr();
+            if self.consume(TokenKind::Rbracket) {
+                return Some(size);
             } else {
                 self.token_error("Expected `]` at the end of array declaration");
             }
         }
-        self.current_token_idx = start_token_idx;
-        false
+        None
     }
-    /// typeName: typeBase arrayDecl? ;
-    fn rule_type_name(&mut self) -> bool {
-        if self.rule_type_base() {
-            self.rule_array_decl();
-            return true;
+    /// typeName: typeBase arrayDecl* ;
+    fn rule_type_name(&mut self) -> Option<TypeSpec> {
+        let base = self.rule_type_base()?;
+        let mut ty = TypeSpec::new(base);
+        while let Some(dim) = self.rule_array_decl() {
+            ty.dims.push(dim);
         }
-        false
+        Some(ty)
     }
     /// declFunc: ( typeBase MUL? | VOID ) ID
     ///                     LPAR ( funcArg ( COMMA funcArg )* )? RPAR
-    ///                     stmCompound ;
-    fn rule_decl_func(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        let has_type = {
-            if self.rule_type_base() {
-                self.consume(TokenType::Mul.discriminant_value());
-                true
-            } else {
-                false
-            }
-        };
-        if (has_type || self.consume(TokenType::Void.discriminant_value())) && self.consume(TokenType::Id("".to_string()).discriminant_value()) && self.consume(TokenType::Lpar.discriminant_value()) {
-            self.rule_func_arg(); // funcarg is optional
-            loop {
-                if self.consume(TokenType::Comma.discriminant_value()) {
-                    if self.rule_func_arg() {
-                    } else {
-                        self.token_error("Expected function argument after ,");
-                    }
-                } else {
-                    break;
+    ///                     ( stmCompound | SEMICOLON ) ;
+    /// Called after the typeBase or VOID
+    fn rule_decl_func(&mut self, base: TypeBase) -> FuncDecl {
+        let mut ret = TypeSpec::new(base);
+        if base != TypeBase::Void && self.consume(TokenKind::Mul) {
+            ret.dims.push(None);
+        }
+        if !self.consume(TokenKind::Id) {
+            self.token_error("Expected function identifier");
+        }
+        let name = self.consumed_name();
+        let loc = self.loc();
+        if !self.consume(TokenKind::Lpar) {
+            self.token_error("Expected `(` after the function identifier");
+        }
+        let mut args = vec![];
+        // funcarg is optional
+        if let Some(arg) = self.rule_func_arg() {
+            args.push(arg);
+            while self.consume(TokenKind::Comma) {
+                match self.rule_func_arg() {
+                    Some(arg) => args.push(arg),
+                    None => self.token_error("Expected function argument after ,"),
                 }
             }
-            if self.consume(TokenType::Rpar.discriminant_value()) {
-                if self.rule_stm_compound() {
-                    return true;
-                } else {
-                    self.token_error("Expected statement after function declaration")
-                }
-            } else {
-                self.token_error("Expected `)` at the end of function declaration");
+        }
+        if !self.consume(TokenKind::Rpar) {
+            self.token_error("Expected `)` at the end of function declaration");
+        }
+        // A prototype ends with `;`
+        let body = if self.consume(TokenKind::Semicolon) {
+            None
+        } else {
+            match self.rule_stm_compound() {
+                Some(body) => Some(Box::new(body)),
+                None => self.token_error("Expected statement after function declaration"),
             }
+        };
+        FuncDecl {
+            name,
+            ret,
+            args,
+            body,
+            loc,
+            frame: 0,
+            locals: vec![],
         }
-        self.current_token_idx = start_token_idx;
-        false
     }
 
-    /// funcArg: typeBase ID arrayDecl? ;
-    fn rule_func_arg(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_type_base() {
-            if self.consume(TokenType::Id("".to_string()).discriminant_value()) {
-                self.rule_array_decl();
-                return true;
-            } else {
-                self.token_error("Expected function argument identifier");
-            }
+    /// funcArg: typeBase ID arrayDecl* ;
+    fn rule_func_arg(&mut self) -> Option<VarDecl> {
+        let base = self.rule_type_base()?;
+        if self.consume(TokenKind::Id) {
+            Some(self.rule_var(base))
+        } else {
+            self.token_error("Expected function argument identifier");
         }
-        self.current_token_idx = start_token_idx;
-        false
     }
 
     /// stm: stmCompound
     ///        | IF LPAR expr RPAR stm ( ELSE stm )?
     ///        | WHILE LPAR expr RPAR stm
     ///       | FOR LPAR expr? SEMICOLON expr? SEMICOLON expr? RPAR stm
+    ///        | DO stm WHILE LPAR expr RPAR SEMICOLON
+    ///        | SWITCH LPAR expr RPAR LACC switchCase* RACC
     ///        | BREAK SEMICOLON
+    ///        | CONTINUE SEMICOLON
     ///        | RETURN expr? SEMICOLON
     ///        | expr? SEMICOLON ;
     ///
-    fn rule_stm(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_stm_compound() {
-            return true;
+    fn rule_stm(&mut self) -> Option<Stm> {
+        if let Some(stm) = self.rule_stm_compound() {
+            return Some(stm);
         }
+        let loc = Loc::of(&self.token_vec[self.current_token_idx]);
+        let stm = |kind| Some(Stm { kind, loc });
 
         // If condition
-        if self.consume(TokenType::If.discriminant_value()) {
-            if self.consume(TokenType::Lpar.discriminant_value()) {
-                if self.rule_expr() {
-                    if self.consume(TokenType::Rpar.discriminant_value()) {
-                        if self.rule_stm() {
-                            // Optional else
-                            if self.consume(TokenType::Else.discriminant_value()) {
-                                if self.rule_stm() {
-                                } else {
-                                    self.token_error("Expected `else` statement");
-                                }
-                            }
-                            return true;
-                        } else {
-                            self.token_error("Expected `if` statement");
-                        }
-                    } else {
-                        self.token_error("Expected closing `)` after `if` condition")
-                    }
-                } else {
-                    self.token_error("Expected `if` condition");
-                }
-            } else {
+        if self.consume(TokenKind::If) {
+            if !self.consume(TokenKind::Lpar) {
                 self.token_error("Expected opening `(` before the `if` condition")
             }
+            let cond = match self.rule_expr() {
+                Some(cond) => cond,
+                None => self.token_error("Expected `if` condition"),
+            };
+            if !self.consume(TokenKind::Rpar) {
+                self.token_error("Expected closing `)` after `if` condition")
+            }
+            let then = match self.rule_stm() {
+                Some(then) => Box::new(then),
+                None => self.token_error("Expected `if` statement"),
+            };
+            // Optional else
+            let mut els = None;
+            if self.consume(TokenKind::Else) {
+                match self.rule_stm() {
+                    Some(s) => els = Some(Box::new(s)),
+                    None => self.token_error("Expected `else` statement"),
+                }
+            }
+            return stm(StmKind::If { cond, then, els });
         }
 
         // While
-        if self.consume(TokenType::While.discriminant_value()) {
-            if self.consume(TokenType::Lpar.discriminant_value()) {
-                if self.rule_expr() {
-                    if self.consume(TokenType::Rpar.discriminant_value()) {
-                        if self.rule_stm() {
-                            return true;
-                        } else {
-                            self.token_error("Expected `while` statement");
-                        }
-                    } else {
-                        self.token_error("Expected `)` after `while` condition")
-                    }
-                } else {
-                    self.token_error("Expected `while` condition");
-                }
-            } else {
+        if self.consume(TokenKind::While) {
+            if !self.consume(TokenKind::Lpar) {
                 self.token_error("Expected `(` before the `while` condition")
             }
+            let cond = match self.rule_expr() {
+                Some(cond) => cond,
+                None => self.token_error("Expected `while` condition"),
+            };
+            if !self.consume(TokenKind::Rpar) {
+                self.token_error("Expected `)` after `while` condition")
+            }
+            let body = match self.rule_stm() {
+                Some(body) => Box::new(body),
+                None => self.token_error("Expected `while` statement"),
+            };
+            return stm(StmKind::While { cond, body });
         }
         // For
-        if self.consume(TokenType::For.discriminant_value()) {
-            if self.consume(TokenType::Lpar.discriminant_value()) {
-                self.rule_expr(); // TODO should i reset if this fails?
-                if self.consume(TokenType::Semicolon.discriminant_value()) {
-                    self.rule_expr(); // TODO should i reset if this fails?
-                    if self.consume(TokenType::Semicolon.discriminant_value()) {
-                        self.rule_expr(); // TODO should i reset if this fails?
-                        if self.consume(TokenType::Rpar.discriminant_value()) {
-                            if self.rule_stm() {
-                                return true;
-                            } else {
-                                self.token_error("Expected `for` statement")
-                            }
-                        } else {
-                            self.token_error("Expected `)` at the end of the `for`")
-                        }
-                    } else {
-                        self.token_error("Expected semicolon `;` after the second `for` expression")
-                    }
-                } else {
-                    self.token_error("Expected semicolon `;` after the first `for` expression")
-                }
-            } else {
+        if self.consume(TokenKind::For) {
+            if !self.consume(TokenKind::Lpar) {
                 self.token_error("Expected `(` at the start of the `for`")
             }
-        }
-
-        if self.consume(TokenType::Break.discriminant_value()) {
-            if self.consume(TokenType::Semicolon.discriminant_value()) {
-                return true;
-            } else {
-                self.token_error("Expected semicolon `;` at the end of the `break` statement")
+            let init = self.rule_expr().map(Box::new);
+            if !self.consume(TokenKind::Semicolon) {
+                self.token_error("Expected semicolon `;` after the first `for` expression")
+            }
+            let cond = self.rule_expr().map(Box::new);
+            if !self.consume(TokenKind::Semicolon) {
+                self.token_error("Expected semicolon `;` after the second `for` expression")
             }
+            let step = self.rule_expr().map(Box::new);
+            if !self.consume(TokenKind::Rpar) {
+                self.token_error("Expected `)` at the end of the `for`")
+            }
+            let body = match self.rule_stm() {
+                Some(body) => Box::new(body),
+                None => self.token_error("Expected `for` statement"),
+            };
+            return stm(StmKind::For {
+                init,
+                cond,
+                step,
+                body,
+            });
         }
 
-        if self.consume(TokenType::Return.discriminant_value()) {
-            self.rule_expr();
-            if self.consume(TokenType::Semicolon.discriminant_value()) {
-                return true;
-            } else {
-                self.token_error("Expected semicolon `;` at the end of the `return` statement")
+        // Do while
+        if self.consume(TokenKind::Do) {
+            let body = match self.rule_stm() {
+                Some(body) => Box::new(body),
+                None => self.token_error("Expected `do` statement"),
+            };
+            if !self.consume(TokenKind::While) {
+                self.token_error("Expected `while` after the `do` statement")
             }
-        }
-        if self.rule_expr() {
-            if self.consume(TokenType::Semicolon.discriminant_value()) {
-                return true;
-            } else {
-                self.token_error("Expected semicolon `;` at the end of the expression")
+            if !self.consume(TokenKind::Lpar) {
+                self.token_error("Expected `(` before the `while` condition")
             }
-        }
-        if self.consume(TokenType::Semicolon.discriminant_value()) {
-            return true;
-        };
-        self.current_token_idx = start_token_idx;
-        false
-    }
-    /// stmCompound: LACC ( declVar | stm )* RACC ;
-    fn rule_stm_compound(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.consume(TokenType::Lacc.discriminant_value()) {
-            loop {
-                let temp_idx = self.current_token_idx;
-                if {
-                    self.current_token_idx = temp_idx;
-                    self.rule_decl_var()
-                } || {
-                    self.current_token_idx = temp_idx;
-                    self.rule_stm()
-                } {
-                } else {
-                    break;
-                }
+            let cond = match self.rule_expr() {
+                Some(cond) => cond,
+                None => self.token_error("Expected `while` condition"),
+            };
+            if !self.consume(TokenKind::Rpar) {
+                self.token_error("Expected `)` after `while` condition")
             }
-            if self.consume(TokenType::Racc.discriminant_value()) {
-                return true;
-            } else {
-                self.token_error("Expected } at the end of the statement")
+            if !self.consume(TokenKind::Semicolon) {
+                self.token_error("Expected semicolon `;` at the end of the `do` statement")
             }
+            return stm(StmKind::DoWhile { body, cond });
         }
-        self.current_token_idx = start_token_idx;
-        false
-    }
-    /// expr: exprAssign ;
-    fn rule_expr(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_expr_assign() {
-            return true;
-        }
-        self.current_token_idx = start_token_idx;
-        false
-    }
 
-    /// exprAssign: exprUnary ASSIGN exprAssign | exprOr ;
-    fn rule_expr_assign(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_expr_unary() {
-            if self.consume(TokenType::Assign.discriminant_value()) {
-                if self.rule_expr_assign() {
-                    return true;
-                } else {
-                    self.token_error("Missing right operand after `=` in assign operation")
-                }
-            } // No need to expect assign operator
-            self.current_token_idx = start_token_idx;
+        // Switch
+        if self.consume(TokenKind::Switch) {
+            if !self.consume(TokenKind::Lpar) {
+                self.token_error("Expected `(` before the `switch` value")
+            }
+            let value = match self.rule_expr() {
+                Some(value) => value,
+                None => self.token_error("Expected `switch` value"),
+            };
+            if !self.consume(TokenKind::Rpar) {
+                self.token_error("Expected `)` after `switch` value")
+            }
+            if !self.consume(TokenKind::Lacc) {
+                self.token_error("Expected `{` at the start of the `switch` body")
+            }
+            let mut cases = vec![];
+            while let Some(case) = self.rule_switch_case() {
+                cases.push(case);
+            }
+            if !self.consume(TokenKind::Racc) {
+                self.token_error("Expected `case`, `default` or `}` in the `switch` body")
+            }
+            return stm(StmKind::Switch { value, cases });
         }
 
-        // Reset before or variable
-        //self.current_token_idx = start_token_idx;
-        if self.rule_expr_or() {
-            return true;
-        }
-        self.current_token_idx = start_token_idx;
-        false
-    }
-    /// exprOr: exprOr OR exprAnd | exprAnd ;
-    fn rule_expr_or(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_expr_and() && self.rule_expr_or1() {
-            return true;
+        if self.consume(TokenKind::Break) {
+            if self.consume(TokenKind::Semicolon) {
+                return stm(StmKind::Break);
+            } else {
+                self.token_error("Expected semicolon `;` at the end of the `break` statement")
+            }
         }
-        self.current_token_idx = start_token_idx;
-        false
-    }
 
-    /// exprOr1: (OR exprAnd exprOr1)?
-    fn rule_expr_or1(&mut self) -> bool {
-        //let start_token_idx = self.current_token_idx;
-        if self.consume(TokenType::Or.discriminant_value()) {
-            if self.rule_expr_and() {
-                if self.rule_expr_or1() {
-                    return true;
-                }
+        if self.consume(TokenKind::Continue) {
+            if self.consume(TokenKind::Semicolon) {
+                return stm(StmKind::Continue);
             } else {
-                self.token_error("Expected operand in `or` expression body");
+                self.token_error("Expected semicolon `;` at the end of the `continue` statement")
             }
-        };
-        //self.current_token_idx = start_token_idx;
-        true
-    }
-
-    /// exprAnd: exprAnd AND exprEq | exprEq ;
-    fn rule_expr_and(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_expr_eq() && self.rule_expr_and1() {
-            return true;
         }
-        self.current_token_idx = start_token_idx;
-        false
-    }
-    /// exprAnd1:  (AND exprEq | exprAnd1)? ;
-    fn rule_expr_and1(&mut self) -> bool {
-        if self.consume(TokenType::And.discriminant_value()) {
-            if self.rule_expr_eq() {
-                if self.rule_expr_and1() {}
+
+        if self.consume(TokenKind::Return) {
+            let value = self.rule_expr();
+            if self.consume(TokenKind::Semicolon) {
+                return stm(StmKind::Return(value));
             } else {
-                self.token_error("Expected operand in `and` expression body");
+                self.token_error("Expected semicolon `;` at the end of the `return` statement")
             }
-        };
-        true
-    }
-    /// exprEq: exprEq ( EQUAL | NOTEQ ) exprRel | exprRel ;
-    fn rule_expr_eq(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_expr_rel() && self.rule_expr_eq1() {
-            return true;
         }
-        self.current_token_idx = start_token_idx;
-        false
-    }
-    /// exprEq1: (( EQUAL | NOTEQ ) exprRel exprEq1)?' ;
-    fn rule_expr_eq1(&mut self) -> bool {
-        if self.consume(TokenType::Equal.discriminant_value())
-            || self.consume(TokenType::NotEq.discriminant_value())
-        {
-            if self.rule_expr_rel() {
-                if self.rule_expr_eq1() {
-                    return true;
-                }
+        if let Some(e) = self.rule_expr() {
+            if self.consume(TokenKind::Semicolon) {
+                return stm(StmKind::Expr(e));
             } else {
-                self.token_error("Expected operand in `equals` expression body");
+                self.token_error("Expected semicolon `;` at the end of the expression")
             }
         }
-        true
+        if self.consume(TokenKind::Semicolon) {
+            return stm(StmKind::Empty);
+        };
+        None
     }
-
-    /// exprRel: exprRel ( LESS | LESSEQ | GREATER | GREATEREQ ) exprAdd | exprAdd ;
-    fn rule_expr_rel(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_expr_add() && self.rule_expr_rel1() {
-            return true;
+    /// switchCase: ( CASE expr | DEFAULT ) COLON ( declVar | stm )* ;
+    fn rule_switch_case(&mut self) -> Option<Case> {
+        let loc = Loc::of(&self.token_vec[self.current_token_idx]);
+        let value = if self.consume(TokenKind::Case) {
+            match self.rule_expr() {
+                Some(value) => Some(value),
+                None => self.token_error("Expected `case` value"),
+            }
+        } else if self.consume(TokenKind::Default) {
+            None
+        } else {
+            return None;
+        };
+        if !self.consume(TokenKind::Colon) {
+            self.token_error("Expected `:` after the `case` label")
         }
-        self.current_token_idx = start_token_idx;
-        false
+        let stms = self.rule_block_items();
+        Some(Case { value, stms, loc })
     }
-
-    fn rule_expr_rel1(&mut self) -> bool {
-        if self.consume(TokenType::Less.discriminant_value())
-            || self.consume(TokenType::LessEq.discriminant_value())
-            || self.consume(TokenType::Greater.discriminant_value())
-            || self.consume(TokenType::GreaterEq.discriminant_value())
-        {
-            if self.rule_expr_add() {
-                if self.rule_expr_rel1() {
-                    return true;
-                }
+    /// stmCompound: LACC ( declVar | stm )* RACC ;
+    fn rule_stm_compound(&mut self) -> Option<Stm> {
+        if self.consume(TokenKind::Lacc) {
+            let loc = self.loc();
+            let stms = self.rule_block_items();
+            if self.consume(TokenKind::Racc) {
+                return Some(Stm {
+                    kind: StmKind::Block(stms),
+                    loc,
+                });
             } else {
-                self.token_error("Expected operand in `relation` expression body");
+                self.token_error("Expected } at the end of the statement")
             }
         }
-        true
-    }
-    /// exprAdd: exprAdd ( ADD | SUB ) exprMul | exprMul ;
-    fn rule_expr_add(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_expr_mul() && self.rule_expr_add1() {
-            return true;
-        }
-        self.current_token_idx = start_token_idx;
-        false
+        None
     }
-    fn rule_expr_add1(&mut self) -> bool {
-        if self.consume(TokenType::Add.discriminant_value())
-            || self.consume(TokenType::Sub.discriminant_value())
-        {
-            if self.rule_expr_mul() {
-                if self.rule_expr_add1() {
-                    return true;
+    /// ( declVar | stm )*
+    /// A declaration starts with a typeBase, no statement does
+    fn rule_block_items(&mut self) -> Vec<Stm> {
+        let mut stms = vec![];
+        loop {
+            if let Some(base) = self.rule_type_base() {
+                for v in self.rule_decl_var(base) {
+                    let loc = v.loc;
+                    stms.push(Stm {
+                        kind: StmKind::Var(v),
+                        loc,
+                    });
                 }
+            } else if let Some(stm) = self.rule_stm() {
+                stms.push(stm);
             } else {
-                self.token_error("Expected operand in `addition / subtraction` expression body");
+                return stms;
             }
         }
-        true
     }
-    /// exprMul: exprMul ( MUL | DIV ) exprCast | exprCast ;
-    fn rule_expr_mul(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_expr_cast() && self.rule_expr_mul1() {
-            return true;
-        }
-        self.current_token_idx = start_token_idx;
-        false
+    /// expr: exprAssign ;
+    fn rule_expr(&mut self) -> Option<Expr> {
+        self.rule_expr_binary(0)
     }
 
-    fn rule_expr_mul1(&mut self) -> bool {
-        if self.consume(TokenType::Mul.discriminant_value())
-            || self.consume(TokenType::Div.discriminant_value())
-        {
-            if self.rule_expr_cast() {
-                if self.rule_expr_mul1() {
-                    return true;
-                }
-            } else {
-                self.token_error("Expected operand in `multiplication / division` expression body");
+    /// Precedence climbing over `BINARY_OPS`, for exprAssign down to exprMul:
+    /// exprAssign: exprUnary ( ASSIGN | ADDASSIGN | SUBASSIGN | MULASSIGN | DIVASSIGN )
+    ///             exprAssign | exprOr ;
+    /// exprOr: exprOr OR exprAnd | exprAnd ;
+    /// exprAnd: exprAnd AND exprBitOr | exprBitOr ;
+    /// exprBitOr: exprBitOr BITOR exprBitXor | exprBitXor ;
+    /// exprBitXor: exprBitXor BITXOR exprBitAnd | exprBitAnd ;
+    /// exprBitAnd: exprBitAnd BITAND exprEq | exprEq ;
+    /// exprEq: exprEq ( EQUAL | NOTEQ ) exprRel | exprRel ;
+    /// exprRel: exprRel ( LESS | LESSEQ | GREATER | GREATEREQ ) exprShift | exprShift ;
+    /// exprShift: exprShift ( SHL | SHR ) exprAdd | exprAdd ;
+    /// exprAdd: exprAdd ( ADD | SUB ) exprMul | exprMul ;
+    /// exprMul: exprMul ( MUL | DIV | MOD ) exprCast | exprCast ;
+    /// Parses the operators which bind tighter than `min_power`
+    fn rule_expr_binary(&mut self, min_power: u8) -> Option<Expr> {
+        let mut left = self.rule_expr_cast()?;
+        // Only an exprUnary can be assigned to
+        let mut is_unary = !matches!(left.kind, ExprKind::Cast(..));
+        loop {
+            let kind = self.peek(0);
+            let op = match binary_op(kind) {
+                Some(op) if op.power > min_power => op,
+                _ => return Some(left),
+            };
+            let is_assign = op.assoc == Assoc::Right;
+            if is_assign && !is_unary {
+                return Some(left);
             }
+            self.consume(kind);
+            let loc = self.loc();
+            let right_power = match op.assoc {
+                Assoc::Left => op.power,
+                Assoc::Right => op.power - 1,
+            };
+            let right = match self.rule_expr_binary(right_power) {
+                Some(right) => Box::new(right),
+                None if is_assign => {
+                    let text = match BinOp::from_assign_token(kind) {
+                        Some(bin_op) => format!("{}=", bin_op.text()),
+                        None => "=".to_string(),
+                    };
+                    self.token_error(&format!(
+                        "Missing right operand after `{}` in assign operation",
+                        text
+                    ))
+                }
+                None => self.token_error(&format!(
+                    "Expected operand in `{}` expression body",
+                    op.what
+                )),
+            };
+            let expr = match (BinOp::from_token(kind), BinOp::from_assign_token(kind)) {
+                (Some(bin_op), _) => ExprKind::Binary(bin_op, Box::new(left), right),
+                (None, Some(bin_op)) => ExprKind::OpAssign(bin_op, Box::new(left), right),
+                (None, None) => ExprKind::Assign(Box::new(left), right),
+            };
+            left = Expr::new(expr, loc);
+            is_unary = false;
         }
-        true
     }
     /// exprCast: LPAR typeName RPAR exprCast | exprUnary ;
     /// Examples:
     /// (int)x;
     /// (int)(double)x;
-    fn rule_expr_cast(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.consume(TokenType::Lpar.discriminant_value()) {
-            if self.rule_type_name() {
-                if self.consume(TokenType::Rpar.discriminant_value()) {
-                    if self.rule_expr_cast() {
-                        return true;
-                    }
-                    else {
-                        self.token_error("Invalid `cast` expression")
-                    }
-                } else {
-                    self.token_error("Expected closing `)` in `cast` expression")
-                }
-            } else {
-                self.token_error("Expected `type` in `cast` expression")
+    fn rule_expr_cast(&mut self) -> Option<Expr> {
+        // `(` without a type starts a parenthesized expression
+        let next = self.peek(1);
+        let starts_type = [
+            TokenKind::Int,
+            TokenKind::Double,
+            TokenKind::Char,
+            TokenKind::Struct,
+        ];
+        if self.peek(0) == TokenKind::Lpar && starts_type.contains(&next) {
+            self.consume(TokenKind::Lpar);
+            let loc = self.loc();
+            let ty = self.rule_type_name().unwrap();
+            if !self.consume(TokenKind::Rpar) {
+                self.token_error("Expected closing `)` in `cast` expression")
+            }
+            match self.rule_expr_cast() {
+                Some(e) => return Some(Expr::new(ExprKind::Cast(ty, Box::new(e)), loc)),
+                None => self.token_error("Invalid `cast` expression"),
             }
-            self.current_token_idx = start_token_idx;
-        }
-        // TODO should i reset here?
-        self.current_token_idx = start_token_idx;
-        if self.rule_expr_unary() {
-            return true;
         }
-        self.current_token_idx = start_token_idx;
-        false
+        self.rule_expr_unary()
     }
 
-    /// exprUnary: ( SUB | NOT ) exprUnary | exprPostfix ;
-    /// Check if and expression starts with `-` or `!`
-    fn rule_expr_unary(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if (self.consume(TokenType::Sub.discriminant_value())
-            || self.consume(TokenType::Not.discriminant_value())) && self.rule_expr_unary() {
-            return true;
+    /// exprUnary: ( SUB | NOT | BITNOT | INC | DEC ) exprUnary | exprPostfix ;
+    /// Check if and expression starts with `-`, `!`, `~`, `++` or `--`
+    fn rule_expr_unary(&mut self) -> Option<Expr> {
+        if self.consume(TokenKind::Inc) || self.consume(TokenKind::Dec) {
+            let loc = self.loc();
+            let op = BinOp::from_assign_token(self.consumed_token.unwrap().kind).unwrap();
+            match self.rule_expr_unary() {
+                Some(e) => {
+                    let operand = Box::new(e);
+                    let kind = ExprKind::IncDec {
+                        op,
+                        prefix: true,
+                        operand,
+                    };
+                    return Some(Expr::new(kind, loc));
+                }
+                None => self.token_error("Invalid unary expression"),
+            }
         }
-
-        //self.current_token_idx = start_token_idx;
-        if self.rule_expr_postfix() {
-            return true;
+        if self.consume(TokenKind::Sub)
+            || self.consume(TokenKind::Not)
+            || self.consume(TokenKind::BitNot)
+        {
+            let loc = self.loc();
+            let op = UnaryOp::from_token(self.consumed_token.unwrap().kind).unwrap();
+            match self.rule_expr_unary() {
+                Some(e) => return Some(Expr::new(ExprKind::Unary(op, Box::new(e)), loc)),
+                None => self.token_error("Invalid unary expression"),
+            }
         }
-        self.current_token_idx = start_token_idx;
-        false
+        self.rule_expr_postfix()
     }
 
     /// exprPostfix: exprPostfix LBRACKET expr RBRACKET
     /// | exprPostfix DOT ID
+    /// | exprPostfix ( INC | DEC )
     /// | exprPrimary ;
-    fn rule_expr_postfix(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.rule_expr_primary() && self.rule_expr_postfix1() {
-            return true;
-        }
-        self.current_token_idx = start_token_idx;
-        false
-    }
-    fn rule_expr_postfix1(&mut self) -> bool {
-        if self.consume(TokenType::Lbracket.discriminant_value()) {
-            if self.rule_expr() {
-                if self.consume(TokenType::Rbracket.discriminant_value()) {
-                    if self.rule_expr_postfix1() {
-                        return true;
+    fn rule_expr_postfix(&mut self) -> Option<Expr> {
+        let mut e = self.rule_expr_primary()?;
+        loop {
+            if self.consume(TokenKind::Lbracket) {
+                let loc = self.loc();
+                match self.rule_expr() {
+                    Some(index) => {
+                        if !self.consume(TokenKind::Rbracket) {
+                            self.token_error("Expected `]` in `postfix` rule");
+                        }
+                        e = Expr::new(ExprKind::Index(Box::new(e), Box::new(index)), loc);
                     }
-                } else {
-                    self.token_error("Expected `]` in `postfix` rule");
+                    None => self.token_error("Expected `expression` after `[`"),
                 }
-            } else {
-                self.token_error("Expected `expression` after `[`")
-            }
-        }
-        // TODO should i reset here?
-        //self.current_token_idx = start_token_idx;
-        if self.consume(TokenType::Dot.discriminant_value()) {
-            if self.consume(TokenType::Id("".to_string()).discriminant_value()) {
-                if self.rule_expr_postfix1() {
-                    return true;
+            } else if self.consume(TokenKind::Inc) || self.consume(TokenKind::Dec) {
+                let loc = self.loc();
+                let op = BinOp::from_assign_token(self.consumed_token.unwrap().kind).unwrap();
+                let operand = Box::new(e);
+                let kind = ExprKind::IncDec {
+                    op,
+                    prefix: false,
+                    operand,
+                };
+                e = Expr::new(kind, loc);
+            } else if self.consume(TokenKind::Dot) {
+                let loc = self.loc();
+                if self.consume(TokenKind::Id) {
+                    e = Expr::new(ExprKind::Member(Box::new(e), self.consumed_name()), loc);
+                } else {
+                    self.token_error("Expected identifier after `.`");
                 }
             } else {
-                self.token_error("Expected identifier after `.`");
+                return Some(e);
             }
         }
-        //self.current_token_idx = start_token_idx;
-        true
     }
 
     /// exprPrimary: ID ( LPAR ( expr ( COMMA expr )* )? RPAR )?
@@ -683,66 +750,277 @@ impl SyntaxAnalyser {
     /// | CT_CHAR
     /// | CT_STRING
     /// | LPAR expr RPAR ;
-    fn rule_expr_primary(&mut self) -> bool {
-        let start_token_idx = self.current_token_idx;
-        if self.consume(TokenType::Id("".to_string()).discriminant_value()) {
+    fn rule_expr_primary(&mut self) -> Option<Expr> {
+        if self.consume(TokenKind::Id) {
+            let name = self.consumed_name();
+            let loc = self.loc();
             // Optional
-            if self.consume(TokenType::Lpar.discriminant_value()) {
-                if self.rule_expr() {
-                    loop {
-                        if self.consume(TokenType::Comma.discriminant_value()) {
-                            if self.rule_expr() {
-                            } else {
-                                self.token_error(
-                                    "expected `expression` after `comma` in primary rule",
-                                );
-                            }
-                        } else {
-                            break;
+            if self.consume(TokenKind::Lpar) {
+                let mut args = vec![];
+                if let Some(arg) = self.rule_expr() {
+                    args.push(arg);
+                    while self.consume(TokenKind::Comma) {
+                        match self.rule_expr() {
+                            Some(arg) => args.push(arg),
+                            None => self
+                                .token_error("expected `expression` after `comma` in primary rule"),
                         }
                     }
                 } // no else because it's optional
-                if self.consume(TokenType::Rpar.discriminant_value()) {
-                } else {
+                if !self.consume(TokenKind::Rpar) {
                     self.token_error("Expected closing `)` after expression body");
                 }
+                return Some(Expr::new(ExprKind::Call(name, args), loc));
             }
-            return true;
+            return Some(Expr::new(ExprKind::Id(name), loc));
         }
 
-        //self.current_token_idx = start_token_idx;
-        if self.consume(TokenType::CtInt(0).discriminant_value())
-            || self.consume(TokenType::CtReal(0.).discriminant_value())
-            || self.consume(TokenType::CtChar('a').discriminant_value())
-            || self.consume(TokenType::CtString("".to_string()).discriminant_value())
-        {
-            return true;
+        let token = self.token_vec[self.current_token_idx];
+        let constant = match token.value {
+            TokenValue::Int(i) => Some(ExprKind::Int(i)),
+            TokenValue::Real(d) => Some(ExprKind::Real(d)),
+            TokenValue::Char(c) => Some(ExprKind::Char(c)),
+            TokenValue::Str(s) => Some(ExprKind::Str(unescape(s))),
+            _ => None,
+        };
+        if let Some(kind) = constant {
+            self.consume(token.kind);
+            return Some(Expr::new(kind, self.loc()));
         }
-        if self.consume(TokenType::Lpar.discriminant_value()) && self.rule_expr() {
-            if self.consume(TokenType::Rpar.discriminant_value()) {
-                return true;
-            } else {
-                self.token_error("Expected closing `)` after expression");
+        if self.consume(TokenKind::Lpar) {
+            match self.rule_expr() {
+                Some(e) if self.consume(TokenKind::Rpar) => return Some(e),
+                Some(_) => self.token_error("Expected closing `)` after expression"),
+                None => self.token_error("Expected expression after `(`"),
             }
         }
-        self.current_token_idx = start_token_idx;
-        false
+        None
     }
 }
 
 #[cfg(test)]
 pub mod tests {
     use crate::asdr::SyntaxAnalyser;
+    use crate::ast::*;
     use crate::lexer::Lexer;
+    use std::fs;
     #[test]
     fn syntax_test() {
-        //let mut lexer = Lexer::from_file("../tests/test_syntax.txt");
-        let mut lexer = Lexer::from_file("../tests/test_syntax.c");
+        let contents = fs::read_to_string("tests/test_syntax.c")
+            .expect("Something went wrong reading the file");
+        let mut lexer = Lexer::new(&contents, "tests/test_syntax.c");
         let token_vec = lexer.get_tokens();
-        for elem in &token_vec {
-            println!("{:?}", elem);
+        let mut syntax_analyser = SyntaxAnalyser::new(token_vec);
+        let unit = syntax_analyser.analyse_syntax();
+        // struct Pt, points, max, len and main
+        assert_eq!(unit.items.len(), 5);
+        match &unit.items[1] {
+            Item::Var(v) => {
+                assert_eq!(v.name.as_str(), "points");
+                assert!(matches!(
+                    v.ty.dims[..],
+                    [Some(Expr {
+                        kind: ExprKind::Int(10),
+                        ..
+                    })]
+                ));
+            }
+            item => panic!("expected a variable, found {:?}", item),
+        }
+    }
+    #[test]
+    fn syntax_top_level_lookahead() {
+        let src = "struct P { int x; }; struct P p, q[2]; struct P f(struct P a) { return a; }
+            double *g(double v[]) { return v; } void h() { struct P r; int i; r.x = i; }";
+        let unit = SyntaxAnalyser::new(Lexer::new(src, "").get_tokens()).analyse_syntax();
+        let kinds: Vec<String> = unit
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Struct(s) => format!("struct {}", s.name),
+                Item::Var(v) => format!("var {}: {}", v.name, v.ty),
+                Item::Func(f) => format!("func {}: {}", f.name, f.ret),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "struct P",
+                "var p: struct P",
+                "var q: struct P[2]",
+                "func f: struct P",
+                "func g: double[]",
+                "func h: void",
+            ]
+        );
+    }
+    #[test]
+    #[should_panic(expected = "Expected `(` after the function identifier")]
+    fn syntax_void_variable() {
+        SyntaxAnalyser::new(Lexer::new("void x;", "").get_tokens()).analyse_syntax();
+    }
+    /// The first expression statement of `src`, fully parenthesized
+    fn expr_tree(src: &str) -> String {
+        let mut lexer = Lexer::new(src, "");
+        let unit = SyntaxAnalyser::new(lexer.get_tokens()).analyse_syntax();
+        let body = match &unit.items[0] {
+            Item::Func(f) => f.body.as_ref().unwrap(),
+            item => panic!("expected a function, found {:?}", item),
+        };
+        let e = match &body.kind {
+            StmKind::Block(stms) => match &stms[0].kind {
+                StmKind::Expr(e) => e,
+                stm => panic!("expected an expression, found {:?}", stm),
+            },
+            stm => panic!("expected a block, found {:?}", stm),
+        };
+        fn show(e: &Expr) -> String {
+            match &e.kind {
+                ExprKind::Id(n) => n.to_string(),
+                ExprKind::Int(i) => i.to_string(),
+                ExprKind::Assign(a, b) => format!("({} = {})", show(a), show(b)),
+                ExprKind::OpAssign(op, a, b) => {
+                    format!("({} {}= {})", show(a), op.text(), show(b))
+                }
+                ExprKind::IncDec {
+                    op,
+                    prefix: true,
+                    operand,
+                } => format!("{}{}{}", op.text(), op.text(), show(operand)),
+                ExprKind::IncDec { op, operand, .. } => {
+                    format!("({}{}{})", show(operand), op.text(), op.text())
+                }
+                ExprKind::Binary(op, a, b) => format!("({} {} {})", show(a), op.text(), show(b)),
+                ExprKind::Unary(op, a) => format!("{}{}", op.text(), show(a)),
+                ExprKind::Cast(t, a) => format!("({}){}", t.base, show(a)),
+                ExprKind::Member(a, m) => format!("{}.{}", show(a), m),
+                ExprKind::Index(a, i) => format!("{}[{}]", show(a), show(i)),
+                kind => format!("{:?}", kind),
+            }
         }
-        let mut syntax_analyser: SyntaxAnalyser = SyntaxAnalyser::new(token_vec);
-        syntax_analyser.analyse_syntax();
+        show(e)
+    }
+    #[test]
+    fn syntax_expr_tree() {
+        assert_eq!(
+            expr_tree("void f(){ x = (a + b) * -c - (int)d.e[1]; }"),
+            "(x = (((a + b) * -c) - (int)d.e[1]))"
+        );
+    }
+    #[test]
+    fn syntax_expr_precedence() {
+        assert_eq!(
+            expr_tree("void f(){ a = b = c || d && e == f < g + h * i; }"),
+            "(a = (b = (c || (d && (e == (f < (g + (h * i))))))))"
+        );
+        assert_eq!(
+            expr_tree("void f(){ a - b - c / d / e; }"),
+            "((a - b) - ((c / d) / e))"
+        );
+        assert_eq!(
+            expr_tree("void f(){ (a) = !-b < (double)c; }"),
+            "(a = (!-b < (double)c))"
+        );
+    }
+    #[test]
+    #[should_panic(expected = "Expected semicolon `;` at the end of the expression")]
+    fn syntax_assign_needs_unary() {
+        expr_tree("void f(){ a + b = c; }");
+    }
+    #[test]
+    fn syntax_bitwise_precedence() {
+        assert_eq!(
+            expr_tree("void f(){ a = b | c ^ d & e == f << g + h % i; }"),
+            "(a = (b | (c ^ (d & (e == (f << (g + (h % i))))))))"
+        );
+        assert_eq!(
+            expr_tree("void f(){ a && b | c < d >> 1 * ~e; }"),
+            "(a && (b | (c < (d >> (1 * ~e)))))"
+        );
+    }
+    #[test]
+    fn syntax_compound_assign() {
+        assert_eq!(
+            expr_tree("void f(){ a += b -= c * d; }"),
+            "(a += (b -= (c * d)))"
+        );
+        assert_eq!(
+            expr_tree("void f(){ a[i++] *= -++b.x + c--; }"),
+            "(a[(i++)] *= (-++b.x + (c--)))"
+        );
+        assert_eq!(expr_tree("void f(){ a - --b; }"), "(a - --b)");
+    }
+    #[test]
+    #[should_panic(expected = "Missing right operand after `/=` in assign operation")]
+    fn syntax_compound_assign_operand() {
+        expr_tree("void f(){ a /= ; }");
+    }
+    #[test]
+    fn syntax_switch_and_do() {
+        let src = "void f(int x) {
+            do { x--; continue; } while (x);
+            switch (x) { case 1: case 2: int y; y = x; break; default: ; }
+        }";
+        let unit = SyntaxAnalyser::new(Lexer::new(src, "").get_tokens()).analyse_syntax();
+        let stms = match &unit.items[0] {
+            Item::Func(f) => match &f.body.as_ref().unwrap().kind {
+                StmKind::Block(stms) => stms,
+                stm => panic!("expected a block, found {:?}", stm),
+            },
+            item => panic!("expected a function, found {:?}", item),
+        };
+        match &stms[0].kind {
+            StmKind::DoWhile { body, cond } => {
+                assert_eq!(cond.to_string(), "x");
+                assert!(
+                    matches!(&body.kind, StmKind::Block(b) if matches!(b[1].kind, StmKind::Continue))
+                );
+            }
+            stm => panic!("expected a do while, found {:?}", stm),
+        }
+        match &stms[1].kind {
+            StmKind::Switch { value, cases } => {
+                assert_eq!(value.to_string(), "x");
+                let labels: Vec<(Option<String>, usize, usize)> = cases
+                    .iter()
+                    .map(|c| {
+                        (
+                            c.value.as_ref().map(|v| v.to_string()),
+                            c.stms.len(),
+                            c.loc.line,
+                        )
+                    })
+                    .collect();
+                assert_eq!(
+                    labels,
+                    [
+                        (Some("1".into()), 0, 3),
+                        (Some("2".into()), 3, 3),
+                        (None, 1, 3)
+                    ]
+                );
+            }
+            stm => panic!("expected a switch, found {:?}", stm),
+        }
+    }
+    #[test]
+    #[should_panic(expected = "Expected `:` after the `case` label")]
+    fn syntax_case_colon() {
+        SyntaxAnalyser::new(Lexer::new("void f() { switch (1) { case 1; } }", "").get_tokens())
+            .analyse_syntax();
+    }
+    #[test]
+    fn syntax_initializers() {
+        let src = "int a[] = {1, {2, 'c'}, \"s\"}, n = 2 * 3, e[2] = {}; struct P p;";
+        let unit = SyntaxAnalyser::new(Lexer::new(src, "").get_tokens()).analyse_syntax();
+        let inits: Vec<String> = unit
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Var(v) => v.init.as_ref().map_or("-".into(), |i| i.to_string()),
+                item => panic!("expected a variable, found {:?}", item),
+            })
+            .collect();
+        assert_eq!(inits, ["{1, {2, 'c'}, \"s\"}", "(2 * 3)", "{}", "-"]);
     }
 }
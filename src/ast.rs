@@ -0,0 +1,419 @@
+use crate::intern::Name;
+use crate::lexer::{Token, TokenKind};
+use crate::symbols::{RetVal, Symbol, SymbolType};
+use std::fmt;
+
+/// Where a node starts in the source
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Loc {
+    /// The file of the node, empty if the source was not read from a file
+    pub file: Name,
+    pub line: usize,
+}
+impl Loc {
+    pub fn of(token: &Token) -> Self {
+        Self {
+            file: Name::intern(token.file),
+            line: token.line,
+        }
+    }
+}
+/// Same format as `Token::location`: `file:line` or `line N`
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.file.as_str().is_empty() {
+            write!(f, "line {}", self.line)
+        } else {
+            write!(f, "{}:{}", self.file, self.line)
+        }
+    }
+}
+
+/// unit: ( declStruct | declFunc | declVar )* END ;
+#[derive(Clone, Debug, Default)]
+pub struct Unit {
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Item {
+    Struct(StructDecl),
+    Func(FuncDecl),
+    Var(VarDecl),
+}
+
+/// typeBase: INT | DOUBLE | CHAR | STRUCT ID ; and VOID for functions
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TypeBase {
+    Int,
+    Double,
+    Char,
+    Void,
+    Struct(Name),
+}
+impl fmt::Display for TypeBase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeBase::Int => write!(f, "int"),
+            TypeBase::Double => write!(f, "double"),
+            TypeBase::Char => write!(f, "char"),
+            TypeBase::Void => write!(f, "void"),
+            TypeBase::Struct(name) => write!(f, "struct {}", name),
+        }
+    }
+}
+
+/// typeName: typeBase arrayDecl? ;
+#[derive(Clone, Debug)]
+pub struct TypeSpec {
+    pub base: TypeBase,
+    /// One entry for every `[size]`, `None` for `[]`
+    pub dims: Vec<Option<Expr>>,
+}
+impl TypeSpec {
+    pub fn new(base: TypeBase) -> Self {
+        Self { base, dims: vec![] }
+    }
+    pub fn is_array(&self) -> bool {
+        !self.dims.is_empty()
+    }
+}
+
+/// `int`, `char[]`, `struct Pt[10]`, `char[(N + 1)]`
+impl fmt::Display for TypeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.base)?;
+        for dim in &self.dims {
+            match dim {
+                None => write!(f, "[]")?,
+                Some(size) => write!(f, "[{}]", size)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A variable, a struct member or a function argument: `int v[10]`.
+/// `int x, y;` gives a `VarDecl` for each name
+#[derive(Clone, Debug)]
+pub struct VarDecl {
+    pub name: Name,
+    pub ty: TypeSpec,
+    pub loc: Loc,
+    /// `= {1, 2}`, only the variables can have one
+    pub init: Option<Init>,
+    /// The symbol of a variable, set by name resolution
+    pub sym: Option<Box<Symbol>>,
+}
+
+/// init: expr | LACC ( init ( COMMA init )* )? RACC ;
+#[derive(Clone, Debug)]
+pub enum Init {
+    Expr(Expr),
+    /// The elements of an array or the members of a struct, in order
+    List(Vec<Init>, Loc),
+}
+impl Init {
+    pub fn loc(&self) -> Loc {
+        match self {
+            Init::Expr(e) => e.loc,
+            Init::List(_, loc) => *loc,
+        }
+    }
+}
+/// `{1, {2.5, 'c'}, "text"}`
+impl fmt::Display for Init {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Init::Expr(e) => write!(f, "{}", e),
+            Init::List(items, _) => {
+                write!(f, "{{")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// declStruct: STRUCT ID LACC declVar* RACC SEMICOLON ;
+#[derive(Clone, Debug)]
+pub struct StructDecl {
+    pub name: Name,
+    pub members: Vec<VarDecl>,
+    pub loc: Loc,
+}
+
+/// declFunc: ( typeBase MUL? | VOID ) ID LPAR ( funcArg ( COMMA funcArg )* )? RPAR ( stmCompound | SEMICOLON ) ;
+#[derive(Clone, Debug)]
+pub struct FuncDecl {
+    pub name: Name,
+    /// The return type. `typeBase MUL` returns an array, it has a single dimension without size
+    pub ret: TypeSpec,
+    pub args: Vec<VarDecl>,
+    /// `None` for a prototype: `int f(int x);`
+    pub body: Option<Box<Stm>>,
+    pub loc: Loc,
+    /// Bytes of the local variables, set by name resolution
+    pub frame: usize,
+    /// The local variables of all the blocks of the body, set by name resolution
+    pub locals: Vec<Symbol>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stm {
+    pub kind: StmKind,
+    pub loc: Loc,
+}
+
+#[derive(Clone, Debug)]
+pub enum StmKind {
+    /// stmCompound: LACC ( declVar | stm )* RACC ;
+    Block(Vec<Stm>),
+    /// A variable declared in a block
+    Var(VarDecl),
+    If {
+        cond: Expr,
+        then: Box<Stm>,
+        els: Option<Box<Stm>>,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stm>,
+    },
+    For {
+        init: Option<Box<Expr>>,
+        cond: Option<Box<Expr>>,
+        step: Option<Box<Expr>>,
+        body: Box<Stm>,
+    },
+    /// The body runs once before the condition is tested
+    DoWhile {
+        body: Box<Stm>,
+        cond: Expr,
+    },
+    /// The cases share one scope, a case without `break` continues with the next one
+    Switch {
+        value: Expr,
+        cases: Vec<Case>,
+    },
+    Break,
+    Continue,
+    Return(Option<Expr>),
+    Expr(Expr),
+    /// A lone `;`
+    Empty,
+}
+
+/// A `case` or the `default` of a `switch`, with the statements up to the next one
+#[derive(Clone, Debug)]
+pub struct Case {
+    /// `None` for `default`
+    pub value: Option<Expr>,
+    pub stms: Vec<Stm>,
+    pub loc: Loc,
+}
+
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub loc: Loc,
+    /// The symbol of an `Id` or of the function of a `Call`, set by name resolution
+    pub sym: Option<Box<Symbol>>,
+    /// The type of the expression, set by the type checker
+    pub rv: RetVal,
+}
+impl Expr {
+    pub fn new(kind: ExprKind, loc: Loc) -> Self {
+        Self {
+            kind,
+            loc,
+            sym: None,
+            rv: RetVal::default(),
+        }
+    }
+    /// The type set by the type checker
+    pub fn ty(&self) -> &SymbolType {
+        self.rv
+            .symbol_type
+            .as_ref()
+            .expect("the expression was not type checked")
+    }
+}
+
+/// The source of the expression, with the binary operations in parentheses: `x = (a + 1)`
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Int(i) => write!(f, "{}", i),
+            ExprKind::Real(d) => write!(f, "{:?}", d),
+            ExprKind::Char(c) => write!(f, "{:?}", c),
+            ExprKind::Str(s) => write!(f, "{:?}", s),
+            ExprKind::Id(name) => write!(f, "{}", name),
+            ExprKind::Call(name, args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Index(array, index) => write!(f, "{}[{}]", array, index),
+            ExprKind::Member(object, member) => write!(f, "{}.{}", object, member),
+            ExprKind::Unary(op, operand) => write!(f, "{}{}", op.text(), operand),
+            ExprKind::Binary(op, left, right) => {
+                write!(f, "({} {} {})", left, op.text(), right)
+            }
+            ExprKind::Assign(dst, src) => write!(f, "{} = {}", dst, src),
+            ExprKind::OpAssign(op, dst, src) => write!(f, "{} {}= {}", dst, op.text(), src),
+            ExprKind::IncDec {
+                op,
+                prefix: true,
+                operand,
+            } => write!(f, "{}{}{}", op.text(), op.text(), operand),
+            ExprKind::IncDec { op, operand, .. } => {
+                write!(f, "{}{}{}", operand, op.text(), op.text())
+            }
+            ExprKind::Cast(ty, operand) => write!(f, "({}){}", ty, operand),
+            ExprKind::Conv(operand) => write!(f, "{}", operand),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ExprKind {
+    Int(isize),
+    Real(f32),
+    Char(char),
+    /// A string constant with its escape sequences replaced
+    Str(String),
+    Id(Name),
+    Call(Name, Vec<Expr>),
+    /// `array[index]`
+    Index(Box<Expr>, Box<Expr>),
+    /// `expr.member`
+    Member(Box<Expr>, Name),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Assign(Box<Expr>, Box<Expr>),
+    /// `dst op= src`, the address of `dst` is computed once
+    OpAssign(BinOp, Box<Expr>, Box<Expr>),
+    /// `++x`, `x--`: `op` is `Add` or `Sub`, `prefix` if it is written before the operand
+    IncDec {
+        op: BinOp,
+        prefix: bool,
+        operand: Box<Expr>,
+    },
+    Cast(TypeSpec, Box<Expr>),
+    /// An implicit conversion to the type of the node, inserted by the type checker
+    Conv(Box<Expr>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+impl UnaryOp {
+    pub fn from_token(kind: TokenKind) -> Option<Self> {
+        match kind {
+            TokenKind::Sub => Some(UnaryOp::Neg),
+            TokenKind::Not => Some(UnaryOp::Not),
+            TokenKind::BitNot => Some(UnaryOp::BitNot),
+            _ => None,
+        }
+    }
+    pub fn text(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+            UnaryOp::BitNot => "~",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BinOp {
+    Or,
+    And,
+    Equal,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+impl BinOp {
+    pub fn from_token(kind: TokenKind) -> Option<Self> {
+        match kind {
+            TokenKind::Or => Some(BinOp::Or),
+            TokenKind::And => Some(BinOp::And),
+            TokenKind::Equal => Some(BinOp::Equal),
+            TokenKind::NotEq => Some(BinOp::NotEq),
+            TokenKind::Less => Some(BinOp::Less),
+            TokenKind::LessEq => Some(BinOp::LessEq),
+            TokenKind::Greater => Some(BinOp::Greater),
+            TokenKind::GreaterEq => Some(BinOp::GreaterEq),
+            TokenKind::Add => Some(BinOp::Add),
+            TokenKind::Sub => Some(BinOp::Sub),
+            TokenKind::Mul => Some(BinOp::Mul),
+            TokenKind::Div => Some(BinOp::Div),
+            TokenKind::Mod => Some(BinOp::Mod),
+            TokenKind::BitAnd => Some(BinOp::BitAnd),
+            TokenKind::BitOr => Some(BinOp::BitOr),
+            TokenKind::BitXor => Some(BinOp::BitXor),
+            TokenKind::Shl => Some(BinOp::Shl),
+            TokenKind::Shr => Some(BinOp::Shr),
+            _ => None,
+        }
+    }
+    /// The operator of a compound assignment: `+=` is `Add`
+    pub fn from_assign_token(kind: TokenKind) -> Option<Self> {
+        match kind {
+            TokenKind::AddAssign | TokenKind::Inc => Some(BinOp::Add),
+            TokenKind::SubAssign | TokenKind::Dec => Some(BinOp::Sub),
+            TokenKind::MulAssign => Some(BinOp::Mul),
+            TokenKind::DivAssign => Some(BinOp::Div),
+            _ => None,
+        }
+    }
+    pub fn text(self) -> &'static str {
+        match self {
+            BinOp::Or => "||",
+            BinOp::And => "&&",
+            BinOp::Equal => "==",
+            BinOp::NotEq => "!=",
+            BinOp::Less => "<",
+            BinOp::LessEq => "<=",
+            BinOp::Greater => ">",
+            BinOp::GreaterEq => ">=",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+        }
+    }
+}
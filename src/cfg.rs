@@ -0,0 +1,413 @@
+//! Control flow graphs of the function bodies. A body is split in basic blocks, runs of
+//! statements without jumps, linked by the jumps of `if`, the loops, `switch`, `break`,
+//! `continue` and `return`
+use crate::ast::*;
+use crate::intern::Name;
+use crate::symbols::CtVal;
+
+/// The index of a block in `Cfg::blocks`
+pub type BlockId = usize;
+
+/// A statement of a basic block, which continues with the next one
+#[derive(Copy, Clone, Debug)]
+pub enum Inst<'a> {
+    Var(&'a VarDecl),
+    /// An expression evaluated for its side effects
+    Eval(&'a Expr),
+}
+
+/// How a basic block ends
+#[derive(Clone, Debug)]
+pub enum Terminator<'a> {
+    Goto(BlockId),
+    /// Goes to `then` if `cond` is true, else to `els`
+    Branch {
+        cond: &'a Expr,
+        then: BlockId,
+        els: BlockId,
+    },
+    /// Goes to the block of the case equal to `value`, else to `default`
+    Switch {
+        value: &'a Expr,
+        cases: Vec<(&'a Expr, BlockId)>,
+        default: BlockId,
+    },
+    Return(Option<&'a Expr>),
+    /// The end of the body, reached without a `return`
+    End,
+}
+
+#[derive(Clone, Debug)]
+pub struct Block<'a> {
+    pub insts: Vec<Inst<'a>>,
+    pub term: Terminator<'a>,
+    /// The location of the first statement of the block, `None` for the blocks which
+    /// only join other blocks
+    pub loc: Option<Loc>,
+}
+
+/// The graph of a function. The body starts with the block 0
+#[derive(Clone, Debug)]
+pub struct Cfg<'a> {
+    pub name: Name,
+    pub blocks: Vec<Block<'a>>,
+}
+
+/// The truth of a constant condition
+fn constant(cond: &Expr) -> Option<bool> {
+    match (&cond.kind, &cond.rv.ctval) {
+        (ExprKind::Int(n), _) => Some(*n != 0),
+        (_, Some(CtVal::IntChar(n))) if cond.rv.is_ctval => Some(*n != 0),
+        (_, Some(CtVal::Double(d))) if cond.rv.is_ctval => Some(*d != 0.),
+        _ => None,
+    }
+}
+/// The value of a constant `switch` value or `case`
+fn constant_int(e: &Expr) -> Option<isize> {
+    match (&e.kind, &e.rv.ctval) {
+        (ExprKind::Int(n), _) => Some(*n),
+        (_, Some(CtVal::IntChar(n))) if e.rv.is_ctval => Some(*n),
+        _ => None,
+    }
+}
+
+/// The graphs of the functions of `unit` which have a body
+pub fn build(unit: &Unit) -> Vec<Cfg<'_>> {
+    unit.items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Func(FuncDecl {
+                name,
+                body: Some(body),
+                ..
+            }) => Some(Cfg::new(*name, body)),
+            _ => None,
+        })
+        .collect()
+}
+
+impl<'a> Cfg<'a> {
+    pub fn new(name: Name, body: &'a Stm) -> Self {
+        let mut builder = Builder {
+            blocks: vec![],
+            current: 0,
+            breaks: vec![],
+            continues: vec![],
+        };
+        builder.new_block();
+        builder.lower(body);
+        Self {
+            name,
+            blocks: builder.blocks,
+        }
+    }
+
+    /// The blocks which can follow `id`. A condition which is a constant, also one folded
+    /// by the type checker, only has the edge it takes
+    pub fn successors(&self, id: BlockId) -> Vec<BlockId> {
+        match &self.blocks[id].term {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::Branch { cond, then, els } => match constant(cond) {
+                Some(false) => vec![*els],
+                Some(true) => vec![*then],
+                None => vec![*then, *els],
+            },
+            Terminator::Switch {
+                value,
+                cases,
+                default,
+            } => match constant_int(value) {
+                Some(v) => {
+                    let case = cases.iter().find(|(c, _)| constant_int(c) == Some(v));
+                    vec![case.map_or(*default, |&(_, block)| block)]
+                }
+                None => {
+                    let mut blocks: Vec<BlockId> = cases.iter().map(|&(_, b)| b).collect();
+                    blocks.push(*default);
+                    blocks
+                }
+            },
+            Terminator::Return(_) | Terminator::End => vec![],
+        }
+    }
+
+    /// For every block, whether it can be reached from the start of the body
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![0];
+        while let Some(id) = stack.pop() {
+            if !reachable[id] {
+                reachable[id] = true;
+                stack.extend(self.successors(id));
+            }
+        }
+        reachable
+    }
+
+    /// Whether the end of the body can be reached without a `return`
+    pub fn falls_off_end(&self) -> bool {
+        self.reachable()
+            .iter()
+            .zip(&self.blocks)
+            .any(|(&reachable, b)| reachable && matches!(b.term, Terminator::End))
+    }
+}
+
+struct Builder<'a> {
+    blocks: Vec<Block<'a>>,
+    /// The block which gets the next statements
+    current: BlockId,
+    /// The blocks after the loops and `switch`es around the current statement, where
+    /// `break` jumps
+    breaks: Vec<BlockId>,
+    /// The blocks where `continue` jumps in the loops around the current statement
+    continues: Vec<BlockId>,
+}
+
+impl<'a> Builder<'a> {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Block {
+            insts: vec![],
+            term: Terminator::End,
+            loc: None,
+        });
+        self.blocks.len() - 1
+    }
+    /// Ends the current block with `term` and continues with `next`
+    fn terminate(&mut self, term: Terminator<'a>, next: BlockId) {
+        self.blocks[self.current].term = term;
+        self.current = next;
+    }
+    fn push(&mut self, inst: Inst<'a>) {
+        self.blocks[self.current].insts.push(inst);
+    }
+    fn mark(&mut self, loc: Loc) {
+        self.blocks[self.current].loc.get_or_insert(loc);
+    }
+
+    fn lower(&mut self, stm: &'a Stm) {
+        if !matches!(stm.kind, StmKind::Block(_) | StmKind::Empty) {
+            self.mark(stm.loc);
+        }
+        match &stm.kind {
+            StmKind::Block(stms) => stms.iter().for_each(|s| self.lower(s)),
+            StmKind::Var(v) => self.push(Inst::Var(v)),
+            StmKind::Expr(e) => self.push(Inst::Eval(e)),
+            StmKind::Empty => {}
+            StmKind::If { cond, then, els } => {
+                let then_block = self.new_block();
+                let els_block = els.as_ref().map(|_| self.new_block());
+                let join = self.new_block();
+                let branch = Terminator::Branch {
+                    cond,
+                    then: then_block,
+                    els: els_block.unwrap_or(join),
+                };
+                self.terminate(branch, then_block);
+                self.lower(then);
+                if let (Some(els), Some(els_block)) = (els, els_block) {
+                    self.terminate(Terminator::Goto(join), els_block);
+                    self.lower(els);
+                }
+                self.terminate(Terminator::Goto(join), join);
+            }
+            StmKind::While { cond, body } => {
+                let head = self.new_block();
+                let body_block = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Goto(head), head);
+                self.mark(cond.loc);
+                let branch = Terminator::Branch {
+                    cond,
+                    then: body_block,
+                    els: exit,
+                };
+                self.terminate(branch, body_block);
+                self.lower_loop(body, exit, head);
+                self.terminate(Terminator::Goto(head), exit);
+            }
+            StmKind::DoWhile { body, cond } => {
+                let body_block = self.new_block();
+                let cond_block = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Goto(body_block), body_block);
+                self.lower_loop(body, exit, cond_block);
+                self.terminate(Terminator::Goto(cond_block), cond_block);
+                self.mark(cond.loc);
+                let branch = Terminator::Branch {
+                    cond,
+                    then: body_block,
+                    els: exit,
+                };
+                self.terminate(branch, exit);
+            }
+            StmKind::Switch { value, cases } => {
+                let blocks: Vec<BlockId> = cases.iter().map(|_| self.new_block()).collect();
+                let exit = self.new_block();
+                let default = cases
+                    .iter()
+                    .zip(&blocks)
+                    .find(|(case, _)| case.value.is_none())
+                    .map_or(exit, |(_, &block)| block);
+                let switch = Terminator::Switch {
+                    value,
+                    cases: cases
+                        .iter()
+                        .zip(&blocks)
+                        .filter_map(|(case, &block)| Some((case.value.as_ref()?, block)))
+                        .collect(),
+                    default,
+                };
+                // The first case is entered only by the switch, the others also by falling
+                // through from the case before them
+                self.terminate(switch, blocks.first().copied().unwrap_or(exit));
+                self.breaks.push(exit);
+                for (i, (case, &block)) in cases.iter().zip(&blocks).enumerate() {
+                    if i > 0 {
+                        self.terminate(Terminator::Goto(block), block);
+                    }
+                    self.mark(case.loc);
+                    case.stms.iter().for_each(|s| self.lower(s));
+                }
+                self.breaks.pop();
+                self.terminate(Terminator::Goto(exit), exit);
+            }
+            StmKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.push(Inst::Eval(init));
+                }
+                let head = self.new_block();
+                let body_block = self.new_block();
+                let step_block = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Goto(head), head);
+                self.mark(stm.loc);
+                let enter = match cond {
+                    Some(cond) => Terminator::Branch {
+                        cond,
+                        then: body_block,
+                        els: exit,
+                    },
+                    None => Terminator::Goto(body_block),
+                };
+                self.terminate(enter, body_block);
+                self.lower_loop(body, exit, step_block);
+                self.terminate(Terminator::Goto(step_block), step_block);
+                if let Some(step) = step {
+                    self.mark(step.loc);
+                    self.push(Inst::Eval(step));
+                }
+                self.terminate(Terminator::Goto(head), exit);
+            }
+            StmKind::Break => {
+                let exit = match self.breaks.last() {
+                    Some(&exit) => exit,
+                    None => panic!(
+                        "Error in {}, `break` outside of a loop or a `switch`",
+                        stm.loc
+                    ),
+                };
+                // The statements after it go to a block without predecessors
+                let next = self.new_block();
+                self.terminate(Terminator::Goto(exit), next);
+            }
+            StmKind::Continue => {
+                let target = match self.continues.last() {
+                    Some(&target) => target,
+                    None => panic!("Error in {}, `continue` outside of a loop", stm.loc),
+                };
+                let next = self.new_block();
+                self.terminate(Terminator::Goto(target), next);
+            }
+            StmKind::Return(value) => {
+                let next = self.new_block();
+                self.terminate(Terminator::Return(value.as_ref()), next);
+            }
+        }
+    }
+    /// `break` goes to `exit`, `continue` to `next`
+    fn lower_loop(&mut self, body: &'a Stm, exit: BlockId, next: BlockId) {
+        self.breaks.push(exit);
+        self.continues.push(next);
+        self.lower(body);
+        self.breaks.pop();
+        self.continues.pop();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::asdr::SyntaxAnalyser;
+    use crate::ast::Unit;
+    use crate::cfg::*;
+    use crate::lexer::Lexer;
+
+    fn parse(src: &str) -> Unit {
+        SyntaxAnalyser::new(Lexer::new(src, "").get_tokens()).analyse_syntax()
+    }
+    #[test]
+    fn cfg_if_while() {
+        let unit = parse("void f(int x) { if (x) x = 1; else x = 2; while (x) { x = x - 1; } }");
+        let cfgs = build(&unit);
+        let cfg = &cfgs[0];
+        // entry, then, else, join, head, body, exit
+        assert_eq!(cfg.blocks.len(), 7);
+        assert_eq!(cfg.successors(0), vec![1, 2]);
+        assert_eq!(cfg.successors(1), vec![3]);
+        assert_eq!(cfg.successors(2), vec![3]);
+        assert_eq!(cfg.successors(3), vec![4]);
+        assert_eq!(cfg.successors(4), vec![5, 6]);
+        assert_eq!(cfg.successors(5), vec![4]);
+        assert!(cfg.falls_off_end());
+    }
+    #[test]
+    fn cfg_do_while_switch_continue() {
+        let unit = parse(
+            "void f(int x) {
+                do { if (x) continue; x = 1; } while (x);
+                switch (x) { case 1: x = 2; case 2: break; default: x = 3; }
+            }
+            void g() { switch (2) { case 1: ; case 2: ; } }",
+        );
+        let cfgs = build(&unit);
+        let cfg = &cfgs[0];
+        // body, condition, exit of the `do`, then the `continue` goes to the condition
+        assert_eq!(cfg.successors(0), vec![1]);
+        assert_eq!(cfg.successors(1), vec![4, 5]);
+        assert_eq!(cfg.successors(4), vec![2]);
+        assert_eq!(cfg.successors(2), vec![1, 3]);
+        // the cases, the default, the first case falls through and `break` leaves
+        assert_eq!(cfg.successors(3), vec![7, 8, 9]);
+        assert_eq!(cfg.successors(7), vec![8]);
+        assert_eq!(cfg.successors(8), vec![10]);
+        assert_eq!(cfg.successors(9), vec![10]);
+        assert!(cfg.falls_off_end());
+        // A constant value only goes to its case
+        assert_eq!(cfgs[1].successors(0), vec![2]);
+    }
+    #[test]
+    fn cfg_break_and_return() {
+        let unit = parse(
+            "int f() { for (;;) { if (f()) break; return 1; f(); } }
+            int g() { while (1) { if (g()) return 1; } }",
+        );
+        let cfgs = build(&unit);
+        // The call after the `return` cannot be reached
+        let reachable = cfgs[0].reachable();
+        let dead: Vec<_> = cfgs[0]
+            .blocks
+            .iter()
+            .zip(reachable)
+            .filter(|(b, reachable)| !reachable && !b.insts.is_empty())
+            .collect();
+        assert_eq!(dead.len(), 1);
+        // The `break` reaches the end of `f`, the loop of `g` never ends
+        assert!(cfgs[0].falls_off_end());
+        assert!(!cfgs[1].falls_off_end());
+    }
+}
@@ -0,0 +1,1060 @@
+use crate::ast::*;
+use crate::intern::Name;
+use crate::layout::Layout;
+use crate::mv::*;
+use crate::symbols::*;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+/// Code generation. Runs after type checking and emits the instructions of the virtual machine.
+/// The program starts with a call to `main`, followed by `HALT`
+pub struct CodeGen<'g> {
+    pub code: InstrList,
+    /// Memory of the globals, string constants are also placed here
+    globals: &'g mut Globals,
+    /// First instruction of every generated function
+    funcs: HashMap<Name, *mut Instr>,
+    /// Calls to functions which were not generated yet
+    calls: Vec<(*mut Instr, Name, Loc)>,
+    /// The `JMP`s of the `break`s of every enclosing loop or `switch`
+    breaks: Vec<Vec<*mut Instr>>,
+    /// The `JMP`s of the `continue`s of every enclosing loop
+    continues: Vec<Vec<*mut Instr>>,
+    /// The global context built by name resolution
+    symbols: &'g Context,
+    /// Size of the arguments and the return type of the current function
+    args_size: usize,
+    ret: SymbolType,
+    /// The locals of the current function, followed by the temporaries which hold the
+    /// struct values whose array members are addressed
+    frame: Layout,
+}
+
+const ADDR_SIZE: usize = size_of::<*const ()>();
+
+/// Sets the destination of a jump or of a call
+fn patch(instr: *mut Instr, target: *mut Instr) {
+    // Both instructions are owned by the same list
+    unsafe {
+        (*instr).arg1 = Some(InstrArg::Addr(target as *const ()));
+    }
+}
+/// The parts of a variable set by its initializer, as `(offset, type, value)`. The value
+/// is `None` for the parts left to zero
+fn init_parts<'a>(
+    t: &SymbolType,
+    init: Option<&'a Init>,
+    offset: usize,
+    parts: &mut Vec<(usize, SymbolType, Option<&'a Expr>)>,
+) {
+    let items = match init {
+        Some(Init::Expr(e)) => return parts.push((offset, t.clone(), Some(e))),
+        Some(Init::List(items, _)) => &items[..],
+        None => &[],
+    };
+    if t.num_elements > -1 {
+        let elem = t.elem();
+        for i in 0..t.num_elements as usize {
+            init_parts(&elem, items.get(i), offset + i * elem.size(), parts);
+        }
+    } else if let Some(s) = &t.struct_symbol {
+        for (i, m) in s.am.iter().flat_map(|am| am.values()).enumerate() {
+            let member = offset + m.ao.get_offset() as usize;
+            init_parts(&m.symbol_type, items.get(i), member, parts);
+        }
+    } else {
+        parts.push((offset, t.clone(), None));
+    }
+}
+/// Picks the opcode of the type `t` from the variants for int, double, char and addresses
+fn typed(t: &SymbolType, ops: [Opcode; 4]) -> Opcode {
+    if t.num_elements > -1 {
+        return ops[3];
+    }
+    match t.type_base {
+        TypeName::TbInt => ops[0],
+        TypeName::TbDouble => ops[1],
+        TypeName::TbChar => ops[2],
+        _ => panic!("no instruction for values of type {}", t),
+    }
+}
+
+/// The opcodes of a binary operator for int, double, char and addresses
+fn binary_ops(op: BinOp) -> [Opcode; 4] {
+    match op {
+        BinOp::Equal => [Opcode::OEqI, Opcode::OEqD, Opcode::OEqC, Opcode::OEqA],
+        BinOp::NotEq => [
+            Opcode::ONotEqI,
+            Opcode::ONotEqD,
+            Opcode::ONotEqC,
+            Opcode::ONotEqA,
+        ],
+        BinOp::Less => [Opcode::OLessI, Opcode::OLessD, Opcode::OLessC, Opcode::ONop],
+        BinOp::LessEq => [
+            Opcode::OLessEqI,
+            Opcode::OLessEqD,
+            Opcode::OLessEqC,
+            Opcode::ONop,
+        ],
+        BinOp::Greater => [
+            Opcode::OGreaterI,
+            Opcode::OGreaterD,
+            Opcode::OGreaterC,
+            Opcode::ONop,
+        ],
+        BinOp::GreaterEq => [
+            Opcode::OGreaterEqI,
+            Opcode::OGreaterEqD,
+            Opcode::OGreaterEqC,
+            Opcode::ONop,
+        ],
+        BinOp::Add => [Opcode::OAddI, Opcode::OAddD, Opcode::OAddC, Opcode::ONop],
+        BinOp::Sub => [Opcode::OSubI, Opcode::OSubD, Opcode::OSubC, Opcode::ONop],
+        BinOp::Mul => [Opcode::OMulI, Opcode::OMulD, Opcode::OMulC, Opcode::ONop],
+        BinOp::Div => [Opcode::ODivI, Opcode::ODivD, Opcode::ODivC, Opcode::ONop],
+        // The char operands of these operators are promoted to int
+        BinOp::Mod => [Opcode::OModI, Opcode::ONop, Opcode::ONop, Opcode::ONop],
+        BinOp::BitAnd => [Opcode::OBitAndI, Opcode::ONop, Opcode::ONop, Opcode::ONop],
+        BinOp::BitOr => [Opcode::OBitOrI, Opcode::ONop, Opcode::ONop, Opcode::ONop],
+        BinOp::BitXor => [Opcode::OBitXorI, Opcode::ONop, Opcode::ONop, Opcode::ONop],
+        BinOp::Shl => [Opcode::OShlI, Opcode::ONop, Opcode::ONop, Opcode::ONop],
+        BinOp::Shr => [Opcode::OShrI, Opcode::ONop, Opcode::ONop, Opcode::ONop],
+        BinOp::And | BinOp::Or => unreachable!(),
+    }
+}
+
+impl<'g> CodeGen<'g> {
+    pub fn new(globals: &'g mut Globals, symbols: &'g Context) -> Self {
+        Self {
+            code: InstrList::new(),
+            globals,
+            funcs: HashMap::new(),
+            calls: vec![],
+            breaks: vec![],
+            continues: vec![],
+            symbols,
+            args_size: 0,
+            ret: SymbolType::default(),
+            frame: Layout::default(),
+        }
+    }
+    fn emit(&mut self, op: Opcode) -> *mut Instr {
+        self.code.push_back_op(op)
+    }
+    fn emit_int(&mut self, op: Opcode, i: usize) -> *mut Instr {
+        self.code
+            .push_back(Instr::new_arg(op, InstrArg::Int(i as i64)))
+    }
+    fn emit_addr(&mut self, op: Opcode, addr: *const ()) -> *mut Instr {
+        self.code
+            .push_back(Instr::new_arg(op, InstrArg::Addr(addr)))
+    }
+    /// Copies the top `size` bytes of the stack below the `depth` bytes under them
+    fn emit_insert(&mut self, depth: usize, size: usize) {
+        self.code.push_back(Instr::new_arg2(
+            Opcode::OInsert,
+            InstrArg::Int(depth as i64),
+            InstrArg::Int(size as i64),
+        ));
+    }
+    /// A `NOP` used as the destination of jumps
+    fn label(&mut self) -> *mut Instr {
+        self.emit(Opcode::ONop)
+    }
+
+    pub fn gen(&mut self, unit: &Unit) {
+        let call_main = self.emit(Opcode::OCall);
+        let halt = self.emit(Opcode::OHalt);
+        for item in &unit.items {
+            match item {
+                Item::Func(f) if f.body.is_some() => self.gen_func(f),
+                Item::Var(v) if v.init.is_some() => self.init_global(v),
+                _ => {}
+            }
+        }
+        match self.funcs.get(&Name::intern("main")) {
+            Some(&main) => patch(call_main, main),
+            // A library has no entry point, running it stops at once
+            None => patch(call_main, halt),
+        }
+        for (call, name, loc) in std::mem::take(&mut self.calls) {
+            match self.funcs.get(&name) {
+                Some(&target) => patch(call, target),
+                None => panic!("Error in {}, function `{}` has no body", loc, name),
+            }
+        }
+    }
+
+    /// Writes the constant initializer of the global `v` in the globals image
+    fn init_global(&mut self, v: &VarDecl) {
+        let s = v.sym.as_ref().unwrap();
+        let mut parts = vec![];
+        init_parts(&s.symbol_type, v.init.as_ref(), 0, &mut parts);
+        for (offset, t, value) in parts {
+            let ctval = match value {
+                Some(e) => e.rv.ctval.as_ref().unwrap(),
+                None => continue,
+            };
+            let bytes = match (ctval, &t.type_base) {
+                (CtVal::String(s), _) => [s.as_bytes(), &[0]].concat(),
+                (CtVal::IntChar(i), TypeName::TbChar) => vec![*i as u8],
+                (CtVal::IntChar(i), _) => (*i as i64).to_ne_bytes().to_vec(),
+                (CtVal::Double(d), _) => d.to_ne_bytes().to_vec(),
+            };
+            let addr = unsafe { (s.ao.get_addr() as *const u8).add(offset) } as *const ();
+            self.globals.write(addr, &bytes);
+        }
+    }
+    /// Stores the initializer of the local `v`, the parts it does not give are set to zero
+    fn init_local(&mut self, v: &VarDecl) {
+        let s = v.sym.as_ref().unwrap();
+        let mut parts = vec![];
+        init_parts(&s.symbol_type, v.init.as_ref(), 0, &mut parts);
+        for (offset, t, value) in parts {
+            let addr = s.ao.get_offset() + offset as isize;
+            match value {
+                Some(Expr {
+                    kind: ExprKind::Str(text),
+                    ..
+                }) if t.num_elements > -1 => {
+                    let bytes = text.bytes().chain(std::iter::repeat(0));
+                    for (i, byte) in bytes.take(t.num_elements as usize).enumerate() {
+                        self.emit_int(Opcode::OPushFPAddr, (addr + i as isize) as usize);
+                        self.emit_int(Opcode::OPushCtC, byte as usize);
+                        self.emit_int(Opcode::OStore, 1);
+                    }
+                    continue;
+                }
+                Some(e) => {
+                    self.emit_int(Opcode::OPushFPAddr, addr as usize);
+                    self.gen_value(e);
+                }
+                None => {
+                    self.emit_int(Opcode::OPushFPAddr, addr as usize);
+                    self.gen_zero(&t);
+                }
+            }
+            self.emit_int(Opcode::OStore, t.size());
+        }
+    }
+    /// Pushes the zero of the number type `t`
+    fn gen_zero(&mut self, t: &SymbolType) {
+        match t.type_base {
+            TypeName::TbDouble => self
+                .code
+                .push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(0.))),
+            TypeName::TbChar => self.emit_int(Opcode::OPushCtC, 0),
+            _ => self.emit_int(Opcode::OPushCtI, 0),
+        };
+    }
+
+    fn gen_func(&mut self, f: &FuncDecl) {
+        let enter = self.emit_int(Opcode::OEnter, f.frame);
+        self.funcs.insert(f.name, enter);
+        let s = self.symbols.find_symbol(f.name).unwrap();
+        let args = s.am.as_ref().unwrap().values();
+        self.args_size = args.map(|a| a.symbol_type.size()).sum();
+        self.ret = s.symbol_type.clone();
+        self.frame = Layout {
+            size: f.frame,
+            align: 1,
+        };
+        self.gen_stm(f.body.as_ref().unwrap());
+        // Falling off the end of the function
+        self.gen_ret();
+        // The frame grows with the temporaries
+        unsafe {
+            (*enter).arg1 = Some(InstrArg::Int(self.frame.size as i64));
+        }
+    }
+    fn gen_ret(&mut self) {
+        self.code.push_back(Instr::new_arg2(
+            Opcode::ORet,
+            InstrArg::Int(self.args_size as i64),
+            InstrArg::Int(self.ret.size() as i64),
+        ));
+    }
+
+    /// Jumps to `target` if `cond` is false
+    fn gen_jump_false(&mut self, cond: &Expr) -> *mut Instr {
+        self.gen_value(cond);
+        let op = typed(
+            cond.ty(),
+            [Opcode::OJfI, Opcode::OJfD, Opcode::OJfC, Opcode::OJfA],
+        );
+        self.emit(op)
+    }
+    /// Jumps to `target` if `cond` is true
+    fn gen_jump_true(&mut self, cond: &Expr) -> *mut Instr {
+        self.gen_value(cond);
+        let op = typed(
+            cond.ty(),
+            [Opcode::OJtI, Opcode::OJtD, Opcode::OJtC, Opcode::OJtA],
+        );
+        self.emit(op)
+    }
+    /// Evaluates `e` and drops its value
+    fn gen_discard(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Assign(dst, src) => return self.gen_assign(dst, src, false),
+            ExprKind::OpAssign(op, dst, src) => return self.gen_op_assign(*op, dst, src, false),
+            ExprKind::IncDec {
+                op,
+                prefix,
+                operand,
+            } => return self.gen_inc_dec(*op, *prefix, operand, false),
+            _ => {}
+        }
+        self.gen_value(e);
+        let size = e.ty().size();
+        if size > 0 {
+            self.emit_int(Opcode::ODrop, size);
+        }
+    }
+
+    fn gen_stm(&mut self, stm: &Stm) {
+        match &stm.kind {
+            StmKind::Block(stms) => stms.iter().for_each(|s| self.gen_stm(s)),
+            StmKind::Var(v) if v.init.is_some() => self.init_local(v),
+            StmKind::Var(_) | StmKind::Empty => {}
+            StmKind::If { cond, then, els } => {
+                let jf = self.gen_jump_false(cond);
+                self.gen_stm(then);
+                match els {
+                    Some(els) => {
+                        let jmp = self.emit(Opcode::OJmp);
+                        let else_label = self.label();
+                        patch(jf, else_label);
+                        self.gen_stm(els);
+                        let end = self.label();
+                        patch(jmp, end);
+                    }
+                    None => {
+                        let end = self.label();
+                        patch(jf, end);
+                    }
+                }
+            }
+            StmKind::While { cond, body } => {
+                let start = self.label();
+                let jf = self.gen_jump_false(cond);
+                self.gen_loop_body(body);
+                let jmp = self.emit(Opcode::OJmp);
+                patch(jmp, start);
+                let end = self.label();
+                patch(jf, end);
+                self.patch_jumps(end, start);
+            }
+            StmKind::DoWhile { body, cond } => {
+                let start = self.label();
+                self.gen_loop_body(body);
+                let next = self.label();
+                let jt = self.gen_jump_true(cond);
+                patch(jt, start);
+                let end = self.label();
+                self.patch_jumps(end, next);
+            }
+            StmKind::Switch { value, cases } => self.gen_switch(value, cases),
+            StmKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.gen_discard(init);
+                }
+                let start = self.label();
+                let jf = cond.as_ref().map(|cond| self.gen_jump_false(cond));
+                self.gen_loop_body(body);
+                let next = self.label();
+                if let Some(step) = step {
+                    self.gen_discard(step);
+                }
+                let jmp = self.emit(Opcode::OJmp);
+                patch(jmp, start);
+                let end = self.label();
+                if let Some(jf) = jf {
+                    patch(jf, end);
+                }
+                self.patch_jumps(end, next);
+            }
+            StmKind::Break => {
+                let jmp = self.emit(Opcode::OJmp);
+                match self.breaks.last_mut() {
+                    Some(breaks) => breaks.push(jmp),
+                    None => panic!(
+                        "Error in {}, `break` outside of a loop or a `switch`",
+                        stm.loc
+                    ),
+                }
+            }
+            StmKind::Continue => {
+                let jmp = self.emit(Opcode::OJmp);
+                match self.continues.last_mut() {
+                    Some(continues) => continues.push(jmp),
+                    None => panic!("Error in {}, `continue` outside of a loop", stm.loc),
+                }
+            }
+            StmKind::Return(value) => {
+                if let Some(value) = value {
+                    self.gen_value(value);
+                }
+                self.gen_ret();
+            }
+            StmKind::Expr(e) => self.gen_discard(e),
+        }
+    }
+    /// The body of a loop, its `break`s and `continue`s are patched by `patch_jumps`
+    fn gen_loop_body(&mut self, body: &Stm) {
+        self.breaks.push(vec![]);
+        self.continues.push(vec![]);
+        self.gen_stm(body);
+    }
+    /// Sends the `break`s of the loop to `end` and its `continue`s to `next`
+    fn patch_jumps(&mut self, end: *mut Instr, next: *mut Instr) {
+        for jmp in self.breaks.pop().unwrap() {
+            patch(jmp, end);
+        }
+        for jmp in self.continues.pop().unwrap() {
+            patch(jmp, next);
+        }
+    }
+    /// Compares the value with every case in order, the value is dropped before jumping
+    /// to the statements of the case. Without a match it goes to `default` or after the
+    /// `switch`
+    fn gen_switch(&mut self, value: &Expr, cases: &[Case]) {
+        let t = value.ty();
+        let size = t.size();
+        self.gen_value(value);
+        let mut jumps = vec![];
+        for case in cases {
+            if let Some(case_value) = &case.value {
+                // value, value, case
+                self.emit_insert(size, size);
+                self.gen_value(case_value);
+                self.emit(typed(t, binary_ops(BinOp::Equal)));
+                let jf = self.emit(Opcode::OJfI);
+                self.emit_int(Opcode::ODrop, size);
+                jumps.push(self.emit(Opcode::OJmp));
+                let next = self.label();
+                patch(jf, next);
+            }
+        }
+        self.emit_int(Opcode::ODrop, size);
+        let no_match = self.emit(Opcode::OJmp);
+        let mut jumps = jumps.into_iter();
+        let mut default = None;
+        self.breaks.push(vec![]);
+        for case in cases {
+            let label = self.label();
+            match case.value {
+                Some(_) => patch(jumps.next().unwrap(), label),
+                None => default = Some(label),
+            }
+            case.stms.iter().for_each(|s| self.gen_stm(s));
+        }
+        let end = self.label();
+        patch(no_match, default.unwrap_or(end));
+        for jmp in self.breaks.pop().unwrap() {
+            patch(jmp, end);
+        }
+    }
+    /// Converts the value on top of the stack from `from` to `to`
+    fn gen_conv(&mut self, from: &SymbolType, to: &SymbolType) {
+        if from.num_elements > -1 || to.num_elements > -1 {
+            return;
+        }
+        let op = match (&from.type_base, &to.type_base) {
+            (TypeName::TbInt, TypeName::TbDouble) => Opcode::OCastID,
+            (TypeName::TbInt, TypeName::TbChar) => Opcode::OCastIC,
+            (TypeName::TbDouble, TypeName::TbInt) => Opcode::OCastDI,
+            (TypeName::TbDouble, TypeName::TbChar) => Opcode::OCastDC,
+            (TypeName::TbChar, TypeName::TbInt) => Opcode::OCastCI,
+            (TypeName::TbChar, TypeName::TbDouble) => Opcode::OCastCD,
+            _ => return,
+        };
+        self.emit(op);
+    }
+
+    /// Pushes the address of the lvalue `e`
+    fn gen_addr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Id(_) => {
+                let s = e.sym.as_ref().unwrap();
+                match s.storage {
+                    StorageType::MemGlobal => {
+                        self.emit_addr(Opcode::OPushCtA, s.ao.get_addr());
+                    }
+                    _ => {
+                        let offset = s.ao.get_offset();
+                        self.code.push_back(Instr::new_arg(
+                            Opcode::OPushFPAddr,
+                            InstrArg::Int(offset as i64),
+                        ));
+                    }
+                }
+            }
+            ExprKind::Index(array, index) => {
+                self.gen_value(array);
+                self.gen_value(index);
+                self.emit_int(Opcode::OPushCtI, e.ty().size());
+                self.emit(Opcode::OMulI);
+                self.emit(Opcode::OOffset);
+            }
+            ExprKind::Member(object, member) => {
+                if object.rv.is_lval {
+                    self.gen_addr(object);
+                } else {
+                    self.gen_spill(object);
+                }
+                let s_struct = object.ty().struct_symbol.as_ref().unwrap();
+                let offset = s_struct.find_symbol(*member).unwrap().ao.get_offset();
+                self.emit_int(Opcode::OPushCtI, offset as usize);
+                self.emit(Opcode::OOffset);
+            }
+            _ => panic!("Error in {}, the expression has no address", e.loc),
+        }
+    }
+
+    /// Stores the struct value `e` in a new temporary of the frame and pushes its address
+    fn gen_spill(&mut self, e: &Expr) {
+        let t = e.ty();
+        let offset = self.frame.place(t) as i64;
+        let push_addr = Instr::new_arg(Opcode::OPushFPAddr, InstrArg::Int(offset));
+        self.code.push_back(push_addr);
+        self.gen_value(e);
+        self.emit_int(Opcode::OStore, t.size());
+        self.code.push_back(push_addr);
+    }
+
+    /// `dst = src`, the value is kept on the stack if `keep` is set
+    fn gen_assign(&mut self, dst: &Expr, src: &Expr, keep: bool) {
+        self.gen_addr(dst);
+        self.gen_value(src);
+        let size = dst.ty().size();
+        if keep {
+            // value, address, value
+            self.emit_insert(ADDR_SIZE + size, size);
+        }
+        self.emit_int(Opcode::OStore, size);
+    }
+    /// `dst op= src`: the address of `dst` is computed once and duplicated for the load
+    fn gen_op_assign(&mut self, op: BinOp, dst: &Expr, src: &Expr, keep: bool) {
+        // The type checker converted `src` to the type of the operation
+        let t = src.ty();
+        let size = dst.ty().size();
+        self.gen_addr(dst);
+        self.emit_insert(ADDR_SIZE, ADDR_SIZE);
+        self.emit_int(Opcode::OLoad, size);
+        self.gen_conv(dst.ty(), t);
+        self.gen_value(src);
+        self.emit(typed(t, binary_ops(op)));
+        self.gen_conv(t, dst.ty());
+        if keep {
+            self.emit_insert(ADDR_SIZE + size, size);
+        }
+        self.emit_int(Opcode::OStore, size);
+    }
+    /// `++x`, `x--`: the value kept is the new one for a prefix operator, the old one else
+    fn gen_inc_dec(&mut self, op: BinOp, prefix: bool, operand: &Expr, keep: bool) {
+        let t = operand.ty();
+        let size = t.size();
+        self.gen_addr(operand);
+        self.emit_insert(ADDR_SIZE, ADDR_SIZE);
+        self.emit_int(Opcode::OLoad, size);
+        if keep && !prefix {
+            self.emit_insert(ADDR_SIZE + size, size);
+        }
+        match t.type_base {
+            TypeName::TbDouble => {
+                self.code
+                    .push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(1.0)));
+            }
+            _ => {
+                let push = typed(
+                    t,
+                    [
+                        Opcode::OPushCtI,
+                        Opcode::ONop,
+                        Opcode::OPushCtC,
+                        Opcode::ONop,
+                    ],
+                );
+                self.emit_int(push, 1);
+            }
+        }
+        self.emit(typed(t, binary_ops(op)));
+        if keep && prefix {
+            self.emit_insert(ADDR_SIZE + size, size);
+        }
+        self.emit_int(Opcode::OStore, size);
+    }
+
+    /// Pushes the value of `e`. The value of an array is its address
+    fn gen_value(&mut self, e: &Expr) {
+        // The constants, also those folded by the type checker
+        if let (true, Some(value)) = (e.rv.is_ctval, &e.rv.ctval) {
+            match value {
+                CtVal::IntChar(i) => {
+                    let op = typed(
+                        e.ty(),
+                        [
+                            Opcode::OPushCtI,
+                            Opcode::ONop,
+                            Opcode::OPushCtC,
+                            Opcode::ONop,
+                        ],
+                    );
+                    self.emit_int(op, *i as usize);
+                }
+                CtVal::Double(d) => {
+                    self.code
+                        .push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(*d)));
+                }
+                CtVal::String(s) => {
+                    let mut bytes = s.clone().into_bytes();
+                    bytes.push(0);
+                    let addr = self.globals.alloc_bytes(&bytes);
+                    self.emit_addr(Opcode::OPushCtA, addr);
+                }
+            }
+            return;
+        }
+        match &e.kind {
+            ExprKind::Int(_) | ExprKind::Real(_) | ExprKind::Char(_) | ExprKind::Str(_) => {
+                unreachable!("the type checker gives a value to every constant")
+            }
+            // An array member is addressed in a temporary copy of the struct
+            ExprKind::Member(object, member) if !object.rv.is_lval && e.ty().num_elements < 0 => {
+                self.gen_value(object);
+                self.gen_extract(object.ty(), *member);
+            }
+            ExprKind::Id(_) | ExprKind::Index(..) | ExprKind::Member(..) => {
+                self.gen_addr(e);
+                let t = e.ty();
+                let by_address = matches!(&e.sym, Some(s) if s.storage == StorageType::MemArg);
+                if t.num_elements < 0 || by_address {
+                    self.emit_int(Opcode::OLoad, t.size());
+                }
+            }
+            ExprKind::Call(_, args) => {
+                let s = e.sym.as_ref().unwrap();
+                for arg in args {
+                    self.gen_value(arg);
+                }
+                match s.class {
+                    ClassType::ClsExtFunc => {
+                        self.emit_addr(Opcode::OCallext, s.ao.get_addr());
+                    }
+                    _ => {
+                        let call = self.emit(Opcode::OCall);
+                        self.calls.push((call, s.name, e.loc));
+                    }
+                }
+            }
+            ExprKind::Assign(dst, src) => self.gen_assign(dst, src, true),
+            ExprKind::OpAssign(op, dst, src) => self.gen_op_assign(*op, dst, src, true),
+            ExprKind::IncDec {
+                op,
+                prefix,
+                operand,
+            } => self.gen_inc_dec(*op, *prefix, operand, true),
+            ExprKind::Cast(_, operand) | ExprKind::Conv(operand) => {
+                self.gen_value(operand);
+                self.gen_conv(operand.ty(), e.ty());
+            }
+            ExprKind::Unary(op, operand) => {
+                self.gen_value(operand);
+                let t = operand.ty();
+                let op = match op {
+                    UnaryOp::Neg => typed(
+                        t,
+                        [Opcode::ONegI, Opcode::ONegD, Opcode::ONegC, Opcode::ONop],
+                    ),
+                    UnaryOp::Not => typed(
+                        t,
+                        [Opcode::ONotI, Opcode::ONotD, Opcode::ONotC, Opcode::ONotA],
+                    ),
+                    UnaryOp::BitNot => typed(
+                        t,
+                        [Opcode::OBitNotI, Opcode::ONop, Opcode::ONop, Opcode::ONop],
+                    ),
+                };
+                self.emit(op);
+            }
+            // Short-circuit: the right operand is skipped once the result is known
+            ExprKind::Binary(op @ (BinOp::And | BinOp::Or), left, right) => {
+                let and = *op == BinOp::And;
+                let jumps = if and {
+                    [self.gen_jump_false(left), self.gen_jump_false(right)]
+                } else {
+                    [self.gen_jump_true(left), self.gen_jump_true(right)]
+                };
+                self.emit_int(Opcode::OPushCtI, and as usize);
+                let jmp = self.emit(Opcode::OJmp);
+                let short = self.label();
+                jumps.iter().for_each(|&j| patch(j, short));
+                self.emit_int(Opcode::OPushCtI, !and as usize);
+                let end = self.label();
+                patch(jmp, end);
+            }
+            ExprKind::Binary(op, left, right) => {
+                // The type checker converted the numbers to a common type
+                let t = left.ty();
+                self.gen_value(left);
+                self.gen_value(right);
+                self.emit(typed(t, binary_ops(*op)));
+            }
+        }
+    }
+    /// Replaces the struct value of type `t` on top of the stack with its `member`
+    fn gen_extract(&mut self, t: &SymbolType, member: Name) {
+        let s_struct = t.struct_symbol.as_ref().unwrap();
+        let m = s_struct.find_symbol(member).unwrap();
+        let offset = m.ao.get_offset() as usize;
+        let size = m.symbol_type.size();
+        // struct: before, member, after
+        let after = t.size() - offset - size;
+        if after > 0 {
+            self.emit_int(Opcode::ODrop, after);
+        }
+        if offset > 0 {
+            // member, before, member
+            self.emit_insert(offset + size, size);
+            self.emit_int(Opcode::ODrop, offset + size);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::compile::{compile, CompileOptions};
+    use crate::lexer::Lexer;
+    use crate::mv::{Opcode, VirtualMachine};
+
+    /// Compiles and runs `src`, returns what it printed
+    pub fn run(src: &str) -> String {
+        let tokens = Lexer::new(src, "").get_tokens();
+        let compilation = compile(tokens, &CompileOptions::default());
+        let mut vm = VirtualMachine::new();
+        vm.echo = false;
+        vm.run(compilation.code.as_ref().unwrap());
+        vm.output
+    }
+    #[test]
+    fn codegen_loops_and_calls() {
+        let out = run(
+            "int fact(int n) { if (n < 2) return 1; return n * fact(n - 1); }
+            void main() { int i; for (i = 1; i <= 5; i = i + 1) { put_i(fact(i)); put_c(' '); } }",
+        );
+        assert_eq!(out, "1 2 6 24 120 ");
+    }
+    #[test]
+    fn codegen_arrays_and_structs() {
+        let out = run("struct P { char c; int v[3]; }; struct P ps[2];
+            int len(char s[]) { int i; i = 0; while (s[i]) i = i + 1; return i; }
+            void main() { int i; double d;
+                for (i = 0; i < 3; i = i + 1) ps[1].v[i] = i * 10;
+                ps[0].c = 'z';
+                put_i(ps[1].v[2]); put_c(ps[0].c); put_i(len(\"hello\"));
+                d = 7.0 / 2.0; put_d(d);
+                while (1) { if (i == 0) break; i = i - 1; }
+                put_i(i);
+                put_s(\"!\\n\");
+            }");
+        assert_eq!(out, "20z53.50!\n");
+    }
+    #[test]
+    fn codegen_test_syntax_file() {
+        let src = std::fs::read_to_string("tests/test_syntax.c").unwrap();
+        assert_eq!(run(&src), "1098765432135a");
+    }
+    #[test]
+    fn codegen_constant_folding() {
+        let src = "void main() { put_i(2 * 3 + 1); put_d(1.0 / 2.0 - 0.25); put_c((char)('a' + 2)); put_i(!(1 < 2) || 3 == 3); put_i((int)2.9 + (int)(char)257); }";
+        let c = compile(Lexer::new(src, "").get_tokens(), &CompileOptions::default());
+        let code = c.code.as_ref().unwrap();
+        let ops: Vec<Opcode> = code.iter().map(|i| i.opcode).collect();
+        assert_eq!(
+            ops,
+            vec![
+                Opcode::OCall,
+                Opcode::OHalt,
+                Opcode::OEnter,
+                Opcode::OPushCtI,
+                Opcode::OCallext,
+                Opcode::OPushCtD,
+                Opcode::OCallext,
+                Opcode::OPushCtC,
+                Opcode::OCallext,
+                Opcode::OPushCtI,
+                Opcode::OCallext,
+                Opcode::OPushCtI,
+                Opcode::OCallext,
+                Opcode::ORet,
+            ]
+        );
+        let mut vm = VirtualMachine::new();
+        vm.echo = false;
+        vm.run(code);
+        assert_eq!(vm.output, "70.25c13");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, division by zero")]
+    fn codegen_constant_division_by_zero() {
+        run("void main() { int x; x = 1; put_i(x / (2 - 2)); }");
+    }
+    #[test]
+    fn codegen_mixed_arithmetic() {
+        // Every pair of char, int and double, the result has the larger type, at least int
+        let out = run(r#"
+            void main() {
+                char c; int i; double d;
+                c = (char)200; i = 100; d = 0.5;
+                put_i(c + c); put_c(' '); put_i(c + i); put_c(' '); put_d(c + d); put_c(' ');
+                put_i(i + c); put_c(' '); put_i(i + i); put_c(' '); put_d(i + d); put_c(' ');
+                put_d(d + c); put_c(' '); put_d(d + i); put_c(' '); put_d(d + d); put_c(' ');
+                put_i(i / 3 * 3); put_c(' '); put_d(i / 8.0); put_c(' ');
+                put_i(c > i); put_i(d < i); put_i(c == 200.0);
+                i = d * 9; put_c(' '); put_i(i);
+            }
+        "#);
+        assert_eq!(
+            out,
+            "400 300 200.5 300 200 100.5 200.5 100.5 1 99 12.5 111 4"
+        );
+    }
+    #[test]
+    fn codegen_mutual_recursion() {
+        let out = run(r#"
+            int odd(int n);
+            int even(int n) { if (n == 0) return 1; return odd(n - 1); }
+            int odd(int n) { if (n == 0) return 0; return even(n - 1); }
+            void main() { put_i(even(10)); put_i(odd(7)); put_i(even(3)); }
+        "#);
+        assert_eq!(out, "110");
+    }
+    #[test]
+    fn codegen_structs_by_value() {
+        let out = run(r#"
+            struct P { int x; double y; };
+            struct L { struct P a; struct P b; char tag; };
+            struct P mk(int x, double y) { struct P p; p.x = x; p.y = y; return p; }
+            struct L line(struct P a, struct P b) {
+                struct L l; l.a = a; l.b = b; l.tag = 'L'; return l;
+            }
+            int sum(struct L l) { l.a.x = 100; return l.a.x + l.b.x; }
+            struct P first(struct P ps[]) { return ps[0]; }
+            void main() {
+                struct L l; struct P ps[3]; struct P p; int i;
+                for (i = 0; i < 3; i = i + 1) ps[i] = mk(i * 10, i + 0.5);
+                l = line(ps[1], ps[2]);
+                put_i(sum(l)); put_c(' '); put_i(l.a.x); put_c(' ');
+                put_d(l.b.y); put_c(l.tag); put_c(' ');
+                put_i(mk(7, 1.0).x); put_d(mk(7, 1.5).y); put_c(' ');
+                put_i(line(mk(1, 1.0), mk(2, 2.0)).b.x); put_c(line(p = first(ps), p).tag);
+                put_i((ps[2] = p).x); put_i(ps[2].x);
+            }
+        "#);
+        assert_eq!(out, "120 10 2.5L 71.5 2L00");
+    }
+    #[test]
+    fn codegen_arrays_in_struct_values() {
+        let out = run(r#"
+            struct P { int x; char tag[4]; int v[3]; };
+            struct L { char c; struct P p; double m[2][2]; };
+            struct P mk(int x) { struct P p = {x, "ab", {x, x + 1, x + 2}}; return p; }
+            struct L line(int x) { struct L l = {'l', mk(x), {{0.5, 1}, {1.5, 2}}}; return l; }
+            int sum(int v[]) { return v[0] + v[1] + v[2]; }
+            void main() {
+                int i;
+                put_s(mk(1).tag); put_i(mk(4).v[1]); put_c(mk(1).tag[1]); put_c(' ');
+                put_i(sum(mk(10).v)); put_c(' ');
+                for (i = 0; i < 2; i++) put_d(line(i).m[i][1]);
+                put_s(line(3).p.tag); put_i(line(3).p.v[2] + mk(mk(2).v[2]).v[0]);
+            }
+        "#);
+        assert_eq!(out, "ab5b 33 12ab9");
+    }
+    #[test]
+    fn codegen_assign_element_of_returned_array() {
+        let out = run(r#"
+            int g[3];
+            int *f() { return g; }
+            void main() { int i = 2; f()[i] = 5; f()[0] += 7; put_i(g[0]); put_i(g[2]); }
+        "#);
+        assert_eq!(out, "75");
+    }
+    #[test]
+    fn codegen_assignment_value() {
+        assert_eq!(
+            run("void main() { int x; int y; put_i(x = 5); put_i(y = x = 7); put_i(x + y); }"),
+            "5714"
+        );
+    }
+    #[test]
+    fn codegen_switch_and_loops() {
+        let out = run(r#"
+            int kind(char c) {
+                switch (c) {
+                    case 'a': case 'e': case 'i': return 1;
+                    case ' ': return 2;
+                    default: return 0;
+                }
+            }
+            void main() {
+                int i; int n;
+                for (i = 0; i < 6; i++) {
+                    switch (i % 4) {
+                        case 0: put_c('z'); break;
+                        case 1: put_c('o');
+                        case 2 + 1: put_c('t'); continue;
+                        default: put_c('d');
+                    }
+                    put_c('.');
+                }
+                put_c(' ');
+                n = 0;
+                do { n += 3; if (n % 2) continue; put_i(n); } while (n < 12);
+                put_c(' ');
+                do put_i(n); while (0);
+                put_c(' '); put_i(kind('e')); put_i(kind(' ')); put_i(kind('x')); put_c(' ');
+                i = 0;
+                while (i < 5) { i++; if (i == 2) continue; put_i(i); }
+                switch (n) { }
+                switch (n) { case 1: put_i(1); }
+                switch (n) { default: put_c('!'); case 12: put_c('?'); }
+            }
+        "#);
+        assert_eq!(out, "z.otd.tz.ot 612 12 120 1345?");
+    }
+    #[test]
+    fn codegen_short_circuit() {
+        let out = run(r#"
+            int calls;
+            int hit(int v) { calls += 1; return v; }
+            void main() {
+                int x = 0; double d = 0.5; char s[] = "";
+                put_i(0 && hit(1)); put_i(calls); put_i(1 || hit(1)); put_i(calls);
+                put_i(1 && hit(2)); put_i(calls); put_i(0 || hit(0)); put_i(calls); put_c(' ');
+                if (x != 0 && 10 / x > 1) put_i(9); else put_i(8);
+                put_i(x == 0 || (x = 5)); put_i(x); put_i(x++ && x++); put_i(x); put_c(' ');
+                put_i(d && 'a'); put_i(d - 0.5 || s); put_i(!d || hit(0) && hit(1)); put_i(calls);
+            }
+        "#);
+        assert_eq!(out, "00101102 81001 1103");
+    }
+    #[test]
+    fn codegen_integer_operators() {
+        let out = run(r#"
+            int k[1 << 3 | 5 % 3 ^ ~0 & 6];
+            void main() {
+                int a = 17; int b = 5; int n = -17; char c = 'a';
+                put_i(a % b); put_i(n % b); put_c(' ');
+                put_i(a & b); put_i(a | b); put_i(a ^ b); put_c(' ');
+                put_i(a << 2); put_i(n >> 1); put_i(~a); put_c(' ');
+                put_c(c ^ ' '); put_c(c & ~32); put_c(c | 2); put_c(' ');
+                put_i(1 << 3 | 5 % 3 ^ ~0 & 6); put_i(a % b * 2 << 1 | 1);
+            }
+        "#);
+        assert_eq!(out, "2-2 12120 68-9-18 AAc 129");
+    }
+    #[test]
+    fn codegen_char_unary_promoted() {
+        // `-` and `~` of a char give an int, folded or computed by the VM
+        let out = run(r#"
+            void main() {
+                char c = 'a';
+                put_i(-c); put_i(~c); put_c(' '); put_i(-'a'); put_i(~'a'); put_c(' ');
+                c = -c; put_i(c); put_i(-c == -97);
+            }
+        "#);
+        assert_eq!(out, "-97-98 -97-98 1590");
+    }
+    #[test]
+    fn codegen_char_shifts_match_folding() {
+        // The constants are folded by the type checker, the variables are shifted by the VM
+        let out = run(r#"
+            void main() {
+                char one = 1; char nine = 9; char big = 200; char three = 3; char far = 70;
+                put_i((char)1 << (char)9); put_c('='); put_i(one << nine); put_c(' ');
+                put_i((char)200 >> (char)9); put_c('='); put_i(big >> nine); put_c(' ');
+                put_i((char)3 << (char)70); put_c('='); put_i(three << far);
+            }
+        "#);
+        assert_eq!(out, "512=512 0=0 192=192");
+    }
+    #[test]
+    fn codegen_compound_assignment() {
+        let out = run(r#"
+            int calls;
+            int at(int i) { calls += 1; return i; }
+            void main() {
+                int a[3] = {1, 2, 3}; int i = 0; double d = 1; char c = 'a';
+                a[i++] += 10; a[at(2)] *= a[1]; put_i(a[0]); put_i(a[2]); put_i(i);
+                put_i(calls); put_c(' ');
+                put_i(i++); put_i(i); put_i(++i); put_i(--a[at(1)]); put_i(a[1]); put_c(' ');
+                d += 1; d /= 4; d--; put_d(d); put_c(' ');
+                c += 2; c++; put_c(c); c -= 1.5; put_c(c); put_c(' ');
+                i = 10; i /= 4; i *= 2.5; put_i(i); put_i(i -= 3); put_c(' '); put_i(calls);
+            }
+        "#);
+        assert_eq!(out, "11611 12311 -0.5 db 52 2");
+    }
+    #[test]
+    fn codegen_multi_dimensional_arrays() {
+        let out = run(r#"
+            struct P { int v[2][3]; char name[2][4]; };
+            int m[3][4];
+            int sum(int a[][4], int rows) {
+                int i; int j; int s; s = 0;
+                for (i = 0; i < rows; i = i + 1) for (j = 0; j < 4; j = j + 1) s = s + a[i][j];
+                return s;
+            }
+            int ends(int r[4]) { return r[0] + r[3]; }
+            void main() {
+                int i; int j; struct P p; double d[2][2][2];
+                for (i = 0; i < 3; i = i + 1) for (j = 0; j < 4; j = j + 1) m[i][j] = i * 10 + j;
+                put_i(sum(m, 3)); put_c(' '); put_i(m[2][1]); put_c(' '); put_i(ends(m[1]));
+                p.v[1][2] = 5; p.name[1][0] = 'h'; p.name[1][1] = 'i'; p.name[1][2] = 0;
+                put_c(' '); put_i(p.v[1][2]); put_s(p.name[1]);
+                d[1][0][1] = 2.5; d[0][1][1] = 1.0; put_c(' '); put_d(d[1][0][1] + d[0][1][1]);
+            }
+        "#);
+        assert_eq!(out, "138 21 23 5hi 3.5");
+    }
+    #[test]
+    fn codegen_initializers() {
+        let out = run(r#"
+            struct P { int x; double y; char tag[4]; };
+            struct L { struct P a; struct P b; };
+            int n = 2 * 3 + 1;
+            double half = 1 / 2.0, one = 1;
+            char c = 'a' + 1;
+            int a[4] = {1, 2, 3};
+            char s[] = "hi";
+            int m[2][3] = {{1, 2, 3}, {4}};
+            struct L gl = {{1}, {2, 3.5, {'x', 'y'}}};
+            struct P mk(int x) { struct P p = {x, x * 0.5, "mk"}; return p; }
+            void main() {
+                int i;
+                put_i(n); put_c(' '); put_d(half + one); put_c(c); put_c(' ');
+                for (i = 0; i < 4; i = i + 1) put_i(a[i]);
+                put_c(' '); put_s(s); put_c(' '); put_i(m[0][2] + m[1][0] + m[1][2]); put_c(' ');
+                put_i(gl.a.x); put_i(gl.b.x); put_d(gl.b.y); put_s(gl.b.tag); put_c(' ');
+                for (i = 0; i < 2; i = i + 1) {
+                    int k = i * 10; double d = k; char t[8] = "loc";
+                    struct L l = {mk(k), {k + 1}}; int z[3] = {k};
+                    put_i(k); put_d(d); put_s(t); put_i(l.a.x); put_d(l.a.y); put_s(l.a.tag);
+                    put_i(l.b.x); put_d(l.b.y); put_i(z[0] + z[1] + z[2]); put_c(' ');
+                    z[1] = 5; l.b.y = 9.5; t[0] = 'L';
+                }
+            }
+        "#);
+        assert_eq!(
+            out,
+            "7 1.5b 1230 hi 7 123.5xy 00loc00mk100 1010loc105mk11010 "
+        );
+    }
+}
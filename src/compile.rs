@@ -0,0 +1,105 @@
+use crate::asdr::SyntaxAnalyser;
+use crate::ast::Unit;
+use crate::codegen::CodeGen;
+use crate::lexer::Token;
+use crate::lint::{lint, Lint, Warning};
+use crate::mv::{Globals, InstrList};
+use crate::resolve::Resolver;
+use crate::symbols::Context;
+use crate::typecheck::TypeChecker;
+
+/// The stages of the compiler, in the order they run
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Parse the tokens into a `Unit`
+    Syntax,
+    /// Build the symbol tables and link the names to their symbols
+    Resolve,
+    /// Check the types of the expressions and look for warnings
+    Typecheck,
+    /// Emit the instructions of the virtual machine
+    Codegen,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    /// The last stage to run
+    pub stage: Stage,
+    /// The lints whose warnings are not reported
+    pub allow: Vec<Lint>,
+    /// Compiles functions for other programs, `main` is not needed
+    pub library: bool,
+}
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            stage: Stage::Codegen,
+            allow: vec![],
+            library: false,
+        }
+    }
+}
+
+/// What the stages produced. The fields of the stages which did not run are empty
+#[derive(Debug, Default)]
+pub struct Compilation {
+    pub unit: Unit,
+    pub symbol_tables: Vec<Context>,
+    pub warnings: Vec<Warning>,
+    /// The memory of the globals, the code refers to it so it must outlive the runs of the code
+    pub globals: Globals,
+    pub code: Option<InstrList>,
+}
+
+/// Runs the stages up to `options.stage`. Errors stop the compilation with a panic
+pub fn compile(tokens: Vec<Token>, options: &CompileOptions) -> Compilation {
+    let mut c = Compilation {
+        unit: SyntaxAnalyser::new(tokens).analyse_syntax(),
+        ..Default::default()
+    };
+    if options.stage >= Stage::Resolve {
+        let mut resolver = Resolver::new(&mut c.globals);
+        resolver.resolve(&mut c.unit);
+        if !options.library {
+            resolver.check_main();
+        }
+        c.symbol_tables = resolver.symbol_tables;
+    }
+    if options.stage >= Stage::Typecheck {
+        TypeChecker::new(&c.symbol_tables[0]).check(&mut c.unit);
+        c.warnings = lint(&c.unit, &options.allow);
+    }
+    if options.stage >= Stage::Codegen {
+        let mut codegen = CodeGen::new(&mut c.globals, &c.symbol_tables[0]);
+        codegen.gen(&c.unit);
+        c.code = Some(codegen.code);
+    }
+    c
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::compile::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn compile_stops_at_stage() {
+        // Fine for the parser, `y` is only found by name resolution
+        let src = "void main() { y = 1; }";
+        let options = CompileOptions {
+            stage: Stage::Syntax,
+            ..Default::default()
+        };
+        let c = compile(Lexer::new(src, "").get_tokens(), &options);
+        assert_eq!(c.unit.items.len(), 1);
+        assert!(c.symbol_tables.is_empty() && c.code.is_none());
+        let resolved = std::panic::catch_unwind(|| {
+            let options = CompileOptions {
+                stage: Stage::Resolve,
+                ..Default::default()
+            };
+            compile(Lexer::new(src, "").get_tokens(), &options)
+        });
+        assert!(resolved.is_err());
+    }
+}
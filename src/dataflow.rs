@@ -0,0 +1,286 @@
+//! Dataflow analysis over the control flow graphs: which locals are surely assigned at
+//! every point of a function, to warn about the reads of the others
+use crate::ast::*;
+use crate::cfg::{Block, Cfg, Inst, Terminator};
+use crate::intern::Name;
+use crate::lint::{Lint, Warning};
+use crate::symbols::StorageType;
+use std::collections::BTreeSet;
+
+/// A local or a member of it: the name and the line of the local, then the names of the
+/// members. The indexes are left out, all the elements of an array are one place
+type Place = (Name, usize, Vec<Name>);
+
+/// The places assigned on every path to a point of the function
+type Assigned = BTreeSet<Place>;
+
+/// The local accessed by `e` if it is a place, with its source: `p.items[]`
+fn access(e: &Expr) -> Option<(Place, String)> {
+    match &e.kind {
+        ExprKind::Id(name) => match e.sym.as_deref() {
+            Some(s) if s.storage == StorageType::MemLocal => {
+                Some(((*name, s.line, vec![]), name.to_string()))
+            }
+            _ => None,
+        },
+        ExprKind::Member(object, member) => {
+            let (mut place, text) = access(object)?;
+            place.2.push(*member);
+            Some((place, format!("{}.{}", text, member)))
+        }
+        ExprKind::Index(array, _) => {
+            let (place, text) = access(array)?;
+            Some((place, format!("{}[]", text)))
+        }
+        _ => None,
+    }
+}
+
+/// A place is assigned when it, a part of it or the whole it is part of was assigned
+fn is_assigned(assigned: &Assigned, (name, line, path): &Place) -> bool {
+    assigned
+        .iter()
+        .any(|(n, l, p)| n == name && l == line && (p.starts_with(path) || path.starts_with(p)))
+}
+
+/// Warns about the reads of the locals of `cfg` which may not be assigned yet
+pub fn uninitialized(cfg: &Cfg) -> Vec<Warning> {
+    let reachable = cfg.reachable();
+    let mut preds = vec![vec![]; cfg.blocks.len()];
+    for id in (0..cfg.blocks.len()).filter(|&id| reachable[id]) {
+        for s in cfg.successors(id) {
+            preds[s].push(id);
+        }
+    }
+    // `None` until a path reaches the block, it is the neutral value of the intersection
+    let mut outs: Vec<Option<Assigned>> = vec![None; cfg.blocks.len()];
+    let mut flow = Flow {
+        assigned: Assigned::new(),
+        warnings: vec![],
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for id in (0..cfg.blocks.len()).filter(|&id| reachable[id]) {
+            let assigned = match entry(id, &preds[id], &outs) {
+                Some(assigned) => assigned,
+                None => continue,
+            };
+            flow.assigned = assigned;
+            flow.block(&cfg.blocks[id]);
+            if outs[id].as_ref() != Some(&flow.assigned) {
+                outs[id] = Some(std::mem::take(&mut flow.assigned));
+                changed = true;
+            }
+        }
+    }
+    // The states are final, only now the reads are checked
+    flow.warnings.clear();
+    for id in (0..cfg.blocks.len()).filter(|&id| reachable[id]) {
+        if let Some(assigned) = entry(id, &preds[id], &outs) {
+            flow.assigned = assigned;
+            flow.block(&cfg.blocks[id]);
+        }
+    }
+    flow.warnings
+}
+
+/// The places assigned when the block `id` starts
+fn entry(id: usize, preds: &[usize], outs: &[Option<Assigned>]) -> Option<Assigned> {
+    if id == 0 {
+        return Some(Assigned::new());
+    }
+    preds.iter().filter_map(|&p| outs[p].as_ref()).fold(
+        None,
+        |acc: Option<Assigned>, out| match acc {
+            None => Some(out.clone()),
+            Some(acc) => Some(acc.intersection(out).cloned().collect()),
+        },
+    )
+}
+
+struct Flow {
+    assigned: Assigned,
+    warnings: Vec<Warning>,
+}
+
+impl Flow {
+    fn block(&mut self, block: &Block) {
+        for inst in &block.insts {
+            match inst {
+                // A declaration in a loop makes the local unassigned again, unless it
+                // has an initializer
+                Inst::Var(v) => {
+                    if let Some(init) = &v.init {
+                        self.read_init(init);
+                    }
+                    self.assigned
+                        .retain(|(name, line, _)| *name != v.name || *line != v.loc.line);
+                    if v.init.is_some() {
+                        self.assigned.insert((v.name, v.loc.line, vec![]));
+                    }
+                }
+                Inst::Eval(e) => self.read(e),
+            }
+        }
+        match &block.term {
+            Terminator::Branch { cond, .. } => self.read(cond),
+            Terminator::Switch { value, .. } => self.read(value),
+            Terminator::Return(Some(value)) => self.read(value),
+            Terminator::Goto(_) | Terminator::Return(None) | Terminator::End => {}
+        }
+    }
+
+    fn read(&mut self, e: &Expr) {
+        if let Some((place, text)) = access(e) {
+            self.read_indexes(e);
+            if e.ty().num_elements > -1 {
+                // An array given to a function may be filled by it
+                self.assigned.insert(place);
+            } else if !is_assigned(&self.assigned, &place) {
+                let (name, line, _) = place;
+                let warning = Warning {
+                    lint: Lint::Uninitialized,
+                    loc: e.loc,
+                    msg: format!(
+                        "`{}` may be used before it is assigned, `{}` is declared on line {}",
+                        text, name, line
+                    ),
+                };
+                if !self.warnings.contains(&warning) {
+                    self.warnings.push(warning);
+                }
+            }
+            return;
+        }
+        match &e.kind {
+            ExprKind::Int(_) | ExprKind::Real(_) | ExprKind::Char(_) | ExprKind::Str(_) => {}
+            ExprKind::Call(_, args) => args.iter().for_each(|a| self.read(a)),
+            ExprKind::Id(_) => {}
+            // The right operand may be skipped, its assignments may not happen
+            ExprKind::Binary(BinOp::And | BinOp::Or, a, b) => {
+                self.read(a);
+                let assigned = self.assigned.clone();
+                self.read(b);
+                self.assigned = assigned;
+            }
+            ExprKind::Index(a, b) | ExprKind::Binary(_, a, b) => {
+                self.read(a);
+                self.read(b);
+            }
+            ExprKind::Member(a, _)
+            | ExprKind::Unary(_, a)
+            | ExprKind::IncDec { operand: a, .. }
+            | ExprKind::Cast(_, a)
+            | ExprKind::Conv(a) => self.read(a),
+            // The destination of a compound assignment is read before it is written
+            ExprKind::OpAssign(_, dst, src) => {
+                self.read(src);
+                self.read(dst);
+            }
+            ExprKind::Assign(dst, src) => {
+                self.read(src);
+                match access(dst) {
+                    Some((place, _)) => {
+                        self.read_indexes(dst);
+                        self.assigned.insert(place);
+                    }
+                    None => self.read(dst),
+                }
+            }
+        }
+    }
+    fn read_init(&mut self, init: &Init) {
+        match init {
+            Init::Expr(e) => self.read(e),
+            Init::List(items, _) => items.iter().for_each(|i| self.read_init(i)),
+        }
+    }
+    /// Reads the indexes of the place `e`
+    fn read_indexes(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Index(array, index) => {
+                self.read_indexes(array);
+                self.read(index);
+            }
+            ExprKind::Member(object, _) => self.read_indexes(object),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::compile::{compile, CompileOptions, Stage};
+    use crate::lexer::Lexer;
+    use crate::lint::Lint;
+
+    fn warnings(src: &str) -> Vec<String> {
+        let options = CompileOptions {
+            stage: Stage::Typecheck,
+            ..Default::default()
+        };
+        let c = compile(Lexer::new(src, "").get_tokens(), &options);
+        c.warnings
+            .iter()
+            .filter(|w| w.lint == Lint::Uninitialized)
+            .map(|w| w.to_string())
+            .collect()
+    }
+    #[test]
+    fn dataflow_paths() {
+        let src = "void main() {
+                int x; int y; int z;
+                if (get_i()) { x = 1; y = 1; } else x = 2;
+                put_i(x + y);
+                while (get_i()) { z = 1; }
+                put_i(z);
+                if (get_i() && (x = get_i()) || (y = 1)) put_i(y);
+            }";
+        assert_eq!(
+            warnings(src),
+            vec![
+                "Warning in line 4, `y` may be used before it is assigned, `y` is declared on line 2 [uninitialized]",
+                "Warning in line 6, `z` may be used before it is assigned, `z` is declared on line 2 [uninitialized]",
+                "Warning in line 7, `y` may be used before it is assigned, `y` is declared on line 2 [uninitialized]",
+            ]
+        );
+    }
+    #[test]
+    fn dataflow_switch() {
+        let src = "void main() {
+                int x; int y; int i;
+                switch (get_i()) { case 1: x = 1; y = 1; case 2: x = 2; break; default: x = 3; }
+                put_i(x + y);
+                for (i = 0; i < 3; i++) { int z; if (i) continue; z = 1; put_i(z); }
+            }";
+        assert_eq!(
+            warnings(src),
+            vec![
+                "Warning in line 4, `y` may be used before it is assigned, `y` is declared on line 2 [uninitialized]",
+            ]
+        );
+    }
+    #[test]
+    fn dataflow_members_and_elements() {
+        let src = "struct P { int x; int y; };
+            void main() {
+                struct P p; int a[3]; char s[10]; int i;
+                p.x = 1;
+                put_i(p.x + p.y);
+                for (i = 0; i < 3; i = i + 1) { put_i(a[i]); a[i] = i; }
+                get_s(s);
+                put_s(s);
+                while (get_i()) { int n; put_i(n); n = 1; }
+                while (get_i()) { int k = 1; int j = k; put_i(j + k); k = j; }
+            }";
+        assert_eq!(
+            warnings(src),
+            vec![
+                "Warning in line 5, `p.y` may be used before it is assigned, `p` is declared on line 3 [uninitialized]",
+                "Warning in line 6, `a[]` may be used before it is assigned, `a` is declared on line 3 [uninitialized]",
+                "Warning in line 9, `n` may be used before it is assigned, `n` is declared on line 9 [uninitialized]",
+            ]
+        );
+    }
+}
@@ -0,0 +1,788 @@
+//! Text and JSON dumps of the tokens, the parsed tree and the symbol tables, text dumps of
+//! the memory layout and text and Graphviz dumps of the control flow graphs, used by the
+//! `--dump-*` modes of the compiler
+use crate::ast::*;
+use crate::cfg::{Cfg, Inst, Terminator};
+use crate::lexer::{Token, TokenValue};
+use crate::mv::Globals;
+use crate::symbols::{ClassType, Context, Symbol, SymbolType, TypeName};
+use std::fmt::Write;
+
+/// The format of a dump
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// A JSON value. Objects keep the order of their keys
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Real(f32),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(&'static str, Json)>),
+}
+impl Json {
+    pub fn str(s: impl ToString) -> Json {
+        Json::Str(s.to_string())
+    }
+    /// Returns the value as pretty printed JSON
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        out
+    }
+    fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => write!(out, "{}", b).unwrap(),
+            Json::Int(i) => write!(out, "{}", i).unwrap(),
+            Json::Real(d) => write!(out, "{:?}", d).unwrap(),
+            Json::Str(s) => write_json_string(out, s),
+            Json::Arr(items) if items.is_empty() => out.push_str("[]"),
+            Json::Arr(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(if i == 0 { "\n" } else { ",\n" });
+                    out.push_str(&"  ".repeat(indent + 1));
+                    item.write(out, indent + 1);
+                }
+                out.push('\n');
+                out.push_str(&"  ".repeat(indent));
+                out.push(']');
+            }
+            Json::Obj(fields) if fields.is_empty() => out.push_str("{}"),
+            Json::Obj(fields) => {
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    out.push_str(if i == 0 { "\n" } else { ",\n" });
+                    out.push_str(&"  ".repeat(indent + 1));
+                    write_json_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                }
+                out.push('\n');
+                out.push_str(&"  ".repeat(indent));
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// Tokens
+
+fn token_value(value: &TokenValue) -> Option<String> {
+    match value {
+        TokenValue::None => None,
+        TokenValue::Id(name) => Some(name.to_string()),
+        TokenValue::Real(d) => Some(format!("{:?}", d)),
+        TokenValue::Int(i) => Some(i.to_string()),
+        TokenValue::Char(c) => Some(format!("{:?}", c)),
+        TokenValue::Str(s) => Some(format!("\"{}\"", s)),
+    }
+}
+
+/// One token per line: `file:line:col-end kind value`
+pub fn dump_tokens(tokens: &[Token], format: Format) -> String {
+    match format {
+        Format::Text => {
+            let mut out = String::new();
+            for t in tokens {
+                let span = format!("{}:{}-{}", t.location(), t.col, t.col + t.len);
+                let kind = format!("{:?}", t.kind);
+                let value = token_value(&t.value).unwrap_or_default();
+                writeln!(out, "{:<24} {:<10} {}", span, kind, value).unwrap();
+            }
+            out
+        }
+        Format::Json => {
+            let tokens = tokens
+                .iter()
+                .map(|t| {
+                    let mut fields = vec![("kind", Json::str(format!("{:?}", t.kind)))];
+                    match t.value {
+                        TokenValue::None => {}
+                        TokenValue::Id(name) => fields.push(("value", Json::str(name))),
+                        TokenValue::Real(d) => fields.push(("value", Json::Real(d))),
+                        TokenValue::Int(i) => fields.push(("value", Json::Int(i as i64))),
+                        TokenValue::Char(c) => fields.push(("value", Json::str(c))),
+                        TokenValue::Str(s) => fields.push(("value", Json::str(s))),
+                    }
+                    fields.push(("file", Json::str(t.file)));
+                    fields.push(("line", Json::Int(t.line as i64)));
+                    fields.push(("col", Json::Int(t.col as i64)));
+                    fields.push(("len", Json::Int(t.len as i64)));
+                    Json::Obj(fields)
+                })
+                .collect();
+            Json::Arr(tokens).pretty() + "\n"
+        }
+    }
+}
+
+// Parsed tree
+
+/// A node of the tree: a label, an optional location and the child nodes.
+/// Both formats are made from it
+struct Node {
+    label: String,
+    loc: Option<Loc>,
+    fields: Vec<(&'static str, Json)>,
+    children: Vec<(&'static str, Node)>,
+}
+impl Node {
+    fn new(label: &str, loc: Loc) -> Self {
+        Self {
+            label: label.to_string(),
+            loc: Some(loc),
+            fields: vec![],
+            children: vec![],
+        }
+    }
+    fn field(mut self, key: &'static str, value: Json) -> Self {
+        self.fields.push((key, value));
+        self
+    }
+    fn child(mut self, key: &'static str, node: Node) -> Self {
+        self.children.push((key, node));
+        self
+    }
+    fn text(&self, out: &mut String, indent: usize) {
+        out.push_str(&"  ".repeat(indent));
+        out.push_str(&self.label);
+        for (key, value) in &self.fields {
+            match value {
+                Json::Str(s) if *key == "name" || *key == "op" => write!(out, " {}", s),
+                Json::Str(s) if *key == "type" => write!(out, ": {}", s),
+                Json::Str(s) => write!(out, " {}={:?}", key, s),
+                value => write!(out, " {}={}", key, value.pretty()),
+            }
+            .unwrap();
+        }
+        if let Some(loc) = self.loc {
+            write!(out, " <{}>", loc).unwrap();
+        }
+        out.push('\n');
+        for (_, child) in &self.children {
+            child.text(out, indent + 1);
+        }
+    }
+    fn json(&self) -> Json {
+        let mut fields = vec![("node", Json::str(&self.label))];
+        if let Some(loc) = self.loc {
+            fields.push(("file", Json::str(loc.file)));
+            fields.push(("line", Json::Int(loc.line as i64)));
+        }
+        fields.extend(self.fields.iter().cloned());
+        // Children with the same key are grouped in an array
+        let mut keys: Vec<&'static str> = vec![];
+        for (key, _) in &self.children {
+            if !keys.contains(key) {
+                keys.push(key);
+            }
+        }
+        for key in keys {
+            let mut group = self
+                .children
+                .iter()
+                .filter(|(k, _)| *k == key)
+                .map(|(_, n)| n.json())
+                .collect::<Vec<Json>>();
+            let value = if key.ends_with('s') {
+                Json::Arr(group)
+            } else {
+                group.remove(0)
+            };
+            fields.push((key, value));
+        }
+        Json::Obj(fields)
+    }
+}
+
+fn var_node(label: &str, v: &VarDecl) -> Node {
+    let node = Node::new(label, v.loc)
+        .field("name", Json::str(v.name))
+        .field("type", Json::str(&v.ty));
+    match &v.init {
+        Some(init) => node.child("init", init_node(init)),
+        None => node,
+    }
+}
+
+fn init_node(init: &Init) -> Node {
+    match init {
+        Init::Expr(e) => expr_node(e),
+        Init::List(items, loc) => items.iter().fold(Node::new("List", *loc), |n, i| {
+            n.child("items", init_node(i))
+        }),
+    }
+}
+
+fn stm_node(s: &Stm) -> Node {
+    let node = |label| Node::new(label, s.loc);
+    match &s.kind {
+        StmKind::Block(stms) => stms
+            .iter()
+            .fold(node("Block"), |n, s| n.child("stms", stm_node(s))),
+        StmKind::Var(v) => var_node("Var", v),
+        StmKind::If { cond, then, els } => {
+            let n = node("If")
+                .child("cond", expr_node(cond))
+                .child("then", stm_node(then));
+            match els {
+                Some(els) => n.child("else", stm_node(els)),
+                None => n,
+            }
+        }
+        StmKind::While { cond, body } => node("While")
+            .child("cond", expr_node(cond))
+            .child("body", stm_node(body)),
+        StmKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            let mut n = node("For");
+            for (key, e) in [("init", init), ("cond", cond), ("step", step)] {
+                if let Some(e) = e {
+                    n = n.child(key, expr_node(e));
+                }
+            }
+            n.child("body", stm_node(body))
+        }
+        StmKind::DoWhile { body, cond } => node("DoWhile")
+            .child("body", stm_node(body))
+            .child("cond", expr_node(cond)),
+        StmKind::Switch { value, cases } => cases.iter().fold(
+            node("Switch").child("value", expr_node(value)),
+            |n, case| n.child("cases", case_node(case)),
+        ),
+        StmKind::Break => node("Break"),
+        StmKind::Continue => node("Continue"),
+        StmKind::Return(e) => match e {
+            Some(e) => node("Return").child("value", expr_node(e)),
+            None => node("Return"),
+        },
+        StmKind::Expr(e) => node("Expr").child("expr", expr_node(e)),
+        StmKind::Empty => node("Empty"),
+    }
+}
+
+fn case_node(case: &Case) -> Node {
+    let node = match &case.value {
+        Some(value) => Node::new("Case", case.loc).child("value", expr_node(value)),
+        None => Node::new("Default", case.loc),
+    };
+    case.stms
+        .iter()
+        .fold(node, |n, s| n.child("stms", stm_node(s)))
+}
+
+fn expr_node(e: &Expr) -> Node {
+    let node = |label| Node::new(label, e.loc);
+    match &e.kind {
+        ExprKind::Int(i) => node("Int").field("value", Json::Int(*i as i64)),
+        ExprKind::Real(d) => node("Real").field("value", Json::Real(*d)),
+        ExprKind::Char(c) => node("Char").field("value", Json::str(c)),
+        ExprKind::Str(s) => node("Str").field("value", Json::str(s)),
+        ExprKind::Id(name) => node("Id").field("name", Json::str(name)),
+        ExprKind::Call(name, args) => args
+            .iter()
+            .fold(node("Call").field("name", Json::str(name)), |n, a| {
+                n.child("args", expr_node(a))
+            }),
+        ExprKind::Index(array, index) => node("Index")
+            .child("array", expr_node(array))
+            .child("index", expr_node(index)),
+        ExprKind::Member(object, member) => node("Member")
+            .field("name", Json::str(member))
+            .child("object", expr_node(object)),
+        ExprKind::Unary(op, operand) => node("Unary")
+            .field("op", Json::str(op.text()))
+            .child("operand", expr_node(operand)),
+        ExprKind::Binary(op, left, right) => node("Binary")
+            .field("op", Json::str(op.text()))
+            .child("left", expr_node(left))
+            .child("right", expr_node(right)),
+        ExprKind::Assign(dst, src) => node("Assign")
+            .child("dst", expr_node(dst))
+            .child("src", expr_node(src)),
+        ExprKind::OpAssign(op, dst, src) => node("OpAssign")
+            .field("op", Json::str(op.text()))
+            .child("dst", expr_node(dst))
+            .child("src", expr_node(src)),
+        ExprKind::IncDec {
+            op,
+            prefix,
+            operand,
+        } => node("IncDec")
+            .field("op", Json::str(op.text()))
+            .field("prefix", Json::Bool(*prefix))
+            .child("operand", expr_node(operand)),
+        ExprKind::Cast(ty, operand) => node("Cast")
+            .field("type", Json::str(ty))
+            .child("operand", expr_node(operand)),
+        ExprKind::Conv(operand) => node("Conv")
+            .field("type", Json::str(e.ty()))
+            .child("operand", expr_node(operand)),
+    }
+}
+
+fn unit_node(unit: &Unit) -> Node {
+    let mut root = Node {
+        label: String::from("Unit"),
+        loc: None,
+        fields: vec![],
+        children: vec![],
+    };
+    for item in &unit.items {
+        let node = match item {
+            Item::Struct(s) => s.members.iter().fold(
+                Node::new("Struct", s.loc).field("name", Json::str(s.name)),
+                |n, m| n.child("members", var_node("Member", m)),
+            ),
+            Item::Func(f) => {
+                let label = if f.body.is_some() { "Func" } else { "Proto" };
+                let n = f.args.iter().fold(
+                    Node::new(label, f.loc)
+                        .field("name", Json::str(f.name))
+                        .field("type", Json::str(&f.ret)),
+                    |n, a| n.child("args", var_node("Arg", a)),
+                );
+                match &f.body {
+                    Some(body) => n.child("body", stm_node(body)),
+                    None => n,
+                }
+            }
+            Item::Var(v) => var_node("Var", v),
+        };
+        root = root.child("items", node);
+    }
+    root
+}
+
+/// The parsed tree, as an indented tree of nodes or as JSON
+pub fn dump_ast(unit: &Unit, format: Format) -> String {
+    let root = unit_node(unit);
+    match format {
+        Format::Text => {
+            let mut out = String::new();
+            root.text(&mut out, 0);
+            out
+        }
+        Format::Json => root.json().pretty() + "\n",
+    }
+}
+
+// Symbol tables
+
+fn symbol_json(s: &Symbol) -> Json {
+    let mut fields = vec![
+        ("name", Json::str(s.name)),
+        ("class", Json::str(format!("{:?}", s.class))),
+        ("storage", Json::str(format!("{:?}", s.storage))),
+        ("type", Json::str(&s.symbol_type)),
+        ("depth", Json::Int(s.depth as i64)),
+        ("line", Json::Int(s.line as i64)),
+    ];
+    if let Some(am) = &s.am {
+        let key = match s.class {
+            ClassType::ClsStruct => "members",
+            _ => "args",
+        };
+        fields.push((key, Json::Arr(am.values().map(symbol_json).collect())));
+    }
+    Json::Obj(fields)
+}
+
+fn symbol_text(out: &mut String, s: &Symbol, indent: usize) {
+    writeln!(
+        out,
+        "{}{:<12} {:<10} {:<10} {:<16} depth {} line {}",
+        "  ".repeat(indent),
+        s.name.as_str(),
+        format!("{:?}", s.class),
+        format!("{:?}", s.storage),
+        s.symbol_type.to_string(),
+        s.depth,
+        s.line
+    )
+    .unwrap();
+    if let Some(am) = &s.am {
+        for member in am.values() {
+            symbol_text(out, member, indent + 1);
+        }
+    }
+}
+
+/// The symbol tables. Struct members and function arguments are listed under their symbol
+pub fn dump_symbols(contexts: &[Context], format: Format) -> String {
+    match format {
+        Format::Text => {
+            let mut out = String::new();
+            for (i, context) in contexts.iter().enumerate() {
+                writeln!(
+                    out,
+                    "context {} ({:?}, depth {})",
+                    i, context.storage, context.depth
+                )
+                .unwrap();
+                for s in context.symbols.values() {
+                    symbol_text(&mut out, s, 1);
+                }
+            }
+            out
+        }
+        Format::Json => {
+            let contexts = contexts
+                .iter()
+                .map(|c| {
+                    Json::Obj(vec![
+                        ("storage", Json::str(format!("{:?}", c.storage))),
+                        ("depth", Json::Int(c.depth as i64)),
+                        (
+                            "symbols",
+                            Json::Arr(c.symbols.values().map(symbol_json).collect()),
+                        ),
+                    ])
+                })
+                .collect();
+            Json::Arr(contexts).pretty() + "\n"
+        }
+    }
+}
+
+// Memory layout
+
+fn offset_text(out: &mut String, s: &Symbol) {
+    writeln!(
+        out,
+        "  {:<12} {:<10} {:<16} size {:<4} offset {}",
+        s.name.as_str(),
+        format!("{:?}", s.storage),
+        s.symbol_type.to_string(),
+        s.symbol_type.size(),
+        s.ao.get_offset()
+    )
+    .unwrap();
+}
+
+/// The sizes and the alignments of the structs, the addresses of the globals in the globals
+/// area and the frame offsets of the arguments and of the locals of every function
+pub fn dump_layout(contexts: &[Context], unit: &Unit, globals: &Globals) -> String {
+    let mut out = String::new();
+    for s in contexts[0].symbols.values() {
+        match (&s.class, &s.am) {
+            (ClassType::ClsStruct, Some(members)) => {
+                let t = SymbolType {
+                    type_base: TypeName::TbStruct,
+                    struct_symbol: Some(Box::new(s.clone())),
+                    ..Default::default()
+                };
+                writeln!(
+                    out,
+                    "struct {}: size {}, align {}",
+                    s.name,
+                    t.size(),
+                    t.align()
+                )
+                .unwrap();
+                members.values().for_each(|m| offset_text(&mut out, m));
+            }
+            (ClassType::ClsVar, _) => writeln!(
+                out,
+                "global {}: {}, size {}, align {}, address {}",
+                s.name,
+                s.symbol_type,
+                s.symbol_type.size(),
+                s.symbol_type.align(),
+                globals.offset_of(s.ao.get_addr())
+            )
+            .unwrap(),
+            (ClassType::ClsFunc, Some(args)) => {
+                let func = unit.items.iter().find_map(|item| match item {
+                    Item::Func(f) if f.name == s.name && f.body.is_some() => Some(f),
+                    _ => None,
+                });
+                let args_size: usize = args.values().map(|a| a.symbol_type.size()).sum();
+                write!(out, "function {}: args {}", s.name, args_size).unwrap();
+                match func {
+                    Some(f) => writeln!(out, ", frame {}", f.frame).unwrap(),
+                    None => writeln!(out).unwrap(),
+                }
+                args.values().for_each(|a| offset_text(&mut out, a));
+                for local in func.iter().flat_map(|f| &f.locals) {
+                    offset_text(&mut out, local);
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+// Control flow graphs
+
+fn inst_text(inst: &Inst) -> String {
+    match inst {
+        Inst::Var(v) => match &v.init {
+            Some(init) => format!("var {}: {} = {}", v.name, v.ty, init),
+            None => format!("var {}: {}", v.name, v.ty),
+        },
+        Inst::Eval(e) => e.to_string(),
+    }
+}
+
+fn terminator_text(term: &Terminator) -> String {
+    match term {
+        Terminator::Goto(target) => format!("goto bb{}", target),
+        Terminator::Branch { cond, then, els } => {
+            format!("if {} goto bb{} else bb{}", cond, then, els)
+        }
+        Terminator::Switch {
+            value,
+            cases,
+            default,
+        } => {
+            let cases: Vec<String> = cases
+                .iter()
+                .map(|(case, target)| format!("case {} goto bb{}, ", case, target))
+                .collect();
+            format!(
+                "switch {} {}default goto bb{}",
+                value,
+                cases.concat(),
+                default
+            )
+        }
+        Terminator::Return(Some(value)) => format!("return {}", value),
+        Terminator::Return(None) => "return".to_string(),
+        Terminator::End => "end".to_string(),
+    }
+}
+
+/// The blocks of every function, with their statements and the jump which ends them
+pub fn dump_cfg(cfgs: &[Cfg]) -> String {
+    let mut out = String::new();
+    for cfg in cfgs {
+        writeln!(out, "{}:", cfg.name).unwrap();
+        for (id, (block, reachable)) in cfg.blocks.iter().zip(cfg.reachable()).enumerate() {
+            let dead = if reachable { "" } else { " (unreachable)" };
+            writeln!(out, "  bb{}:{}", id, dead).unwrap();
+            for inst in &block.insts {
+                writeln!(out, "    {}", inst_text(inst)).unwrap();
+            }
+            writeln!(out, "    {}", terminator_text(&block.term)).unwrap();
+        }
+    }
+    out
+}
+
+fn dot_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The graphs in the Graphviz DOT language, a cluster for every function.
+/// The blocks which cannot be reached are dashed
+pub fn dump_cfg_dot(cfgs: &[Cfg]) -> String {
+    let mut out = String::new();
+    writeln!(out, "digraph cfg {{").unwrap();
+    writeln!(out, "  node [shape=box, fontname=\"monospace\"];").unwrap();
+    for cfg in cfgs {
+        let f = cfg.name;
+        writeln!(out, "  subgraph cluster_{} {{", f).unwrap();
+        writeln!(out, "    label=\"{}\";", f).unwrap();
+        for (id, (block, reachable)) in cfg.blocks.iter().zip(cfg.reachable()).enumerate() {
+            let mut label = format!("bb{}\\l", id);
+            for inst in &block.insts {
+                label += &dot_string(&inst_text(inst));
+                label += "\\l";
+            }
+            if let Terminator::Return(_) | Terminator::End = block.term {
+                label += &dot_string(&terminator_text(&block.term));
+                label += "\\l";
+            }
+            let style = if reachable { "" } else { ", style=dashed" };
+            writeln!(out, "    {}_bb{} [label=\"{}\"{}];", f, id, label, style).unwrap();
+            match &block.term {
+                Terminator::Goto(target) => {
+                    writeln!(out, "    {}_bb{} -> {}_bb{};", f, id, f, target).unwrap()
+                }
+                Terminator::Switch { cases, default, .. } => {
+                    for (case, target) in cases {
+                        let case = dot_string(&case.to_string());
+                        writeln!(
+                            out,
+                            "    {}_bb{} -> {}_bb{} [label=\"case {}\"];",
+                            f, id, f, target, case
+                        )
+                        .unwrap();
+                    }
+                    writeln!(
+                        out,
+                        "    {}_bb{} -> {}_bb{} [label=\"default\"];",
+                        f, id, f, default
+                    )
+                    .unwrap();
+                }
+                Terminator::Branch { cond, then, els } => {
+                    let cond = dot_string(&cond.to_string());
+                    writeln!(
+                        out,
+                        "    {}_bb{} -> {}_bb{} [label=\"{}\"];",
+                        f, id, f, then, cond
+                    )
+                    .unwrap();
+                    writeln!(
+                        out,
+                        "    {}_bb{} -> {}_bb{} [label=\"!{}\"];",
+                        f, id, f, els, cond
+                    )
+                    .unwrap();
+                }
+                Terminator::Return(_) | Terminator::End => {}
+            }
+        }
+        writeln!(out, "  }}").unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::asdr::SyntaxAnalyser;
+    use crate::compile::{compile, CompileOptions, Stage};
+    use crate::dump::*;
+    use crate::lexer::Lexer;
+    #[test]
+    fn dump_tokens_spans() {
+        let tokens = Lexer::new("int x;\n  x = 'a';", "a.c").get_tokens();
+        let text = dump_tokens(&tokens, Format::Text);
+        let lines = text.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[1], format!("{:<24} {:<10} x", "a.c:1:5-6", "Id"));
+        assert_eq!(
+            lines[5],
+            format!("{:<24} {:<10} 'a'", "a.c:2:7-10", "CtChar")
+        );
+        let json = dump_tokens(&tokens[..1], Format::Json);
+        assert_eq!(
+            json,
+            "[\n  {\n    \"kind\": \"Int\",\n    \"file\": \"a.c\",\n    \"line\": 1,\n    \"col\": 1,\n    \"len\": 3\n  }\n]\n"
+        );
+    }
+    #[test]
+    fn dump_ast_formats() {
+        let tokens = Lexer::new("int f(int a){ return a*2; }", "").get_tokens();
+        let unit = SyntaxAnalyser::new(tokens).analyse_syntax();
+        assert_eq!(
+            dump_ast(&unit, Format::Text),
+            "Unit
+  Func f: int <line 1>
+    Arg a: int <line 1>
+    Block <line 1>
+      Return <line 1>
+        Binary * <line 1>
+          Id a <line 1>
+          Int value=2 <line 1>
+"
+        );
+        let json = dump_ast(&unit, Format::Json);
+        assert!(json.contains("\"args\": [\n"));
+        assert!(json.contains("\"node\": \"Return\""));
+    }
+    #[test]
+    fn dump_cfg_formats() {
+        let tokens = Lexer::new("int f(int a) { while (a) a = a / 2; return 1; }", "").get_tokens();
+        let unit = SyntaxAnalyser::new(tokens).analyse_syntax();
+        let cfgs = crate::cfg::build(&unit);
+        assert_eq!(
+            dump_cfg(&cfgs),
+            "f:
+  bb0:
+    goto bb1
+  bb1:
+    if a goto bb2 else bb3
+  bb2:
+    a = (a / 2)
+    goto bb1
+  bb3:
+    return 1
+  bb4: (unreachable)
+    end
+"
+        );
+        let dot = dump_cfg_dot(&cfgs);
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    f_bb1 -> f_bb3 [label=\"!a\"];\n"));
+        assert!(dot.contains("    f_bb4 [label=\"bb4\\lend\\l\", style=dashed];\n"));
+    }
+    #[test]
+    fn dump_cfg_switch() {
+        let src = "void f(int x) { switch (x) { case 1: x = 2; break; default: ; } }";
+        let unit = SyntaxAnalyser::new(Lexer::new(src, "").get_tokens()).analyse_syntax();
+        let cfgs = crate::cfg::build(&unit);
+        assert!(dump_cfg(&cfgs).contains("    switch x case 1 goto bb1, default goto bb2\n"));
+        let dot = dump_cfg_dot(&cfgs);
+        assert!(dot.contains("    f_bb0 -> f_bb1 [label=\"case 1\"];\n"));
+        assert!(dot.contains("    f_bb0 -> f_bb2 [label=\"default\"];\n"));
+    }
+    #[test]
+    fn dump_layout_offsets() {
+        let src = "struct P { char c; double d; }; char g; struct P p;
+            int f(char a, struct P q) { char x; int y; { double z; } return y; }";
+        let options = CompileOptions {
+            stage: Stage::Resolve,
+            library: true,
+            ..Default::default()
+        };
+        let c = compile(Lexer::new(src, "").get_tokens(), &options);
+        let layout = dump_layout(&c.symbol_tables, &c.unit, &c.globals);
+        let lines: Vec<&str> = layout.lines().map(str::trim_end).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "struct P: size 16, align 8",
+                "  c            MemStruct  char             size 1    offset 0",
+                "  d            MemStruct  double           size 8    offset 8",
+                "global g: char, size 1, align 1, address 0",
+                "global p: struct P, size 16, align 8, address 8",
+                "function f: args 17, frame 24",
+                "  a            MemArg     char             size 1    offset -33",
+                "  q            MemArg     struct P         size 16   offset -32",
+                "  x            MemLocal   char             size 1    offset 0",
+                "  y            MemLocal   int              size 8    offset 8",
+                "  z            MemLocal   double           size 8    offset 16",
+            ]
+        );
+    }
+    #[test]
+    fn dump_json_escapes() {
+        let value = Json::Obj(vec![("s", Json::str("a\"b\\\n")), ("n", Json::Null)]);
+        assert_eq!(
+            value.pretty(),
+            "{\n  \"s\": \"a\\\"b\\\\\\n\",\n  \"n\": null\n}"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// An interned identifier. Copying and comparing a `Name` is as cheap as for an integer,
+/// the text is only looked up when it is displayed
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Name(u32);
+
+/// A fast hasher for the short identifiers of a program, in the style of rustc's `FxHasher`
+#[derive(Default)]
+pub struct NameHasher(u64);
+impl Hasher for NameHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        const K: u64 = 0x517c_c1b7_2722_0a95;
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(chunk);
+            self.0 = (self.0.rotate_left(5) ^ u64::from_le_bytes(word)).wrapping_mul(K);
+        }
+        for b in chunks.remainder() {
+            self.0 = (self.0.rotate_left(5) ^ u64::from(*b)).wrapping_mul(K);
+        }
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The string table behind `Name`. Every identifier is stored once and never freed
+#[derive(Default)]
+pub struct Interner {
+    names: HashMap<&'static str, Name, BuildHasherDefault<NameHasher>>,
+    strings: Vec<&'static str>,
+}
+impl Interner {
+    pub fn intern(&mut self, text: &str) -> Name {
+        if let Some(name) = self.names.get(text) {
+            return *name;
+        }
+        let text: &'static str = Box::leak(text.to_string().into_boxed_str());
+        let name = Name(self.strings.len() as u32);
+        self.strings.push(text);
+        self.names.insert(text, name);
+        name
+    }
+    pub fn get(&self, name: Name) -> &'static str {
+        self.strings[name.0 as usize]
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Name {
+    /// Returns the `Name` of `text`, adding it to the string table if needed
+    pub fn intern(text: &str) -> Name {
+        INTERNER.with(|i| i.borrow_mut().intern(text))
+    }
+    pub fn as_str(self) -> &'static str {
+        INTERNER.with(|i| i.borrow().get(self))
+    }
+}
+/// The empty name, used by symbols which were not given one yet
+impl Default for Name {
+    fn default() -> Self {
+        Name::intern("")
+    }
+}
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::intern::Name;
+    #[test]
+    fn intern_same_name() {
+        let a = Name::intern("count");
+        let b = Name::intern(&String::from("count"));
+        assert_eq!(a, b);
+        assert_ne!(a, Name::intern("counter"));
+        assert_eq!(a.as_str(), "count");
+        assert_eq!(format!("{}", b), "count");
+    }
+}
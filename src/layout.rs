@@ -0,0 +1,71 @@
+//! Memory layout of the variables. A value is placed at a multiple of its alignment: the
+//! members from the start of their struct, the locals from the frame pointer and the globals
+//! from the start of the globals area. The arguments are packed, the caller pushes them one
+//! after another
+use crate::symbols::SymbolType;
+use std::mem::size_of;
+
+/// `offset` rounded up to a multiple of `align`
+pub fn align_to(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+/// Places values one after another, each one at its alignment
+#[derive(Clone, Debug)]
+pub struct Layout {
+    /// The bytes used so far
+    pub size: usize,
+    /// The largest alignment of the placed values
+    pub align: usize,
+}
+impl Default for Layout {
+    fn default() -> Self {
+        Self { size: 0, align: 1 }
+    }
+}
+impl Layout {
+    /// Returns the offset of a new value of type `t`
+    pub fn place(&mut self, t: &SymbolType) -> usize {
+        let align = t.align();
+        let offset = align_to(self.size, align);
+        self.size = offset + t.size();
+        self.align = self.align.max(align);
+        offset
+    }
+}
+
+/// The offsets of the arguments from the frame pointer, they are below the return address
+/// and the saved frame pointer
+pub fn arg_offsets(types: &[SymbolType]) -> Vec<isize> {
+    let args_size: usize = types.iter().map(SymbolType::size).sum();
+    let mut offset = -((args_size + 2 * size_of::<*const ()>()) as isize);
+    types
+        .iter()
+        .map(|t| {
+            let arg = offset;
+            offset += t.size() as isize;
+            arg
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::layout::*;
+    use crate::symbols::TypeName;
+
+    #[test]
+    fn layout_place() {
+        let mut layout = Layout::default();
+        let c = SymbolType::new(TypeName::TbChar, -1);
+        let d = SymbolType::new(TypeName::TbDouble, -1);
+        let s = SymbolType::new(TypeName::TbChar, 5);
+        assert_eq!(layout.place(&c), 0);
+        assert_eq!(layout.place(&d), 8);
+        assert_eq!(layout.place(&s), 16);
+        assert_eq!(layout.place(&c), 21);
+        assert_eq!((layout.size, layout.align), (22, 8));
+        assert_eq!(align_to(layout.size, layout.align), 24);
+        assert_eq!(arg_offsets(&[c, d]), vec![-25, -24]);
+    }
+}
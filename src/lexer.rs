@@ -1,982 +1,598 @@
-//#![allow(dead_code)]
-use std::fs;
+use crate::intern::Name;
 
 // Tokens
 
-#[derive(Debug, Clone)]
-#[repr(u8)]
-pub enum TokenType {
-    Id(String),
-    Break,
-    Char,
-    Double,
-    Else,
-    For,
-    If,
-    Int,
-    Return,
-    Struct,
-    Void,
-    While,
-    CtReal(f32),
-    CtInt(isize),
-    CtChar(char),
-    CtString(String),
-    End,
-    Div,
-    Add,
-    Sub,
-    Mul,
-    Dot,
-    And,
-    Or,
-    Not,
-    NotEq,
-    Equal,
-    Assign,
-    Less,
-    LessEq,
-    Greater,
-    GreaterEq,
-    Comma,
-    Semicolon,
-    Lpar,
-    Rpar,
-    Lbracket,
-    Rbracket,
-    Lacc,
-    Racc,
-    Error,
+/// Defines `TokenKind` together with the keyword and punctuator tables,
+/// so a new keyword or operator is added with a single line
+macro_rules! token_kinds {
+    (
+        values { $($value:ident),* $(,)? }
+        keywords { $($keyword:ident => $keyword_text:literal),* $(,)? }
+        punctuators { $($punct:ident => $punct_text:literal),* $(,)? }
+    ) => {
+        /// The kind of a token, without its value
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        pub enum TokenKind {
+            $($value,)*
+            $($keyword,)*
+            $($punct,)*
+            End,
+            Error,
+        }
+        /// Keywords and the kind of their tokens
+        pub const KEYWORDS: &[(&str, TokenKind)] = &[$(($keyword_text, TokenKind::$keyword)),*];
+        /// Operators and delimiters and the kind of their tokens
+        pub const PUNCTUATORS: &[(&str, TokenKind)] = &[$(($punct_text, TokenKind::$punct)),*];
+
+        impl TokenKind {
+            /// Returns the keyword with the given text
+            pub fn keyword(text: &str) -> Option<TokenKind> {
+                match text {
+                    $($keyword_text => Some(TokenKind::$keyword),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+token_kinds! {
+    values {
+        Id,
+        CtReal,
+        CtInt,
+        CtChar,
+        CtString,
+    }
+    keywords {
+        Break => "break",
+        Case => "case",
+        Char => "char",
+        Continue => "continue",
+        Default => "default",
+        Do => "do",
+        Double => "double",
+        Else => "else",
+        For => "for",
+        If => "if",
+        Int => "int",
+        Return => "return",
+        Struct => "struct",
+        Switch => "switch",
+        Void => "void",
+        While => "while",
+    }
+    punctuators {
+        Div => "/",
+        Add => "+",
+        Sub => "-",
+        Mul => "*",
+        AddAssign => "+=",
+        SubAssign => "-=",
+        MulAssign => "*=",
+        DivAssign => "/=",
+        Inc => "++",
+        Dec => "--",
+        Mod => "%",
+        BitAnd => "&",
+        BitOr => "|",
+        BitXor => "^",
+        BitNot => "~",
+        Shl => "<<",
+        Shr => ">>",
+        Dot => ".",
+        And => "&&",
+        Or => "||",
+        Not => "!",
+        NotEq => "!=",
+        Equal => "==",
+        Assign => "=",
+        Less => "<",
+        LessEq => "<=",
+        Greater => ">",
+        GreaterEq => ">=",
+        Comma => ",",
+        Colon => ":",
+        Semicolon => ";",
+        Lpar => "(",
+        Rpar => ")",
+        Lbracket => "[",
+        Rbracket => "]",
+        Lacc => "{",
+        Racc => "}",
+    }
+}
+
+/// For every byte, a bit set of the `PUNCTUATORS` starting with that byte
+const PUNCT_FIRST: [u64; 256] = punct_first();
+const _: () = assert!(PUNCTUATORS.len() <= 64);
+
+const fn punct_first() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < PUNCTUATORS.len() {
+        let b = PUNCTUATORS[i].0.as_bytes()[0];
+        table[b as usize] |= 1 << i;
+        i += 1;
+    }
+    table
 }
-impl TokenType {
-    pub fn get_id(&self) -> Option<String> {
-        if let TokenType::Id(id) = self {
-            return Some(String::from(id));
+
+/// The value carried by identifiers and constants
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TokenValue<'a> {
+    None,
+    Id(Name),
+    Real(f32),
+    Int(isize),
+    Char(char),
+    /// The text between the quotes, with its escape sequences not yet replaced
+    Str(&'a str),
+}
+impl<'a> TokenValue<'a> {
+    pub fn get_id(&self) -> Option<Name> {
+        if let TokenValue::Id(id) = self {
+            return Some(*id);
         }
-        //String::from("")
         None
     }
     pub fn get_char(&self) -> Option<char> {
-        if let TokenType::CtChar(s) = self {
+        if let TokenValue::Char(s) = self {
             return Some(*s);
         }
         None
     }
     pub fn get_int(&self) -> Option<isize> {
-        if let TokenType::CtInt(i) = self {
+        if let TokenValue::Int(i) = self {
             return Some(*i);
         }
         None
     }
     pub fn get_double(&self) -> Option<f32> {
-        if let TokenType::CtReal(d) = self {
+        if let TokenValue::Real(d) = self {
             return Some(*d);
         }
         None
     }
     pub fn get_string(&self) -> Option<String> {
-        if let TokenType::CtString(s) = self {
-            return Some(s.to_string());
+        if let TokenValue::Str(s) = self {
+            return Some(unescape(s));
         }
         None
     }
+}
 
-    pub fn discriminant_value(&self) -> u8 {
-        // Safety: https://rust-lang.github.io/rfcs/2363-arbitrary-enum-discriminant.html
-        unsafe { *(self as *const Self as *const u8) }
+/// Returns the character written after `\` in a char or string constant
+fn escape(c: u8) -> Option<char> {
+    match c {
+        b'a' => Some('\x07'),
+        b'b' => Some('\x08'),
+        b't' => Some('\t'),
+        b'n' => Some('\n'),
+        b'v' => Some('\x0B'),
+        b'f' => Some('\x0C'),
+        b'r' => Some('\r'),
+        b'0' => Some('\0'),
+        b'?' | b'"' | b'\'' | b'\\' => Some(c as char),
+        _ => None,
+    }
+}
+
+/// Replaces the escape sequences of a string constant. The lexer already checked them
+pub fn unescape(raw: &str) -> String {
+    let mut result = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(e) = chars.next() {
+                result.push(escape(e as u8).unwrap_or(e));
+            }
+        } else {
+            result.push(c);
+        }
     }
+    result
 }
 
-#[derive(Debug, Clone)]
-pub struct Token {
-    pub token_type: TokenType,
+/// A token. It is `Copy` and borrows its file name and string constants from the source
+#[derive(Debug, Copy, Clone)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub value: TokenValue<'a>,
     pub line: usize,
+    /// Column of the first byte of the token, from 1. With `len` it gives the span of the token
+    pub col: u32,
+    /// Length of the token in bytes
+    pub len: u32,
+    /// The file the token comes from. Empty if the source was not read from a file
+    pub file: &'a str,
+}
+impl<'a> Token<'a> {
+    pub fn new(kind: TokenKind, value: TokenValue<'a>, line: usize, file: &'a str) -> Self {
+        Self {
+            kind,
+            value,
+            line,
+            col: 0,
+            len: 0,
+            file,
+        }
+    }
+    /// Location of the token used in error messages: `file:line` or `line N`
+    pub fn location(&self) -> String {
+        if self.file.is_empty() {
+            format!("line {}", self.line)
+        } else {
+            format!("{}:{}", self.file, self.line)
+        }
+    }
 }
 
-/// Lexer struct
-pub struct Lexer {
-    /// Give text, text_idx and current_line
-    pub text: Vec<char>,
-    pub text_idx: usize,
+/// Lexer struct. Scans the bytes of the source, the tokens borrow from it
+pub struct Lexer<'a> {
+    text: &'a str,
+    src: &'a [u8],
+    pos: usize,
+    /// Position where the current line starts, used for the columns
+    line_start: usize,
     pub current_line: usize,
+    /// File name given to every token
+    pub file: &'a str,
 }
 
-impl Lexer {
-    /// Reads a file and returns a Lexer with the text being the contents of that file
+impl<'a> Lexer<'a> {
+    /// Returns a Lexer over `text`, read from `file`
     /// # Arguments
-    /// * `filename` - A string slice that is the path of the file to be read
-    pub fn from_file(filename: &'static str) -> Self {
-        let contents = fs::read_to_string(filename).expect("Something went wrong reading the file");
-        let mut chars = contents.chars().collect::<Vec<char>>();
-        chars.push('\0');
-
-        Lexer {
-            text: chars,
-            text_idx: 0,
-            current_line: 1,
-        }
+    /// * `text` - The content to be analysed
+    /// * `file` - The file name given to the tokens, empty if there is none
+    pub fn new(text: &'a str, file: &'a str) -> Self {
+        Lexer::at_line(text, file, 1)
     }
-    /// Returns a Lexer from the contents of the string given
+    /// Returns a Lexer from a piece of a file, used by the preprocessor
     /// # Arguments
-    /// * `content` - A string with the content to be analysed
-    pub fn from_string(content: String) -> Lexer {
-        let mut chars = content.chars().collect::<Vec<char>>();
-        chars.push('\0');
-
+    /// * `line` - The line in `file` where `text` starts
+    pub fn at_line(text: &'a str, file: &'a str, line: usize) -> Self {
         Lexer {
-            text: chars,
-            text_idx: 0,
-            current_line: 1,
+            text,
+            src: text.as_bytes(),
+            pos: 0,
+            line_start: 0,
+            current_line: line,
+            file,
         }
     }
 
-    /// Returns a vector of `Token`s  
-    pub fn get_tokens(&mut self) -> Vec<Token> {
-        let mut token_vec: Vec<Token> = Vec::new();
+    /// Returns a vector of `Token`s, ending with `TokenKind::End`
+    pub fn get_tokens(&mut self) -> Vec<Token<'a>> {
+        // Tokens are about 4 bytes of source each, reserve so the vector grows rarely
+        let mut token_vec: Vec<Token<'a>> = Vec::with_capacity(self.src.len() / 4 + 1);
         for t in self {
-            token_vec.push(t.clone());
-            match t.token_type {
-                TokenType::End => break,
-                _ => continue,
+            token_vec.push(t);
+            if t.kind == TokenKind::End {
+                break;
             }
         }
         token_vec
     }
-}
 
-impl Iterator for Lexer {
-    type Item = Token;
-    fn next(&mut self) -> Option<Self::Item> {
-        // consumes an atom and returns the code of the atom
+    fn peek(&self, offset: usize) -> u8 {
+        *self.src.get(self.pos + offset).unwrap_or(&0)
+    }
+    fn skip_digits(&mut self, is_digit: fn(&u8) -> bool) -> usize {
+        let start = self.pos;
+        while self.pos < self.src.len() && is_digit(&self.src[self.pos]) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+    fn token(&self, kind: TokenKind, value: TokenValue<'a>, line: usize) -> Token<'a> {
+        Token::new(kind, value, line, self.file)
+    }
+    fn error(&self, line: usize) -> Token<'a> {
+        self.token(TokenKind::Error, TokenValue::None, line)
+    }
 
-        let mut state = 0;
-        let mut token_string = String::new();
-        let mut token_char: char = 0 as char;
-        let text = &self.text;
-        let text_idx = &mut self.text_idx;
-        let current_line = &mut self.current_line;
-        loop {
-            // Infinite loop because we don't know the char length of a token
-            // We stop only when we reach a final state
-            // If the final state is decided after we consume a character from the next token we decrement *text_idx
-            let c = text[*text_idx];
-            *text_idx += 1;
-            // println!(
-            //     "state: {}, i: {}, character: {:?}, token_string: {:?}",
-            //     state, text_idx, c, token_string
-            // );
-            match state {
-                // Start
-                0 => match c {
-                    // Id
-                    'a'..='z' | 'A'..='Z' | '_' => {
-                        state = 30;
-                        token_string.push(c);
+    /// Skips spaces and comments. Returns the line, position and line start
+    /// of an unterminated comment as error
+    fn skip_blanks(&mut self) -> Result<(), (usize, usize, usize)> {
+        while self.pos < self.src.len() {
+            match self.src[self.pos] {
+                b' ' | b'\t' | b'\r' => self.pos += 1,
+                b'\n' => {
+                    self.current_line += 1;
+                    self.pos += 1;
+                    self.line_start = self.pos;
+                }
+                b'/' if self.peek(1) == b'/' => {
+                    while self.pos < self.src.len() && self.src[self.pos] != b'\n' {
+                        self.pos += 1;
                     }
-                    // End
-                    '\0' => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::End,
-                            line: *current_line,
-                        });
+                }
+                b'/' if self.peek(1) == b'*' => {
+                    let comment_start = (self.current_line, self.pos, self.line_start);
+                    self.pos += 2;
+                    loop {
+                        match self.src.get(self.pos) {
+                            None => return Err(comment_start),
+                            Some(b'*') if self.peek(1) == b'/' => {
+                                self.pos += 2;
+                                break;
+                            }
+                            Some(b'\n') => {
+                                self.current_line += 1;
+                                self.line_start = self.pos + 1;
+                            }
+                            _ => {}
+                        }
+                        self.pos += 1;
                     }
+                }
+                _ => break,
+            }
+        }
+        Ok(())
+    }
 
-                    // Operators and delimitators
-                    '+' => {
-                        //state = 37;
-                        return Some(Token {
-                            token_type: TokenType::Add,
-                            line: *current_line,
-                        });
-                    }
-                    '-' => {
-                        //state = 38;
-                        return Some(Token {
-                            token_type: TokenType::Sub,
-                            line: *current_line,
-                        });
-                    }
-                    '*' => {
-                        //state = 39;
-                        return Some(Token {
-                            token_type: TokenType::Mul,
-                            line: *current_line,
-                        });
-                    }
-                    '.' => {
-                        //state = 40;
-                        return Some(Token {
-                            token_type: TokenType::Dot,
-                            line: *current_line,
-                        });
-                    }
-                    ',' => {
-                        //state = 40;
-                        return Some(Token {
-                            token_type: TokenType::Comma,
-                            line: *current_line,
-                        });
-                    }
-                    ';' => {
-                        //state = 40;
-                        return Some(Token {
-                            token_type: TokenType::Semicolon,
-                            line: *current_line,
-                        });
-                    }
-                    '(' => {
-                        //state = 40;
-                        return Some(Token {
-                            token_type: TokenType::Lpar,
-                            line: *current_line,
-                        });
-                    }
-                    ')' => {
-                        //state = 40;
-                        return Some(Token {
-                            token_type: TokenType::Rpar,
-                            line: *current_line,
-                        });
-                    }
-                    '[' => {
-                        //state = 40;
-                        return Some(Token {
-                            token_type: TokenType::Lbracket,
-                            line: *current_line,
-                        });
-                    }
-                    ']' => {
-                        //state = 40;
-                        return Some(Token {
-                            token_type: TokenType::Rbracket,
-                            line: *current_line,
-                        });
-                    }
-                    '{' => {
-                        //state = 40;
-                        return Some(Token {
-                            token_type: TokenType::Lacc,
-                            line: *current_line,
-                        });
-                    }
-                    '}' => {
-                        //state = 40;
-                        return Some(Token {
-                            token_type: TokenType::Racc,
-                            line: *current_line,
-                        });
-                    }
+    /// Decimal, octal and hexadecimal integers and reals with an optional exponent
+    fn number(&mut self, line: usize) -> Token<'a> {
+        let start = self.pos;
+        if self.src[start] == b'0' && self.peek(1) == b'x' {
+            self.pos += 2;
+            if self.skip_digits(u8::is_ascii_hexdigit) == 0 {
+                return self.error(line);
+            }
+            return match isize::from_str_radix(&self.text[start + 2..self.pos], 16) {
+                Ok(i) => self.token(TokenKind::CtInt, TokenValue::Int(i), line),
+                Err(_) => self.error(line),
+            };
+        }
+        self.skip_digits(u8::is_ascii_digit);
+        let mut real = false;
+        if self.peek(0) == b'.' {
+            self.pos += 1;
+            if self.skip_digits(u8::is_ascii_digit) == 0 {
+                return self.error(line);
+            }
+            real = true;
+        }
+        if matches!(self.peek(0), b'e' | b'E') {
+            self.pos += 1;
+            if matches!(self.peek(0), b'+' | b'-') {
+                self.pos += 1;
+            }
+            if self.skip_digits(u8::is_ascii_digit) == 0 {
+                return self.error(line);
+            }
+            real = true;
+        }
+        let text = &self.text[start..self.pos];
+        if real {
+            return match text.parse::<f32>() {
+                Ok(d) => self.token(TokenKind::CtReal, TokenValue::Real(d), line),
+                Err(_) => self.error(line),
+            };
+        }
+        let int_value = if text.len() > 1 && text.starts_with('0') {
+            isize::from_str_radix(&text[1..], 8)
+        } else {
+            text.parse::<isize>()
+        };
+        match int_value {
+            Ok(i) => self.token(TokenKind::CtInt, TokenValue::Int(i), line),
+            Err(_) => self.error(line),
+        }
+    }
 
-                    '&' => {
-                        state = 15;
-                    }
-                    '|' => {
-                        state = 16;
-                    }
-                    '!' => {
-                        state = 17;
-                    }
-                    '=' => {
-                        state = 18;
-                    }
-                    '<' => {
-                        state = 19;
-                    }
-                    '>' => {
-                        state = 20;
-                    }
+    /// 'c' or '\e'
+    fn char_constant(&mut self, line: usize) -> Token<'a> {
+        self.pos += 1;
+        let c = match self.peek(0) {
+            b'\\' => match escape(self.peek(1)) {
+                Some(c) => {
+                    self.pos += 2;
+                    c
+                }
+                None => {
+                    self.pos += 1;
+                    return self.error(line);
+                }
+            },
+            b'\'' | b'\n' => return self.error(line),
+            _ => match self.text[self.pos..].chars().next() {
+                Some(c) => {
+                    self.pos += c.len_utf8();
+                    c
+                }
+                None => return self.error(line),
+            },
+        };
+        if self.peek(0) != b'\'' {
+            return self.error(line);
+        }
+        self.pos += 1;
+        self.token(TokenKind::CtChar, TokenValue::Char(c), line)
+    }
 
-                    // Spaces, Comments, etc
-                    ' ' | '\r' | '\n' | '\t' => {
-                        state = 0;
-                        if c == '\n' {
-                            *current_line += 1;
-                        }
-                    }
-                    '/' => {
-                        state = 12;
-                    }
-                    '0' => {
-                        state = 2;
-                        token_string.push(c);
-                    }
-                    '1'..='9' => {
-                        state = 1;
-                        token_string.push(c);
-                    }
-                    // CT_Char
-                    '\'' => {
-                        state = 21;
-                    }
-                    // CT_String
-                    '\"' => {
-                        state = 25;
-                    }
+    /// "text", which must end on the same line
+    fn string_constant(&mut self, line: usize) -> Token<'a> {
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            match self.src.get(self.pos) {
+                None | Some(b'\n') => return self.error(line),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    if escape(self.peek(1)).is_none() {
+                        self.pos += 1;
+                        return self.error(line);
+                    }
+                    self.pos += 2;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        self.pos += 1;
+        let raw = &self.text[start..self.pos - 1];
+        self.token(TokenKind::CtString, TokenValue::Str(raw), line)
+    }
 
-                    _ => {
-                        *text_idx -= 1;
-                        println!("Not implemented yet");
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                // Operators and delimitators
-                15 => match c {
-                    '&' => {
-                        //state = 37;
-                        return Some(Token {
-                            token_type: TokenType::And,
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                16 => match c {
-                    '|' => {
-                        //state = 37;
-                        return Some(Token {
-                            token_type: TokenType::Or,
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                17 => match c {
-                    '=' => {
-                        //state = 37;
-                        return Some(Token {
-                            token_type: TokenType::NotEq,
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Not,
-                            line: *current_line,
-                        });
-                    }
-                },
-                18 => match c {
-                    '=' => {
-                        //state = 37;
-                        return Some(Token {
-                            token_type: TokenType::Equal,
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Assign,
-                            line: *current_line,
-                        });
-                    }
-                },
-                19 => match c {
-                    '=' => {
-                        //state = 37;
-                        return Some(Token {
-                            token_type: TokenType::LessEq,
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Less,
-                            line: *current_line,
-                        });
-                    }
-                },
-                20 => match c {
-                    '=' => {
-                        //state = 37;
-                        return Some(Token {
-                            token_type: TokenType::GreaterEq,
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Greater,
-                            line: *current_line,
-                        });
-                    }
-                },
-                // Id
-                30 => match c {
-                    'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => {
-                        state = 30;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        // Check for keyword
-                        match token_string.as_str() {
-                            "break" => {
-                                return Some(Token {
-                                    token_type: TokenType::Break,
-                                    line: *current_line,
-                                })
-                            }
-                            "char" => {
-                                return Some(Token {
-                                    token_type: TokenType::Char,
-                                    line: *current_line,
-                                })
-                            }
-                            "double" => {
-                                return Some(Token {
-                                    token_type: TokenType::Double,
-                                    line: *current_line,
-                                })
-                            }
-                            "else" => {
-                                return Some(Token {
-                                    token_type: TokenType::Else,
-                                    line: *current_line,
-                                })
-                            }
-                            "for" => {
-                                return Some(Token {
-                                    token_type: TokenType::For,
-                                    line: *current_line,
-                                })
-                            }
-                            "if" => {
-                                return Some(Token {
-                                    token_type: TokenType::If,
-                                    line: *current_line,
-                                })
-                            }
-                            "int" => {
-                                return Some(Token {
-                                    token_type: TokenType::Int,
-                                    line: *current_line,
-                                })
-                            }
-                            "return" => {
-                                return Some(Token {
-                                    token_type: TokenType::Return,
-                                    line: *current_line,
-                                })
-                            }
-                            "struct" => {
-                                return Some(Token {
-                                    token_type: TokenType::Struct,
-                                    line: *current_line,
-                                })
-                            }
-                            "void" => {
-                                return Some(Token {
-                                    token_type: TokenType::Void,
-                                    line: *current_line,
-                                })
-                            }
-                            "while" => {
-                                return Some(Token {
-                                    token_type: TokenType::While,
-                                    line: *current_line,
-                                })
-                            }
-                            _ => {
-                                return Some(Token {
-                                    token_type: TokenType::Id(token_string),
-                                    line: *current_line,
-                                })
-                            }
-                        }
-                        //state = 35;
-                    }
-                },
-                // Comments
-                12 => match c {
-                    '*' => {
-                        state = 13;
-                    }
-                    '/' => {
-                        state = 29;
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Div,
-                            line: *current_line,
-                        });
-                    }
-                },
-                13 => match c {
-                    '*' => {
-                        state = 14;
-                    }
-                    '\n' => {
-                        // anything else stays in state 13
-                        *current_line += 1;
-                    }
-                    _ => {
-                        // anything else stays in state 13
-                    }
-                },
-                14 => match c {
-                    '*' => {
-                        state = 14;
-                    }
-                    '/' => {
-                        state = 0;
-                    }
-                    '\n' => {
-                        // anything else stays in state 13
-                        state = 13;
-                        *current_line += 1;
-                    }
-                    _ => {
-                        // anything except `*` or `/` goes in state 13
-                        state = 13;
-                    }
-                },
-                29 => match c {
-                    '\n' | '\r' | '\0' => {
-                        state = 0;
-                    }
-                    _ => {
-                        // anything else stays in state 29
-                    }
-                },
-                //CT_INT
-                1 => match c {
-                    '0'..='9' => {
-                        // state doesnt change
-                        token_string.push(c);
-                    }
-                    '.' => {
-                        state = 7;
-                        token_string.push(c);
-                    }
-                    'e' | 'E' => {
-                        state = 9;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        let int_value = isize::from_str_radix(&token_string, 10).unwrap_or(-1);
-                        return Some(Token {
-                            token_type: TokenType::CtInt(int_value),
-                            line: *current_line,
-                        });
-                    }
-                },
-                2 => match c {
-                    // Hex
-                    'x' => {
-                        state = 4;
-                        token_string.push(c);
-                    }
-                    // Octal
-                    '0'..='7' => {
-                        state = 3;
-                        token_string.push(c);
-                    }
-                    '8' | '9' => {
-                        state = 6;
-                        token_string.push(c);
-                    }
-                    'e' | 'E' => {
-                        state = 9;
-                        token_string.push(c);
-                    }
-                    '.' => {
-                        state = 7;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::CtInt(0),
-                            line: *current_line,
-                        });
-                    }
-                },
-                4 => match c {
-                    'a'..='f' | 'A'..='F' | '0'..='9' => {
-                        state = 5;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                5 => match c {
-                    'a'..='f' | 'A'..='F' | '0'..='9' => {
-                        state = 5; // more numbers
-                        token_string.push(c);
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        //println!("hex string {}", token_string);
-                        let int_value = isize::from_str_radix(&token_string[2..], 16).unwrap_or(0);
-                        return Some(Token {
-                            token_type: TokenType::CtInt(int_value),
-                            line: *current_line,
-                        });
-                    }
-                },
-                6 => match c {
-                    '0'..='9' => {
-                        token_string.push(c);
-                    }
-                    '.' => {
-                        state = 7;
-                        token_string.push(c);
-                    }
-                    'e' | 'E' => {
-                        state = 9;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                // Octal
-                3 => match c {
-                    '0'..='7' => {
-                        token_string.push(c);
-                    }
-                    '8' | '9' => {
-                        state = 6;
-                        token_string.push(c);
-                    }
-                    'e' | 'E' => {
-                        state = 9;
-                        token_string.push(c);
-                    }
-                    '.' => {
-                        state = 7;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        let int_value = isize::from_str_radix(&token_string[1..], 8).unwrap_or(0);
-                        return Some(Token {
-                            token_type: TokenType::CtInt(int_value),
-                            line: *current_line,
-                        });
-                    }
-                },
+    /// The longest operator or delimiter at the current position
+    fn punctuator(&mut self, line: usize) -> Token<'a> {
+        let rest = &self.src[self.pos..];
+        let mut candidates = PUNCT_FIRST[rest[0] as usize];
+        let mut longest: Option<(usize, TokenKind)> = None;
+        while candidates != 0 {
+            let (text, kind) = PUNCTUATORS[candidates.trailing_zeros() as usize];
+            candidates &= candidates - 1;
+            if rest.starts_with(text.as_bytes()) && longest.is_none_or(|(len, _)| text.len() > len)
+            {
+                longest = Some((text.len(), kind));
+            }
+        }
+        match longest {
+            Some((len, kind)) => {
+                self.pos += len;
+                self.token(kind, TokenValue::None, line)
+            }
+            None => {
+                // Skip the unknown character so the lexer does not get stuck on it
+                self.pos += self.text[self.pos..]
+                    .chars()
+                    .next()
+                    .map_or(1, char::len_utf8);
+                self.error(line)
+            }
+        }
+    }
+}
 
-                //CT_REAL
-                7 => match c {
-                    '0'..='9' => {
-                        state = 8;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                8 => match c {
-                    '0'..='9' => {
-                        state = 8;
-                        token_string.push(c);
-                    }
-                    'e' | 'E' => {
-                        state = 9;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        let float_value = token_string.parse::<f32>().unwrap_or(0.);
-                        return Some(Token {
-                            token_type: TokenType::CtReal(float_value),
-                            line: *current_line,
-                        });
-                    }
-                },
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+    /// Returns the next token. At the end of the text it keeps returning `TokenKind::End`
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut token, start, line_start) = match self.skip_blanks() {
+            // Unterminated comment, reported where it starts
+            Err((line, start, line_start)) => (self.error(line), start, line_start),
+            Ok(()) => {
+                let (line, start, line_start) = (self.current_line, self.pos, self.line_start);
+                let token = match self.src.get(self.pos) {
+                    Some(c) => self.scan(*c, line),
+                    None => self.token(TokenKind::End, TokenValue::None, line),
+                };
+                (token, start, line_start)
+            }
+        };
+        token.col = (start - line_start + 1) as u32;
+        token.len = (self.pos - start) as u32;
+        Some(token)
+    }
+}
 
-                9 => match c {
-                    '+' | '-' => {
-                        state = 10;
-                        token_string.push(c);
-                    }
-                    '0'..='9' => {
-                        state = 11;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                10 => match c {
-                    '0'..='9' => {
-                        state = 11;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                11 => match c {
-                    '0'..='9' => {
-                        state = 11;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        let float_value = token_string.parse::<f32>().unwrap_or(0.);
-                        return Some(Token {
-                            token_type: TokenType::CtReal(float_value),
-                            line: *current_line,
-                        });
-                    }
-                },
-                //Ct_Char
-                21 => match c {
-                    '\\' => state = 22,
-                    _ => {
-                        state = 24;
-                        token_char = c;
-                    }
-                },
-                22 => match c {
-                    'a' => {
-                        state = 23;
-                        token_char = '\x07';
-                    }
-                    'b' => {
-                        state = 23;
-                        token_char = '\x08';
-                    }
-                    't' => {
-                        state = 23;
-                        token_char = '\x09';
-                    }
-                    'n' => {
-                        state = 23;
-                        token_char = '\x0A';
-                    }
-                    'v' => {
-                        state = 23;
-                        token_char = '\x0B';
-                    }
-                    'f' => {
-                        state = 23;
-                        token_char = '\x0C';
-                    }
-                    'r' => {
-                        state = 23;
-                        token_char = '\x0D';
-                    }
-                    '0' => {
-                        state = 23;
-                        token_char = '\0';
-                    }
-                    '?' | '\"' | '\'' | '\\' => {
-                        state = 23;
-                        token_char = c;
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                23 => match c {
-                    '\'' => {
-                        return Some(Token {
-                            token_type: TokenType::CtChar(token_char),
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                24 => match c {
-                    '\'' => {
-                        return Some(Token {
-                            token_type: TokenType::CtChar(token_char),
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        *text_idx -= 1;
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                // Ct_String
-                25 => match c {
-                    '\\' => {
-                        state = 26;
-                    }
-                    '\"' => {
-                        return Some(Token {
-                            token_type: TokenType::CtString(String::from("")),
-                            line: *current_line,
-                        });
-                    }
-                    '\n' => {
-                        //state = 28;
-                        //token_string.push(c);
-                        *current_line += 1;
-                        //println!("No multiline string");
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        state = 28;
-                        token_string.push(c);
-                    }
-                },
-                26 => match c {
-                    'a' => {
-                        state = 27;
-                        token_string.push('\x07');
-                    }
-                    'b' => {
-                        state = 27;
-                        token_string.push('\x08');
-                    }
-                    't' => {
-                        state = 27;
-                        token_string.push('\x09');
-                    }
-                    'n' => {
-                        state = 27;
-                        token_string.push('\x0A');
-                    }
-                    'v' => {
-                        state = 27;
-                        token_string.push('\x0B');
-                    }
-                    'f' => {
-                        state = 27;
-                        token_string.push('\x0C');
-                    }
-                    'r' => {
-                        state = 27;
-                        token_string.push('\x0D');
-                    }
-                    '0' => {
-                        state = 27;
-                        token_string.push('\0');
-                    }
-                    '?' | '\"' | '\'' | '\\' => {
-                        state = 27;
-                        token_string.push(c);
-                    }
-                    _ => {
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                },
-                27 => match c {
-                    '\\' => {
-                        state = 26;
-                    }
-                    '\"' => {
-                        return Some(Token {
-                            token_type: TokenType::CtString(token_string),
-                            line: *current_line,
-                        });
-                    }
-                    '\n' => {
-                        // state = 28;
-                        // token_string.push(c);
-                        *current_line += 1;
-                        //println!("No multiline string");
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                    _ => {
-                        token_string.push(c);
-                        state = 28;
-                    }
-                },
-                28 => match c {
-                    '\\' => state = 26,
-                    '\"' => {
-                        return Some(Token {
-                            token_type: TokenType::CtString(token_string),
-                            line: *current_line,
-                        });
-                    }
-                    '\n' => {
-                        // state = 28;
-                        // token_string.push(c);
-                        *current_line += 1;
-                        //println!("No multiline string");
-                        return Some(Token {
-                            token_type: TokenType::Error,
-                            line: *current_line,
-                        });
-                    }
-                    _ => token_string.push(c),
-                },
-                _ => {
-                    println!("Invalid state");
-                    return Some(Token {
-                        token_type: TokenType::Error,
-                        line: *current_line,
-                    });
+impl<'a> Lexer<'a> {
+    /// Scans the token starting with `c`
+    fn scan(&mut self, c: u8, line: usize) -> Token<'a> {
+        match c {
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                let start = self.pos;
+                self.pos += 1;
+                while self.pos < self.src.len()
+                    && (self.src[self.pos].is_ascii_alphanumeric() || self.src[self.pos] == b'_')
+                {
+                    self.pos += 1;
+                }
+                let text = &self.text[start..self.pos];
+                match TokenKind::keyword(text) {
+                    Some(kind) => self.token(kind, TokenValue::None, line),
+                    None => self.token(TokenKind::Id, TokenValue::Id(Name::intern(text)), line),
                 }
             }
+            b'0'..=b'9' => self.number(line),
+            b'\'' => self.char_constant(line),
+            b'"' => self.string_constant(line),
+            _ => self.punctuator(line),
         }
     }
 }
 
 #[cfg(test)]
 pub mod tests {
-    use crate::lexer::Lexer;
+    use crate::intern::Name;
+    use crate::lexer::{Lexer, TokenKind, TokenValue, KEYWORDS};
     use std::fs;
     #[test]
+    fn lexer_kinds() {
+        let mut lexer = Lexer::new("while(a<=b&&!c) x=y!=z; a&b|~c<<1>>2 @ $", "");
+        let kinds = lexer
+            .get_tokens()
+            .iter()
+            .map(|t| t.kind)
+            .collect::<Vec<TokenKind>>();
+        use TokenKind::*;
+        assert_eq!(
+            kinds,
+            vec![
+                While, Lpar, Id, LessEq, Id, And, Not, Id, Rpar, Id, Assign, Id, NotEq, Id,
+                Semicolon, Id, BitAnd, Id, BitOr, BitNot, Id, Shl, CtInt, Shr, CtInt, Error, Error,
+                End
+            ]
+        );
+        for (text, kind) in KEYWORDS {
+            let mut lexer = Lexer::new(text, "");
+            assert_eq!(lexer.get_tokens()[0].kind, *kind);
+        }
+    }
+    #[test]
+    fn lexer_constants() {
+        let mut lexer = Lexer::new(
+            "10 012 0x1F 0 1.5 2e3 09.5e-1 'a' '\\n' \"a\\tb\" 08 1. \"x\n",
+            "",
+        );
+        let tokens = lexer.get_tokens();
+        let values = tokens.iter().map(|t| t.value).collect::<Vec<TokenValue>>();
+        use TokenValue::*;
+        assert_eq!(
+            values[..11],
+            [
+                Int(10),
+                Int(10),
+                Int(31),
+                Int(0),
+                Real(1.5),
+                Real(2000.),
+                Real(0.95),
+                Char('a'),
+                Char('\n'),
+                Str("a\\tb"),
+                None
+            ]
+        );
+        assert_eq!(tokens[9].value.get_string(), Some(String::from("a\tb")));
+        assert_eq!(tokens[10].kind, TokenKind::Error);
+        assert_eq!(tokens[11].kind, TokenKind::Error);
+        // A string cannot span lines
+        assert_eq!(tokens[12].kind, TokenKind::Error);
+        assert_eq!(tokens[13].kind, TokenKind::End);
+    }
+    #[test]
+    fn lexer_lines_and_comments() {
+        let mut lexer = Lexer::new("a // b\n/* c\n */ d\n/* e", "f.c");
+        let tokens = lexer.get_tokens();
+        assert_eq!(tokens[0].value.get_id(), Some(Name::intern("a")));
+        assert_eq!(tokens[1].value.get_id(), Some(Name::intern("d")));
+        assert_eq!(tokens[1].location(), "f.c:3");
+        assert_eq!((tokens[1].col, tokens[1].len), (5, 1));
+        // The unterminated comment is reported where it starts
+        assert_eq!(
+            (tokens[2].kind, tokens[2].line, tokens[2].col),
+            (TokenKind::Error, 4, 1)
+        );
+        assert_eq!(tokens[3].kind, TokenKind::End);
+    }
+    #[test]
     fn lexer_from_file() {
-        let mut lexer = Lexer::from_file("../tests/test_lexer.c");
+        let contents = fs::read_to_string("tests/test_lexer.c")
+            .expect("Something went wrong reading the file");
+        let mut lexer = Lexer::new(&contents, "tests/test_lexer.c");
         let token_vec = lexer.get_tokens();
         for elem in token_vec {
             println!("{:?}", elem);
@@ -985,10 +601,10 @@ pub mod tests {
     #[test]
     fn lexer_from_string() {
         let contents =
-            fs::read_to_string("../tests/8.c").expect("Something went wrong reading the file");
+            fs::read_to_string("tests/8.c").expect("Something went wrong reading the file");
         // Print contents to debug
         println!("{}", contents);
-        let mut lexer = Lexer::from_string(contents);
+        let mut lexer = Lexer::new(&contents, "");
         let token_vec = lexer.get_tokens();
         for elem in token_vec {
             println!("{:?}", elem);
@@ -1,9 +1,16 @@
-#![feature(arbitrary_enum_discriminant)]
-#![feature(linked_list_remove)]
-
 pub mod asdr;
-//pub mod asdr_dom;
-pub mod asdr_dom_type;
-pub mod asdr_mv;
+pub mod ast;
+pub mod cfg;
+pub mod codegen;
+pub mod compile;
+pub mod dataflow;
+pub mod dump;
+pub mod intern;
+pub mod layout;
 pub mod lexer;
+pub mod lint;
+pub mod mv;
+pub mod preprocessor;
+pub mod resolve;
 pub mod symbols;
+pub mod typecheck;
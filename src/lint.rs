@@ -0,0 +1,387 @@
+//! Warnings about code which is valid but probably wrong. They run after type checking
+//! and never stop the compilation
+use crate::ast::*;
+use crate::cfg::Cfg;
+use crate::dataflow::uninitialized;
+use crate::intern::Name;
+use crate::symbols::CtVal;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A kind of warning, which can be allowed from the command line by its name
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedArgument,
+    UnusedGlobal,
+    UnusedStruct,
+    UnusedFunction,
+    UnreachableCode,
+    ConstantCondition,
+    Uninitialized,
+}
+impl Lint {
+    pub const ALL: [Lint; 8] = [
+        Lint::UnusedVariable,
+        Lint::UnusedArgument,
+        Lint::UnusedGlobal,
+        Lint::UnusedStruct,
+        Lint::UnusedFunction,
+        Lint::UnreachableCode,
+        Lint::ConstantCondition,
+        Lint::Uninitialized,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedArgument => "unused-argument",
+            Lint::UnusedGlobal => "unused-global",
+            Lint::UnusedStruct => "unused-struct",
+            Lint::UnusedFunction => "unused-function",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::ConstantCondition => "constant-condition",
+            Lint::Uninitialized => "uninitialized",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.iter().copied().find(|l| l.name() == name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub loc: Loc,
+    pub msg: String,
+}
+/// `Warning in line 3, the variable `x` is never read [unused-variable]`
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Warning in {}, {} [{}]",
+            self.loc,
+            self.msg,
+            self.lint.name()
+        )
+    }
+}
+
+/// Checks the type checked `unit`, the warnings of the `allowed` lints are left out
+pub fn lint(unit: &Unit, allowed: &[Lint]) -> Vec<Warning> {
+    let mut linter = Linter {
+        decls: vec![],
+        scopes: vec![HashMap::new()],
+        structs: HashMap::new(),
+        func: None,
+        warnings: vec![],
+    };
+    linter.lint_unit(unit);
+    let mut warnings = linter.warnings;
+    for d in linter.decls.iter().filter(|d| !d.used) {
+        let msg = match d.lint {
+            Lint::UnusedVariable => format!("the variable `{}` is never read", d.name),
+            Lint::UnusedArgument => format!("the argument `{}` is never read", d.name),
+            Lint::UnusedGlobal => format!("the global variable `{}` is never read", d.name),
+            Lint::UnusedStruct => format!("the struct `{}` is never used", d.name),
+            _ => format!("the function `{}` is never called", d.name),
+        };
+        warnings.push(Warning {
+            lint: d.lint,
+            loc: d.loc,
+            msg,
+        });
+    }
+    warnings.retain(|w| !allowed.contains(&w.lint));
+    warnings
+}
+
+/// A declaration which should be used somewhere
+struct Decl {
+    /// The lint of the warning if it is not used
+    lint: Lint,
+    name: Name,
+    loc: Loc,
+    used: bool,
+}
+
+struct Linter {
+    decls: Vec<Decl>,
+    /// The declarations visible from the current statement, as indexes in `decls`.
+    /// The first scope has the globals and the functions
+    scopes: Vec<HashMap<Name, usize>>,
+    structs: HashMap<Name, usize>,
+    /// The function being checked, its calls to itself do not count
+    func: Option<Name>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    fn declare(&mut self, lint: Lint, name: Name, loc: Loc) -> usize {
+        self.decls.push(Decl {
+            lint,
+            name,
+            loc,
+            used: false,
+        });
+        self.decls.len() - 1
+    }
+    fn declare_var(&mut self, lint: Lint, v: &VarDecl) {
+        self.use_type(&v.ty);
+        if let Some(init) = &v.init {
+            self.read_init(init);
+        }
+        let decl = self.declare(lint, v.name, v.loc);
+        self.scopes.last_mut().unwrap().insert(v.name, decl);
+    }
+    /// Marks the innermost declaration of `name` as used. The external functions
+    /// have no declaration
+    fn use_name(&mut self, name: Name) {
+        if let Some(&decl) = self.scopes.iter().rev().find_map(|s| s.get(&name)) {
+            self.decls[decl].used = true;
+        }
+    }
+    fn use_type(&mut self, ty: &TypeSpec) {
+        if let TypeBase::Struct(name) = ty.base {
+            if let Some(&decl) = self.structs.get(&name) {
+                self.decls[decl].used = true;
+            }
+        }
+    }
+    fn warn(&mut self, lint: Lint, loc: Loc, msg: String) {
+        self.warnings.push(Warning { lint, loc, msg });
+    }
+
+    fn lint_unit(&mut self, unit: &Unit) {
+        for item in &unit.items {
+            match item {
+                Item::Struct(s) => {
+                    s.members.iter().for_each(|m| self.use_type(&m.ty));
+                    let decl = self.declare(Lint::UnusedStruct, s.name, s.loc);
+                    self.structs.insert(s.name, decl);
+                }
+                Item::Var(v) => self.declare_var(Lint::UnusedGlobal, v),
+                Item::Func(f) => self.lint_func(f),
+            }
+        }
+    }
+
+    fn lint_func(&mut self, f: &FuncDecl) {
+        self.use_type(&f.ret);
+        // A prototype and the definition are the same function
+        if !self.scopes[0].contains_key(&f.name) {
+            let decl = self.declare(Lint::UnusedFunction, f.name, f.loc);
+            self.decls[decl].used = f.name.as_str() == "main";
+            self.scopes[0].insert(f.name, decl);
+        }
+        let body = match &f.body {
+            Some(body) => body,
+            None => {
+                f.args.iter().for_each(|a| self.use_type(&a.ty));
+                return;
+            }
+        };
+        self.func = Some(f.name);
+        self.scopes.push(HashMap::new());
+        f.args
+            .iter()
+            .for_each(|a| self.declare_var(Lint::UnusedArgument, a));
+        // The body shares its scope with the arguments
+        match &body.kind {
+            StmKind::Block(stms) => stms.iter().for_each(|s| self.lint_stm(s)),
+            _ => self.lint_stm(body),
+        }
+        self.scopes.pop();
+        let cfg = Cfg::new(f.name, body);
+        self.lint_flow(&cfg);
+        self.warnings.extend(uninitialized(&cfg));
+    }
+
+    /// Warns about the first statement of every piece of code which cannot be reached
+    fn lint_flow(&mut self, cfg: &Cfg) {
+        let reachable = cfg.reachable();
+        let mut has_pred = vec![false; cfg.blocks.len()];
+        for id in 0..cfg.blocks.len() {
+            cfg.successors(id)
+                .into_iter()
+                .for_each(|s| has_pred[s] = true);
+        }
+        for (id, block) in cfg.blocks.iter().enumerate() {
+            match block.loc {
+                Some(loc) if !reachable[id] && !has_pred[id] => {
+                    self.warn(Lint::UnreachableCode, loc, "unreachable statement".into())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn lint_cond(&mut self, cond: &Expr) {
+        self.read(cond);
+        if cond.rv.is_ctval {
+            let value = match &cond.rv.ctval {
+                Some(CtVal::IntChar(i)) => *i != 0,
+                Some(CtVal::Double(d)) => *d != 0.,
+                _ => true,
+            };
+            let msg = format!("the condition is always {}", value);
+            self.warn(Lint::ConstantCondition, cond.loc, msg);
+        }
+    }
+
+    fn lint_stm(&mut self, stm: &Stm) {
+        match &stm.kind {
+            StmKind::Block(stms) => {
+                self.scopes.push(HashMap::new());
+                stms.iter().for_each(|s| self.lint_stm(s));
+                self.scopes.pop();
+            }
+            StmKind::Var(v) => self.declare_var(Lint::UnusedVariable, v),
+            StmKind::If { cond, then, els } => {
+                self.lint_cond(cond);
+                self.lint_stm(then);
+                if let Some(els) = els {
+                    self.lint_stm(els);
+                }
+            }
+            StmKind::While { cond, body } | StmKind::DoWhile { body, cond } => {
+                self.lint_cond(cond);
+                self.lint_stm(body);
+            }
+            StmKind::Switch { value, cases } => {
+                self.read(value);
+                self.scopes.push(HashMap::new());
+                for case in cases {
+                    case.stms.iter().for_each(|s| self.lint_stm(s));
+                }
+                self.scopes.pop();
+            }
+            StmKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.read(init);
+                }
+                if let Some(cond) = cond {
+                    self.lint_cond(cond);
+                }
+                if let Some(step) = step {
+                    self.read(step);
+                }
+                self.lint_stm(body);
+            }
+            StmKind::Return(Some(e)) | StmKind::Expr(e) => self.read(e),
+            StmKind::Break | StmKind::Continue | StmKind::Return(None) | StmKind::Empty => {}
+        }
+    }
+
+    /// An expression whose value is used
+    fn read(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Int(_) | ExprKind::Real(_) | ExprKind::Char(_) | ExprKind::Str(_) => {}
+            ExprKind::Id(name) => self.use_name(*name),
+            ExprKind::Call(name, args) => {
+                if self.func != Some(*name) {
+                    self.use_name(*name);
+                }
+                args.iter().for_each(|a| self.read(a));
+            }
+            // The destination of a compound assignment is also read
+            ExprKind::Index(a, b) | ExprKind::Binary(_, a, b) | ExprKind::OpAssign(_, a, b) => {
+                self.read(a);
+                self.read(b);
+            }
+            ExprKind::Member(a, _)
+            | ExprKind::Unary(_, a)
+            | ExprKind::IncDec { operand: a, .. }
+            | ExprKind::Conv(a) => self.read(a),
+            ExprKind::Cast(ty, a) => {
+                self.use_type(ty);
+                self.read(a);
+            }
+            ExprKind::Assign(dst, src) => {
+                self.write(dst);
+                self.read(src);
+            }
+        }
+    }
+    fn read_init(&mut self, init: &Init) {
+        match init {
+            Init::Expr(e) => self.read(e),
+            Init::List(items, _) => items.iter().for_each(|i| self.read_init(i)),
+        }
+    }
+    /// The destination of an assignment, only the indexes in it are read
+    fn write(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Id(_) => {}
+            ExprKind::Index(array, index) => {
+                self.write(array);
+                self.read(index);
+            }
+            ExprKind::Member(object, _) => self.write(object),
+            _ => self.read(e),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::compile::{compile, CompileOptions, Stage};
+    use crate::lexer::Lexer;
+    use crate::lint::*;
+
+    fn warnings(src: &str, allow: Vec<Lint>) -> Vec<String> {
+        let options = CompileOptions {
+            stage: Stage::Typecheck,
+            allow,
+            ..Default::default()
+        };
+        let c = compile(Lexer::new(src, "").get_tokens(), &options);
+        c.warnings.iter().map(|w| w.to_string()).collect()
+    }
+    #[test]
+    fn lint_unused() {
+        let src = "struct P { int x; };
+            struct Q { struct P p; };
+            int g;
+            int h;
+            int f(int a, int b) { int x; int y[2]; y[a] = 1; x = 2; return f(h, 0); }
+            void main() { put_i(g); }";
+        assert_eq!(
+            warnings(src, vec![]),
+            vec![
+                "Warning in line 2, the struct `Q` is never used [unused-struct]",
+                "Warning in line 5, the function `f` is never called [unused-function]",
+                "Warning in line 5, the argument `b` is never read [unused-argument]",
+                "Warning in line 5, the variable `x` is never read [unused-variable]",
+                "Warning in line 5, the variable `y` is never read [unused-variable]",
+            ]
+        );
+    }
+    #[test]
+    fn lint_flow() {
+        let src = "int f(int x) {
+                while (1) { if (x) break; return 1; x = 2; }
+                if (0) return 3;
+                return 4;
+                put_i(x);
+            }
+            void main() { f(1); }";
+        assert_eq!(
+            warnings(src, vec![]),
+            vec![
+                "Warning in line 2, the condition is always true [constant-condition]",
+                "Warning in line 3, the condition is always false [constant-condition]",
+                "Warning in line 2, unreachable statement [unreachable-code]",
+                "Warning in line 3, unreachable statement [unreachable-code]",
+                "Warning in line 5, unreachable statement [unreachable-code]",
+            ]
+        );
+        let allowed = warnings(src, vec![Lint::ConstantCondition, Lint::UnreachableCode]);
+        assert!(allowed.is_empty());
+    }
+}
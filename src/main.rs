@@ -1,18 +1,16 @@
-mod asdr;
-//mod asdr_dom;
-mod asdr_dom_type;
-mod asdr_mv;
-mod lexer;
-mod mv;
-mod symbols;
 use clap::{App, Arg};
-use std::fs;
 
-//use lexer::get_tokens;
-use asdr_dom_type::SyntaxAnalyser;
-use lexer::Lexer;
+use compiler::cfg;
+use compiler::compile::{compile, CompileOptions, Stage};
+use compiler::dump::{
+    dump_ast, dump_cfg, dump_cfg_dot, dump_layout, dump_symbols, dump_tokens, Format,
+};
+use compiler::lint::Lint;
+use compiler::mv::VirtualMachine;
+use compiler::preprocessor::Preprocessor;
 
 fn main() -> Result<(), &'static str> {
+    let lints: Vec<&str> = Lint::ALL.iter().map(|l| l.name()).collect();
     let matches = App::new("AtomC compiler")
         .version("0.0")
         .author("Dacian Stroia")
@@ -25,21 +23,145 @@ fn main() -> Result<(), &'static str> {
                 .takes_value(true)
                 .required(true), // file is required
         )
+        .arg(
+            Arg::with_name("dump-tokens")
+                .long("dump-tokens")
+                .help("Prints the tokens of the file with their spans"),
+        )
+        .arg(
+            Arg::with_name("dump-ast")
+                .long("dump-ast")
+                .help("Prints the parsed tree"),
+        )
+        .arg(
+            Arg::with_name("dump-symbols")
+                .long("dump-symbols")
+                .help("Prints the symbol tables after the analysis"),
+        )
+        .arg(
+            Arg::with_name("dump-layout")
+                .long("dump-layout")
+                .help("Prints the sizes, alignments and offsets of the types and variables"),
+        )
+        .arg(
+            Arg::with_name("dump-cfg")
+                .long("dump-cfg")
+                .help("Prints the basic blocks of the functions"),
+        )
+        .arg(
+            Arg::with_name("dump-cfg-dot")
+                .long("dump-cfg-dot")
+                .help("Prints the control flow graphs of the functions for Graphviz"),
+        )
+        .arg(
+            Arg::with_name("lib")
+                .long("lib")
+                .help("Compiles a library, which does not need a `main` function"),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .help("Prints every instruction executed by the virtual machine"),
+        )
+        .arg(
+            Arg::with_name("allow")
+                .short("A")
+                .long("allow")
+                .help("Does not report the warnings of a lint")
+                .value_name("LINT")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .possible_values(&lints),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .help("The format of the dumps")
+                .value_name("FORMAT")
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
         .get_matches();
     // get filename
     let filename = matches.value_of("file").expect("Please input a file");
-    // Get contents to a file as a string
-    let contents = fs::read_to_string(filename).expect("Something went wrong reading the file");
-    // Print contents to debug
-    //println!("{}", contents);
+    let format = match matches.value_of("format") {
+        Some("json") => Format::Json,
+        _ => Format::Text,
+    };
+    let library = matches.is_present("lib");
+    let allow: Vec<Lint> = matches
+        .values_of("allow")
+        .into_iter()
+        .flatten()
+        .filter_map(Lint::from_name)
+        .collect();
+    // Run the preprocessor and get the tokens of the file and its includes
+    let mut preprocessor = Preprocessor::new();
+    let source = preprocessor.process_file(filename);
+    let token_vec = source.tokens();
 
-    let mut lexer = Lexer::from_string(contents);
-    let token_vec = lexer.get_tokens();
-    // for elem in token_vec.iter() {
-    //     println!("{:?}", elem);
-    // }
+    let dumps = [
+        "dump-tokens",
+        "dump-ast",
+        "dump-symbols",
+        "dump-layout",
+        "dump-cfg",
+        "dump-cfg-dot",
+    ];
+    if dumps.iter().any(|d| matches.is_present(d)) {
+        if matches.is_present("dump-tokens") {
+            print!("{}", dump_tokens(&token_vec, format));
+        }
+        // Only run the stages the dumps need
+        let stage = if matches.is_present("dump-cfg") || matches.is_present("dump-cfg-dot") {
+            Stage::Typecheck
+        } else if matches.is_present("dump-symbols") || matches.is_present("dump-layout") {
+            Stage::Resolve
+        } else {
+            Stage::Syntax
+        };
+        let options = CompileOptions {
+            stage,
+            allow,
+            library,
+        };
+        let compilation = compile(token_vec.clone(), &options);
+        compilation.warnings.iter().for_each(|w| eprintln!("{}", w));
+        if matches.is_present("dump-ast") {
+            print!("{}", dump_ast(&compilation.unit, format));
+        }
+        if matches.is_present("dump-symbols") {
+            print!("{}", dump_symbols(&compilation.symbol_tables, format));
+        }
+        if matches.is_present("dump-layout") {
+            print!(
+                "{}",
+                dump_layout(
+                    &compilation.symbol_tables,
+                    &compilation.unit,
+                    &compilation.globals
+                )
+            );
+        }
+        if matches.is_present("dump-cfg") {
+            print!("{}", dump_cfg(&cfg::build(&compilation.unit)));
+        }
+        if matches.is_present("dump-cfg-dot") {
+            print!("{}", dump_cfg_dot(&cfg::build(&compilation.unit)));
+        }
+        return Ok(());
+    }
 
-    let mut syntax_analyser: SyntaxAnalyser = SyntaxAnalyser::new(token_vec);
-    syntax_analyser.analyse_syntax();
+    let options = CompileOptions {
+        allow,
+        library,
+        ..Default::default()
+    };
+    let compilation = compile(token_vec, &options);
+    compilation.warnings.iter().for_each(|w| eprintln!("{}", w));
+    let mut vm = VirtualMachine::new();
+    vm.trace = matches.is_present("trace");
+    vm.run(compilation.code.as_ref().unwrap());
     Ok(())
 }
@@ -1,8 +1,8 @@
+use crate::layout::align_to;
 use std::mem::{size_of, transmute};
 use std::ptr::{null, null_mut};
-#[derive(Copy, Clone, Debug)]
-
-enum Opcode {
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Opcode {
     OAddC,
     OAddD,
     OAddI,
@@ -10,6 +10,10 @@ enum Opcode {
     OAndC,
     OAndD,
     OAndI,
+    OBitAndI,
+    OBitNotI,
+    OBitOrI,
+    OBitXorI,
     OCall,
     OCallext,
     OCastCD,
@@ -51,6 +55,7 @@ enum Opcode {
     OLessEqD,
     OLessEqI,
     OLoad,
+    OModI,
     OMulC,
     OMulD,
     OMulI,
@@ -77,35 +82,65 @@ enum Opcode {
     OPushCtD,
     OPushCtI,
     ORet,
+    OShlI,
+    OShrI,
     OStore,
     OSubC,
     OSubD,
     OSubI,
 }
-// struct Instr{
-//     opcode:
-// }
-const STACK_SIZE: usize = 32 * 1024;
+pub const STACK_SIZE: usize = 32 * 1024;
+/// Prints an executed instruction when tracing is on
+macro_rules! trace {
+    ($vm:expr, $($arg:tt)*) => {
+        if $vm.trace {
+            println!($($arg)*);
+        }
+    };
+}
+/// The type of the external functions. They take their arguments from the stack and push their result
+pub type ExtFunc = fn(&mut VirtualMachine);
 #[derive(Debug)]
-struct VirtualMachine {
+pub struct VirtualMachine {
     sp: *mut u8,          // stack pointer
     stack_after: *mut u8, // used for stack limit
-    stack: [u8; STACK_SIZE],
+    stack: Vec<u8>,       // on the heap so `sp` stays valid when the VM is moved
+    /// Prints every executed instruction
+    pub trace: bool,
+    /// Everything printed by the program
+    pub output: String,
+    /// Also print the output of the program to stdout as it runs
+    pub echo: bool,
+}
+impl Default for VirtualMachine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl VirtualMachine {
-    fn new() -> Self {
-        let stack = [0; STACK_SIZE];
+    pub fn new() -> Self {
+        let stack = vec![0; STACK_SIZE];
         let sp = null_mut();
         let stack_after = null_mut();
         let mut mv = Self {
             sp,
             stack_after,
             stack,
+            trace: false,
+            output: String::new(),
+            echo: true,
         };
         mv.sp = mv.stack.as_mut_ptr();
         mv.stack_after = unsafe { mv.sp.add(STACK_SIZE) };
         mv
     }
+    /// Used by the external functions to print
+    pub fn print(&mut self, text: &str) {
+        if self.echo {
+            print!("{}", text);
+        }
+        self.output.push_str(text);
+    }
     fn check_top<T: Sized>(&self) {
         if unsafe { self.sp.add(size_of::<T>()) > self.stack_after } {
             panic!("Out of stack");
@@ -116,94 +151,95 @@ impl VirtualMachine {
             panic!("Not enough stack bytes");
         }
     }
-    fn push<T: Sized>(&mut self, v: T) {
+    pub fn push<T: Sized>(&mut self, v: T) {
         self.check_top::<T>();
         let x = self.sp as *mut T;
         unsafe {
-            std::ptr::write(x, v);
+            std::ptr::write_unaligned(x, v);
         }
         self.sp = unsafe { self.sp.add(size_of::<T>()) };
     }
-    fn pop<T: Sized>(&mut self) -> T {
+    pub fn pop<T: Sized>(&mut self) -> T {
         self.check_bot::<T>();
         self.sp = unsafe { self.sp.sub(size_of::<T>()) };
         let x = self.sp as *mut T;
-        let v = unsafe { std::ptr::read(x) };
-        v
+        unsafe { std::ptr::read_unaligned(x) }
     }
 
-    fn run(&mut self, instr_list: &InstrList) {
-        let (mut ival1, mut ival2) = (0, 0);
-        let (mut cval1, mut cval2) = (0 as char, 0 as char);
-        let (mut dval1, mut dval2) = (0., 0.);
-        let (mut aval1, mut aval2) = (null::<()>(), null::<()>());
+    pub fn run(&mut self, instr_list: &InstrList) {
+        let (mut ival1, mut ival2): (i64, i64);
+        let (mut cval1, mut cval2): (u8, u8);
+        let (mut dval1, mut dval2): (f64, f64);
+        let (mut aval1, mut aval2): (*const (), *const ());
         let stack_after = self.stack_after;
         let mut fp = null_mut();
         let mut ip = instr_list.front;
         loop {
-            print!("{:p} | {}\t", ip, unsafe {
-                self.sp.offset_from(self.stack.as_ptr())
-            });
+            if self.trace {
+                print!("{:p} | {}\t", ip, unsafe {
+                    self.sp.offset_from(self.stack.as_ptr())
+                });
+            }
             let ipi = unsafe { *ip };
             match ipi.opcode {
                 Opcode::OCall => {
                     aval1 = ipi.arg1.unwrap_or_default().get_addr();
-                    println!("CALL\t{:p}", aval1);
+                    trace!(self, "CALL\t{:p}", aval1);
                     self.push(ipi.next);
+                    ip = aval1 as *mut Instr;
                 }
                 Opcode::OCallext => {
                     let faddr = ipi.arg1.unwrap().get_addr();
-                    println!("CALLEXT\t{:p}", faddr);
-                    let f2 = unsafe { transmute::<*const (), fn()>(faddr) };
-                    f2();
+                    trace!(self, "CALLEXT\t{:p}", faddr);
+                    let f2 = unsafe { transmute::<*const (), ExtFunc>(faddr) };
+                    f2(self);
                     ip = ipi.next;
                 }
                 Opcode::OCastID => {
                     ival1 = self.pop();
                     dval1 = ival1 as f64;
-                    println!("CAST_I_D\t({} -> {}", ival1, dval1);
+                    trace!(self, "CAST_I_D\t({} -> {}", ival1, dval1);
                     self.push(dval1);
                     ip = ipi.next;
                 }
                 Opcode::OCastIC => {
                     ival1 = self.pop();
-                    cval1 = std::char::from_u32(ival1 as u32).unwrap();
-                    //cval1 = unsafe { transmute::<i32, char>(ival1) };
-                    println!("CAST_I_C\t{} -> {}", ival1, cval1);
+                    cval1 = ival1 as u8;
+                    trace!(self, "CAST_I_C\t{} -> {}", ival1, cval1);
                     self.push(cval1);
                     ip = ipi.next;
                 }
                 Opcode::OCastCD => {
                     cval1 = self.pop();
-                    dval1 = cval1 as u64 as f64;
-                    println!("CAST_C_D\t{} -> {}", cval1, dval1);
+                    dval1 = cval1 as f64;
+                    trace!(self, "CAST_C_D\t{} -> {}", cval1, dval1);
                     self.push(dval1);
                     ip = ipi.next;
                 }
                 Opcode::OCastCI => {
                     cval1 = self.pop();
                     ival1 = cval1 as i64;
-                    println!("CAST_C_I\t{} -> {}", cval1, ival1);
+                    trace!(self, "CAST_C_I\t{} -> {}", cval1, ival1);
                     self.push(ival1);
                     ip = ipi.next;
                 }
                 Opcode::OCastDC => {
                     dval1 = self.pop();
-                    cval1 = std::char::from_u32(dval1 as u32).unwrap();
-                    println!("CAST_D_C\t{} -> {}", dval1, cval1);
+                    cval1 = dval1 as u8;
+                    trace!(self, "CAST_D_C\t{} -> {}", dval1, cval1);
                     self.push(cval1);
                     ip = ipi.next;
                 }
                 Opcode::OCastDI => {
                     dval1 = self.pop();
                     ival1 = dval1 as i64;
-                    println!("CAST_D_I\t{} -> {}", dval1, ival1);
+                    trace!(self, "CAST_D_I\t{} -> {}", dval1, ival1);
                     self.push(ival1);
                     ip = ipi.next;
                 }
                 Opcode::ODrop => {
                     ival1 = ipi.arg1.unwrap().get_int();
-                    println!("DROP\t{}", ival1);
+                    trace!(self, "DROP\t{}", ival1);
                     if unsafe { self.sp.sub(ival1 as usize) } < self.stack.as_mut_ptr() {
                         panic!("Not enough stack bytes");
                     }
@@ -212,174 +248,255 @@ impl VirtualMachine {
                 }
                 Opcode::OEnter => {
                     ival1 = ipi.arg1.unwrap().get_int();
-                    println!("ENTER\t{}", ival1);
+                    trace!(self, "ENTER\t{}", ival1);
                     self.push(fp);
                     fp = self.sp;
                     self.sp = unsafe { self.sp.add(ival1 as usize) };
                     ip = ipi.next
                 }
+                Opcode::OEqI => {
+                    ival1 = self.pop();
+                    ival2 = self.pop();
+                    trace!(self, "EQ_I\t{} == {} -> {}", ival2, ival1, ival2 == ival1);
+                    self.push((ival2 == ival1) as i64);
+                    ip = ipi.next;
+                }
                 Opcode::OEqD => {
                     dval1 = self.pop();
                     dval2 = self.pop();
-                    println!("EQ_D\t{} == {} -> {}", dval1, dval1, dval1 == dval2);
-                    self.push((dval1 == dval2) as u64);
-                    ip = ipi.next
+                    trace!(self, "EQ_D\t{} == {} -> {}", dval2, dval1, dval2 == dval1);
+                    self.push((dval2 == dval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OEqC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!("EQ_D\t{} == {} -> {}", cval1, cval1, cval1 == cval2);
-                    self.push((cval1 == cval2) as u64);
-                    ip = ipi.next
+                    trace!(self, "EQ_C\t{} == {} -> {}", cval2, cval1, cval2 == cval1);
+                    self.push((cval2 == cval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OEqA => {
                     aval1 = self.pop();
                     aval2 = self.pop();
-                    println!("EQ_D\t{:p} == {:p} -> {}", aval1, aval1, aval1 == aval2);
-                    self.push((aval1 == aval2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "EQ_A\t{:p} == {:p} -> {}",
+                        aval2,
+                        aval1,
+                        aval2 == aval1
+                    );
+                    self.push((aval2 == aval1) as i64);
+                    ip = ipi.next;
                 }
-                Opcode::OEqI => {
+                Opcode::ONotEqI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!("EQ_D\t{} == {} -> {}", ival1, ival2, ival1 == ival2);
-                    self.push((ival1 == ival2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "NOTEQ_I\t{} != {} -> {}",
+                        ival2,
+                        ival1,
+                        ival2 != ival1
+                    );
+                    self.push((ival2 != ival1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::ONotEqD => {
                     dval1 = self.pop();
                     dval2 = self.pop();
-                    println!("NOTEQ_D\t{} != {} -> {}", dval1, dval2, dval1 != dval2);
-                    self.push((dval1 != dval2) as u64);
-                    ip = ipi.next
-                }
-                Opcode::ONotEqA => {
-                    aval1 = self.pop();
-                    aval2 = self.pop();
-                    println!("NOTEQ_A\t{:p} != {:p} -> {}", aval1, aval2, aval1 != aval2);
-                    self.push((aval1 != aval2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "NOTEQ_D\t{} != {} -> {}",
+                        dval2,
+                        dval1,
+                        dval2 != dval1
+                    );
+                    self.push((dval2 != dval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::ONotEqC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!("NOTEQ_C\t{} != {} -> {}", cval1, cval2, cval1 != cval2);
-                    self.push((cval1 != cval2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "NOTEQ_C\t{} != {} -> {}",
+                        cval2,
+                        cval1,
+                        cval2 != cval1
+                    );
+                    self.push((cval2 != cval1) as i64);
+                    ip = ipi.next;
                 }
-                Opcode::ONotEqI => {
+                Opcode::ONotEqA => {
+                    aval1 = self.pop();
+                    aval2 = self.pop();
+                    trace!(
+                        self,
+                        "NOTEQ_A\t{:p} != {:p} -> {}",
+                        aval2,
+                        aval1,
+                        aval2 != aval1
+                    );
+                    self.push((aval2 != aval1) as i64);
+                    ip = ipi.next;
+                }
+                Opcode::OGreaterI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!("NOTEQ_I\t{} != {} -> {}", ival1, ival2, ival1 != ival2);
-                    self.push((ival1 != ival2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "GREATER_I\t{} > {} -> {}",
+                        ival2,
+                        ival1,
+                        ival2 > ival1
+                    );
+                    self.push((ival2 > ival1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OGreaterD => {
                     dval1 = self.pop();
                     dval2 = self.pop();
-                    println!("GREATER_D\t{} > {} -> {}", dval1, dval2, dval1 > dval2);
-                    self.push((dval1 > dval2) as u64);
-                    ip = ipi.next
-                }
-                Opcode::OGreaterI => {
-                    ival1 = self.pop();
-                    ival2 = self.pop();
-                    println!("GREATER_I\t{} > {} -> {}", ival1, ival2, ival1 > ival2);
-                    self.push((ival1 > ival2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "GREATER_D\t{} > {} -> {}",
+                        dval2,
+                        dval1,
+                        dval2 > dval1
+                    );
+                    self.push((dval2 > dval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OGreaterC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!("GREATER_C\t{} > {} -> {}", cval1, cval2, cval1 > cval2);
-                    self.push((cval1 > cval2) as u64);
-                    ip = ipi.next
-                }
-                Opcode::OLessD => {
-                    dval1 = self.pop();
-                    dval2 = self.pop();
-                    println!("LESS_D\t{} < {} -> {}", dval1, dval2, dval1 < dval2);
-                    self.push((dval1 < dval2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "GREATER_C\t{} > {} -> {}",
+                        cval2,
+                        cval1,
+                        cval2 > cval1
+                    );
+                    self.push((cval2 > cval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OLessI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!("LESS_I\t{} < {} -> {}", ival1, ival2, ival1 < ival2);
-                    self.push((ival1 < ival2) as u64);
-                    ip = ipi.next
+                    trace!(self, "LESS_I\t{} < {} -> {}", ival2, ival1, ival2 < ival1);
+                    self.push((ival2 < ival1) as i64);
+                    ip = ipi.next;
+                }
+                Opcode::OLessD => {
+                    dval1 = self.pop();
+                    dval2 = self.pop();
+                    trace!(self, "LESS_D\t{} < {} -> {}", dval2, dval1, dval2 < dval1);
+                    self.push((dval2 < dval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OLessC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!("LESS_C\t{} < {} -> {}", cval1, cval2, cval1 < cval2);
-                    self.push((cval1 < cval2) as u64);
-                    ip = ipi.next
-                }
-                Opcode::OGreaterEqD => {
-                    dval1 = self.pop();
-                    dval2 = self.pop();
-                    println!("GREATEREQ_D\t{} >= {} -> {}", dval1, dval2, dval1 >= dval2);
-                    self.push((dval1 >= dval2) as u64);
-                    ip = ipi.next
+                    trace!(self, "LESS_C\t{} < {} -> {}", cval2, cval1, cval2 < cval1);
+                    self.push((cval2 < cval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OGreaterEqI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!("GREATEREQ_I\t{} >= {} -> {}", ival1, ival2, ival1 >= ival2);
-                    self.push((ival1 >= ival2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "GREATEREQ_I\t{} >= {} -> {}",
+                        ival2,
+                        ival1,
+                        ival2 >= ival1
+                    );
+                    self.push((ival2 >= ival1) as i64);
+                    ip = ipi.next;
+                }
+                Opcode::OGreaterEqD => {
+                    dval1 = self.pop();
+                    dval2 = self.pop();
+                    trace!(
+                        self,
+                        "GREATEREQ_D\t{} >= {} -> {}",
+                        dval2,
+                        dval1,
+                        dval2 >= dval1
+                    );
+                    self.push((dval2 >= dval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OGreaterEqC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!("GREATEREQ_C\t{} >= {} -> {}", cval1, cval2, cval1 >= cval2);
-                    self.push((cval1 >= cval2) as u64);
+                    trace!(
+                        self,
+                        "GREATEREQ_C\t{} >= {} -> {}",
+                        cval2,
+                        cval1,
+                        cval2 >= cval1
+                    );
+                    self.push((cval2 >= cval1) as i64);
                     ip = ipi.next;
                 }
-                Opcode::OLessEqD => {
-                    dval1 = self.pop();
-                    dval2 = self.pop();
-                    println!("LESSEQ_D\t{} <= {} -> {}", dval1, dval2, dval1 <= dval2);
-                    self.push((dval1 <= dval2) as u64);
-                    ip = ipi.next
-                }
                 Opcode::OLessEqI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!("LESSEQ_I\t{} <= {} -> {}", ival1, ival2, ival1 <= ival2);
-                    self.push((ival1 > ival2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "LESSEQ_I\t{} <= {} -> {}",
+                        ival2,
+                        ival1,
+                        ival2 <= ival1
+                    );
+                    self.push((ival2 <= ival1) as i64);
+                    ip = ipi.next;
+                }
+                Opcode::OLessEqD => {
+                    dval1 = self.pop();
+                    dval2 = self.pop();
+                    trace!(
+                        self,
+                        "LESSEQ_D\t{} <= {} -> {}",
+                        dval2,
+                        dval1,
+                        dval2 <= dval1
+                    );
+                    self.push((dval2 <= dval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OLessEqC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!("LESSEQ_C\t{} <= {} -> {}", cval1, cval2, cval1 <= cval2);
-                    self.push((cval1 <= cval2) as u64);
-                    ip = ipi.next
+                    trace!(
+                        self,
+                        "LESSEQ_C\t{} <= {} -> {}",
+                        cval2,
+                        cval1,
+                        cval2 <= cval1
+                    );
+                    self.push((cval2 <= cval1) as i64);
+                    ip = ipi.next;
                 }
                 Opcode::OHalt => {
-                    println!("Halt");
+                    trace!(self, "Halt");
                     return;
                 }
                 Opcode::OInsert => {
                     ival1 = ipi.arg1.unwrap().get_int(); // idst
                     ival2 = ipi.arg2.unwrap().get_int(); // nbytes
-                    println!("INSERT\t{}, {}", ival1, ival2);
+                    trace!(self, "INSERT\t{}, {}", ival1, ival2);
                     if unsafe { self.sp.add(ival2 as usize) } > stack_after {
                         panic!("Out of stack");
                     }
+                    // Make room for the copy, then duplicate the top `nbytes` at `idst`
                     unsafe {
                         std::ptr::copy(
                             self.sp.sub(ival1 as usize),
-                            self.sp.sub((ival1 + ival2) as usize),
+                            self.sp.sub(ival1 as usize).add(ival2 as usize),
                             ival1 as usize,
                         );
-                        std::ptr::copy(
-                            self.sp.add(ival2 as usize),
-                            self.sp.sub(ival1 as usize),
-                            ival2 as usize,
-                        );
+                        std::ptr::copy(self.sp, self.sp.sub(ival1 as usize), ival2 as usize);
                     }
                     self.sp = unsafe { self.sp.add(ival2 as usize) };
                     ip = ipi.next;
@@ -387,7 +504,7 @@ impl VirtualMachine {
                 Opcode::OJtI => {
                     ival1 = self.pop();
                     let jaddr = ipi.arg1.unwrap().get_addr() as *mut Instr;
-                    println!("JT_I\t{:p}\t{}", jaddr, ival1);
+                    trace!(self, "JT_I\t{:p}\t{}", jaddr, ival1);
                     if ival1 != 0 {
                         ip = jaddr;
                     } else {
@@ -397,8 +514,8 @@ impl VirtualMachine {
                 Opcode::OJtA => {
                     aval1 = self.pop();
                     let jaddr = ipi.arg1.unwrap().get_addr() as *mut Instr;
-                    println!("JT_A\t{:p}\t{:p}", jaddr, aval1);
-                    if aval1 != null() {
+                    trace!(self, "JT_A\t{:p}\t{:p}", jaddr, aval1);
+                    if !aval1.is_null() {
                         ip = jaddr;
                     } else {
                         ip = ipi.next
@@ -407,8 +524,8 @@ impl VirtualMachine {
                 Opcode::OJtC => {
                     cval1 = self.pop();
                     let jaddr = ipi.arg1.unwrap().get_addr() as *mut Instr;
-                    println!("JT_C\t{:p}\t{}", jaddr, cval1);
-                    if cval1 as u64 != 0 {
+                    trace!(self, "JT_C\t{:p}\t{}", jaddr, cval1);
+                    if cval1 != 0 {
                         ip = jaddr;
                     } else {
                         ip = ipi.next
@@ -417,7 +534,7 @@ impl VirtualMachine {
                 Opcode::OJtD => {
                     dval1 = self.pop();
                     let jaddr = ipi.arg1.unwrap().get_addr() as *mut Instr;
-                    println!("JT_D\t{:p}\t{}", jaddr, dval1);
+                    trace!(self, "JT_D\t{:p}\t{}", jaddr, dval1);
                     if dval1 != 0. {
                         ip = jaddr;
                     } else {
@@ -426,14 +543,14 @@ impl VirtualMachine {
                 }
                 Opcode::OJmp => {
                     let jaddr = ipi.arg1.unwrap().get_addr() as *mut Instr;
-                    println!("JT_D\t{:p}", jaddr);
+                    trace!(self, "JMP\t{:p}", jaddr);
                     ip = jaddr;
                 }
                 Opcode::OJfI => {
                     ival1 = self.pop();
                     let jaddr = ipi.arg1.unwrap().get_addr() as *mut Instr;
-                    println!("JF_I\t{:p}\t({})", jaddr, ival1);
-                    if ival1 != 0 {
+                    trace!(self, "JF_I\t{:p}\t({})", jaddr, ival1);
+                    if ival1 == 0 {
                         ip = jaddr;
                     } else {
                         ip = ipi.next
@@ -442,8 +559,8 @@ impl VirtualMachine {
                 Opcode::OJfA => {
                     aval1 = self.pop();
                     let jaddr = ipi.arg1.unwrap().get_addr() as *mut Instr;
-                    println!("JF_A\t{:p}\t({:p})", jaddr, aval1);
-                    if aval1 != null() {
+                    trace!(self, "JF_A\t{:p}\t({:p})", jaddr, aval1);
+                    if aval1.is_null() {
                         ip = jaddr;
                     } else {
                         ip = ipi.next
@@ -452,8 +569,8 @@ impl VirtualMachine {
                 Opcode::OJfC => {
                     cval1 = self.pop();
                     let jaddr = ipi.arg1.unwrap().get_addr() as *mut Instr;
-                    println!("JF_C\t{:p}\t({})", jaddr, cval1);
-                    if cval1 != 0 as char {
+                    trace!(self, "JF_C\t{:p}\t({})", jaddr, cval1);
+                    if cval1 == 0 {
                         ip = jaddr;
                     } else {
                         ip = ipi.next
@@ -462,8 +579,8 @@ impl VirtualMachine {
                 Opcode::OJfD => {
                     dval1 = self.pop();
                     let jaddr = ipi.arg1.unwrap().get_addr() as *mut Instr;
-                    println!("JF_D\t{:p}\t({})", jaddr, dval1);
-                    if dval1 != 0. {
+                    trace!(self, "JF_D\t{:p}\t({})", jaddr, dval1);
+                    if dval1 == 0. {
                         ip = jaddr;
                     } else {
                         ip = ipi.next;
@@ -472,47 +589,47 @@ impl VirtualMachine {
                 Opcode::OOffset => {
                     ival1 = self.pop();
                     aval1 = self.pop();
-                    let finaddr = unsafe { aval1.add(ival1 as usize) };
-                    println!("OFFSET\t{:p} + {} = {:p}", aval1, ival1, finaddr);
+                    let finaddr = unsafe { (aval1 as *const u8).add(ival1 as usize) };
+                    trace!(self, "OFFSET\t{:p} + {} = {:p}", aval1, ival1, finaddr);
                     self.push(finaddr);
                     ip = ipi.next;
                 }
                 Opcode::OPushFPAddr => {
-                    aval1 = ipi.arg1.unwrap().get_addr();
-                    let finaddr = unsafe { fp.add(ival1 as usize) };
-                    println!("OFFSET\t{}\t {:p}", ival1, finaddr);
+                    ival1 = ipi.arg1.unwrap().get_int();
+                    let finaddr = unsafe { fp.offset(ival1 as isize) };
+                    trace!(self, "PUSHFPADDR\t{}\t{:p}", ival1, finaddr);
                     self.push(finaddr);
                     ip = ipi.next;
                 }
 
                 Opcode::OPushCtA => {
                     aval1 = ipi.arg1.unwrap().get_addr();
-                    println!("PUSHCT_A\t{:p}", aval1);
+                    trace!(self, "PUSHCT_A\t{:p}", aval1);
                     self.push(aval1);
                     ip = ipi.next;
                 }
                 Opcode::OPushCtI => {
                     ival1 = ipi.arg1.unwrap().get_int();
-                    println!("PUSHCT_I\t{}", ival1);
+                    trace!(self, "PUSHCT_I\t{}", ival1);
                     self.push(ival1);
                     ip = ipi.next;
                 }
                 Opcode::OPushCtC => {
-                    cval1 = std::char::from_u32(ipi.arg1.unwrap().get_int() as u32).unwrap();
-                    println!("PUSHCT_C\t{}", cval1);
+                    cval1 = ipi.arg1.unwrap().get_int() as u8;
+                    trace!(self, "PUSHCT_C\t{}", cval1);
                     self.push(cval1);
                     ip = ipi.next;
                 }
                 Opcode::OPushCtD => {
                     dval1 = ipi.arg1.unwrap().get_double();
-                    println!("PUSHCT_I\t{}", dval1);
+                    trace!(self, "PUSHCT_D\t{}", dval1);
                     self.push(dval1);
                     ip = ipi.next;
                 }
                 Opcode::ORet => {
                     ival1 = ipi.arg1.unwrap().get_int();
                     ival2 = ipi.arg2.unwrap().get_int();
-                    println!("RET\t{}, {}", ival1, ival2);
+                    trace!(self, "RET\t{}, {}", ival1, ival2);
                     let oldsp = self.sp;
                     self.sp = fp;
                     fp = self.pop();
@@ -533,8 +650,8 @@ impl VirtualMachine {
                     if saddr < self.stack.as_mut_ptr() {
                         panic!("not enough stack bytes for SET");
                     }
-                    aval1 = unsafe { *(saddr as *mut *mut ()) };
-                    println!("STORE\t{}\t{:p}", ival1, aval1);
+                    aval1 = unsafe { std::ptr::read_unaligned(saddr as *const *const ()) };
+                    trace!(self, "STORE\t{}\t{:p}", ival1, aval1);
                     unsafe {
                         std::ptr::copy_nonoverlapping(
                             self.sp.sub(ival1 as usize),
@@ -548,7 +665,7 @@ impl VirtualMachine {
                 Opcode::OLoad => {
                     ival1 = ipi.arg1.unwrap().get_int(); // load nbytes
                     aval1 = self.pop();
-                    println!("Load\t{}\t{:p}", ival1, aval1);
+                    trace!(self, "Load\t{}\t{:p}", ival1, aval1);
                     if unsafe { self.sp.add(ival1 as usize) > stack_after } {
                         panic!("Out of stack");
                     }
@@ -561,71 +678,75 @@ impl VirtualMachine {
                 Opcode::OSubD => {
                     dval1 = self.pop();
                     dval2 = self.pop();
-                    println!("SUB_D\t{} - {} -> {}", dval2, dval1, dval2 - dval1);
+                    trace!(self, "SUB_D\t{} - {} -> {}", dval2, dval1, dval2 - dval1);
                     self.push(dval2 - dval1);
                     ip = ipi.next;
                 }
                 Opcode::OSubI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!("SUB_I\t{} - {} -> {}", ival2, ival1, ival2 - ival1);
+                    trace!(self, "SUB_I\t{} - {} -> {}", ival2, ival1, ival2 - ival1);
                     self.push(ival2 - ival1);
                     ip = ipi.next;
                 }
                 Opcode::OSubC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "SUB_C\t{}  {} -> {}",
                         cval2,
                         cval1,
-                        (cval2 as u8 - cval1 as u8) as char
+                        cval2.wrapping_sub(cval1)
                     );
-                    self.push((cval2 as u8 - cval1 as u8) as char);
+                    self.push(cval2.wrapping_sub(cval1));
                     ip = ipi.next;
                 }
                 Opcode::OAddC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "Add_C\t{} + {} -> {}",
                         cval2,
                         cval1,
-                        (cval2 as u8 + cval1 as u8) as char
+                        cval2.wrapping_add(cval1)
                     );
-                    self.push((cval2 as u8 + cval1 as u8) as char);
+                    self.push(cval2.wrapping_add(cval1));
                     ip = ipi.next;
                 }
                 Opcode::OAddD => {
                     dval1 = self.pop();
                     dval2 = self.pop();
-                    println!("Add_D\t{} + {} -> {}", dval2, dval1, dval1 + dval2);
-                    self.push((cval2 as u8 + cval1 as u8) as char);
+                    trace!(self, "Add_D\t{} + {} -> {}", dval2, dval1, dval1 + dval2);
+                    self.push(dval2 + dval1);
                     ip = ipi.next;
                 }
                 Opcode::OAddI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!("Add_I\t{} + {} -> {}", ival2, ival1, ival2 + ival1);
+                    trace!(self, "Add_I\t{} + {} -> {}", ival2, ival1, ival2 + ival1);
                     self.push(ival2 + ival1);
                     ip = ipi.next;
                 }
                 Opcode::OAndC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "And_C\t{} && {} -> {}",
                         cval2,
                         cval1,
-                        (cval2 as u8 != 0 && cval1 as u8 != 0)
+                        (cval2 != 0 && cval1 != 0)
                     );
-                    self.push((cval2 as u8 != 0 && cval1 as u8 != 0) as i64);
+                    self.push((cval2 != 0 && cval1 != 0) as i64);
                     ip = ipi.next;
                 }
                 Opcode::OAndD => {
                     dval1 = self.pop();
                     dval2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "And_D\t{} && {} -> {}",
                         dval2,
                         dval1,
@@ -637,7 +758,8 @@ impl VirtualMachine {
                 Opcode::OAndI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "And_I\t{} && {} -> {}",
                         ival2,
                         ival1,
@@ -649,7 +771,8 @@ impl VirtualMachine {
                 Opcode::OAndA => {
                     aval1 = self.pop();
                     aval2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "And_A\t{:p} && {:p} -> {}",
                         aval2,
                         aval1,
@@ -661,20 +784,22 @@ impl VirtualMachine {
                 Opcode::OOrC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "Or_C\t{} || {} -> {}",
                         cval2,
                         cval1,
-                        (cval2 as u8 != 0 || cval1 as u8 != 0)
+                        (cval2 != 0 || cval1 != 0)
                     );
-                    self.push((cval2 as u8 != 0 || cval1 as u8 != 0) as i64);
+                    self.push((cval2 != 0 || cval1 != 0) as i64);
                     ip = ipi.next;
                 }
                 Opcode::OOrD => {
                     dval1 = self.pop();
                     dval2 = self.pop();
-                    println!(
-                        "And_D\t{} || {} -> {}",
+                    trace!(
+                        self,
+                        "Or_D\t{} || {} -> {}",
                         dval2,
                         dval1,
                         dval1 != 0. || dval2 != 0.
@@ -685,7 +810,8 @@ impl VirtualMachine {
                 Opcode::OOrI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "Or_I\t{} || {} -> {}",
                         ival2,
                         ival1,
@@ -697,7 +823,8 @@ impl VirtualMachine {
                 Opcode::OOrA => {
                     aval1 = self.pop();
                     aval2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "Or_A\t{:p} || {:p} -> {}",
                         aval2,
                         aval1,
@@ -709,99 +836,178 @@ impl VirtualMachine {
                 Opcode::ODivC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!(
+                    if cval1 == 0 {
+                        panic!("Error: division by zero");
+                    }
+                    trace!(
+                        self,
                         "Div_C\t{} / {} -> {}",
                         cval2,
                         cval1,
-                        (cval2 as u8 / cval1 as u8) as char
+                        cval2.wrapping_div(cval1)
                     );
-                    self.push((cval2 as u8 / cval1 as u8) as char);
+                    self.push(cval2.wrapping_div(cval1));
                     ip = ipi.next;
                 }
                 Opcode::ODivD => {
                     dval1 = self.pop();
                     dval2 = self.pop();
-                    println!("Div_D\t{} / {} -> {}", dval2, dval1, dval1 / dval2);
+                    trace!(self, "Div_D\t{} / {} -> {}", dval2, dval1, dval2 / dval1);
                     self.push(dval2 / dval1);
                     ip = ipi.next;
                 }
                 Opcode::ODivI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!("Div_I\t{} / {} -> {}", ival2, ival1, ival2 / ival1);
+                    if ival1 == 0 {
+                        panic!("Error: division by zero");
+                    }
+                    trace!(self, "Div_I\t{} / {} -> {}", ival2, ival1, ival2 / ival1);
                     self.push(ival2 / ival1);
                     ip = ipi.next;
                 }
                 Opcode::OMulC => {
                     cval1 = self.pop();
                     cval2 = self.pop();
-                    println!(
+                    trace!(
+                        self,
                         "Mul_C\t{} * {} -> {}",
                         cval2,
                         cval1,
-                        (cval2 as u8 * cval1 as u8) as char
+                        cval2.wrapping_mul(cval1)
                     );
-                    self.push((cval2 as u8 * cval1 as u8) as char);
+                    self.push(cval2.wrapping_mul(cval1));
                     ip = ipi.next;
                 }
                 Opcode::OMulD => {
                     dval1 = self.pop();
                     dval2 = self.pop();
-                    println!("Mul_D\t{} * {} -> {}", dval2, dval1, dval1 * dval2);
-                    self.push((cval2 as u8 * cval1 as u8) as char);
+                    trace!(self, "Mul_D\t{} * {} -> {}", dval2, dval1, dval1 * dval2);
+                    self.push(dval2 * dval1);
                     ip = ipi.next;
                 }
                 Opcode::OMulI => {
                     ival1 = self.pop();
                     ival2 = self.pop();
-                    println!("Add_I\t{} * {} -> {}", ival2, ival1, ival2 * ival1);
+                    trace!(self, "Mul_I\t{} * {} -> {}", ival2, ival1, ival2 * ival1);
                     self.push(ival2 * ival1);
                     ip = ipi.next;
                 }
+                Opcode::OModI => {
+                    ival1 = self.pop();
+                    ival2 = self.pop();
+                    if ival1 == 0 {
+                        panic!("Error: division by zero");
+                    }
+                    trace!(
+                        self,
+                        "Mod_I\t{} % {} -> {}",
+                        ival2,
+                        ival1,
+                        ival2.wrapping_rem(ival1)
+                    );
+                    self.push(ival2.wrapping_rem(ival1));
+                    ip = ipi.next;
+                }
+                Opcode::OBitAndI => {
+                    ival1 = self.pop();
+                    ival2 = self.pop();
+                    trace!(self, "BitAnd_I\t{} & {} -> {}", ival2, ival1, ival2 & ival1);
+                    self.push(ival2 & ival1);
+                    ip = ipi.next;
+                }
+                Opcode::OBitOrI => {
+                    ival1 = self.pop();
+                    ival2 = self.pop();
+                    trace!(self, "BitOr_I\t{} | {} -> {}", ival2, ival1, ival2 | ival1);
+                    self.push(ival2 | ival1);
+                    ip = ipi.next;
+                }
+                Opcode::OBitXorI => {
+                    ival1 = self.pop();
+                    ival2 = self.pop();
+                    trace!(self, "BitXor_I\t{} ^ {} -> {}", ival2, ival1, ival2 ^ ival1);
+                    self.push(ival2 ^ ival1);
+                    ip = ipi.next;
+                }
+                Opcode::OShlI => {
+                    ival1 = self.pop();
+                    ival2 = self.pop();
+                    trace!(
+                        self,
+                        "Shl_I\t{} << {} -> {}",
+                        ival2,
+                        ival1,
+                        ival2.wrapping_shl(ival1 as u32)
+                    );
+                    self.push(ival2.wrapping_shl(ival1 as u32));
+                    ip = ipi.next;
+                }
+                Opcode::OShrI => {
+                    ival1 = self.pop();
+                    ival2 = self.pop();
+                    trace!(
+                        self,
+                        "Shr_I\t{} >> {} -> {}",
+                        ival2,
+                        ival1,
+                        ival2.wrapping_shr(ival1 as u32)
+                    );
+                    self.push(ival2.wrapping_shr(ival1 as u32));
+                    ip = ipi.next;
+                }
+                Opcode::OBitNotI => {
+                    ival1 = self.pop();
+                    trace!(self, "BitNot_I\t~{} -> {}", ival1, !ival1);
+                    self.push(!ival1);
+                    ip = ipi.next;
+                }
                 Opcode::ONegC => {
                     cval1 = self.pop();
-                    println!("Neg_C\t-{} -> {}", cval1, !(cval1 as u8) + 1);
-                    self.push(!(cval1 as u8) + 1);
+                    trace!(self, "NEG_C\t-{} -> {}", cval1, cval1.wrapping_neg());
+                    self.push(cval1.wrapping_neg());
                     ip = ipi.next;
                 }
                 Opcode::ONegI => {
                     ival1 = self.pop();
-                    println!("Neg_C\t-{} -> {}", ival1, -ival1);
+                    trace!(self, "NEG_I\t-{} -> {}", ival1, -ival1);
                     self.push(-ival1);
                     ip = ipi.next;
                 }
                 Opcode::ONegD => {
                     dval1 = self.pop();
-                    println!("Neg_C\t-{} -> {}", dval1, -dval1);
+                    trace!(self, "NEG_D\t-{} -> {}", dval1, -dval1);
                     self.push(-dval1);
                     ip = ipi.next;
                 }
                 Opcode::ONop => {
-                    println!("NOP");
+                    trace!(self, "NOP");
+                    ip = ipi.next;
+                }
+                Opcode::ONotA => {
+                    aval1 = self.pop();
+                    trace!(self, "NOT_A\t!{:p} -> {}", aval1, aval1.is_null());
+                    self.push(aval1.is_null() as i64);
                     ip = ipi.next;
                 }
                 Opcode::ONotC => {
                     cval1 = self.pop();
-                    println!("Neg_C\t-{} -> {}", cval1, cval1 as u8 != 0);
-                    self.push(cval1 as u8 != 0);
+                    trace!(self, "NOT_C\t!{} -> {}", cval1, cval1 == 0);
+                    self.push((cval1 == 0) as i64);
                     ip = ipi.next;
                 }
                 Opcode::ONotI => {
                     ival1 = self.pop();
-                    println!("Neg_C\t-{} -> {}", ival1, ival1 != 0);
-                    self.push(ival1 != 0);
+                    trace!(self, "NOT_I\t!{} -> {}", ival1, ival1 == 0);
+                    self.push((ival1 == 0) as i64);
                     ip = ipi.next;
                 }
                 Opcode::ONotD => {
                     dval1 = self.pop();
-                    println!("Neg_C\t-{} -> {}", dval1, dval1 != 0.);
-                    self.push(dval1 != 0.);
+                    trace!(self, "NOT_D\t!{} -> {}", dval1, dval1 == 0.);
+                    self.push((dval1 == 0.) as i64);
                     ip = ipi.next;
                 }
-
-                _ => {
-                    panic!("Invalid opcode");
-                }
             }
             //break;
         }
@@ -842,15 +1048,15 @@ impl InstrArg {
 }
 
 #[derive(Copy, Clone, Debug)]
-struct Instr {
-    opcode: Opcode,
-    arg1: Option<InstrArg>,
-    arg2: Option<InstrArg>,
-    next: *mut Instr,
-    last: *mut Instr,
+pub struct Instr {
+    pub opcode: Opcode,
+    pub arg1: Option<InstrArg>,
+    pub arg2: Option<InstrArg>,
+    pub next: *mut Instr,
+    pub last: *mut Instr,
 }
 impl Instr {
-    fn new(op: Opcode) -> Self {
+    pub fn new(op: Opcode) -> Self {
         Self {
             opcode: op,
             arg1: None,
@@ -859,7 +1065,7 @@ impl Instr {
             last: null_mut(),
         }
     }
-    fn new_arg(op: Opcode, arg: InstrArg) -> Self {
+    pub fn new_arg(op: Opcode, arg: InstrArg) -> Self {
         Self {
             opcode: op,
             arg1: Some(arg),
@@ -868,7 +1074,7 @@ impl Instr {
             last: null_mut(),
         }
     }
-    fn new_arg2(op: Opcode, arg1: InstrArg, arg2: InstrArg) -> Self {
+    pub fn new_arg2(op: Opcode, arg1: InstrArg, arg2: InstrArg) -> Self {
         Self {
             opcode: op,
             arg1: Some(arg1),
@@ -877,12 +1083,6 @@ impl Instr {
             last: null_mut(),
         }
     }
-    fn as_ptr(&self) -> *const Self {
-        self as *const Self
-    }
-    fn as_mut_ptr(&mut self) -> *mut Self {
-        self as *mut Self
-    }
     // fn insert_after(&mut self, instr: &mut Instr) {
     //     self.next = instr.next;
     //     self.last = instr.as_ptr();
@@ -890,23 +1090,25 @@ impl Instr {
     //     if self.next.is_null() {}
     // }
 }
-#[derive(Clone, Debug)]
-struct InstrList {
-    front: *mut Instr,
-    back: *mut Instr,
+/// Doubly linked list of instructions. The list owns its instructions and frees them when dropped,
+/// the raw pointers returned by `push_back` are used as jump addresses
+#[derive(Debug)]
+pub struct InstrList {
+    pub front: *mut Instr,
+    pub back: *mut Instr,
+}
+impl Default for InstrList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl InstrList {
-    fn new() -> Self {
+    pub fn new() -> Self {
         let x = null_mut::<Instr>();
         Self { front: x, back: x }
     }
-    fn push_back(&mut self, i: *mut Instr) -> *const Instr {
-        // (*i).next = (*self.back).next;
-        // (*i).last = self.back;
-        // (*self.back).next = i;
-        // self.back = i;
-        // return i;
-
+    pub fn push_back(&mut self, i: Instr) -> *mut Instr {
+        let i = Box::into_raw(Box::new(i));
         unsafe { (*i).last = self.back };
         if !self.back.is_null() {
             unsafe {
@@ -918,23 +1120,40 @@ impl InstrList {
         self.back = i;
         i
     }
-    fn push_back_op(&mut self, op: Opcode) -> *const Instr {
-        let i = Instr::new(op).as_mut_ptr();
-        self.push_back(i)
+    pub fn push_back_op(&mut self, op: Opcode) -> *mut Instr {
+        self.push_back(Instr::new(op))
     }
-    fn insert_after(&mut self, after: *mut Instr, i: *mut Instr) {
-        unsafe {
-            (*i).next = (*after).next;
-            (*i).last = after;
-            (*after).next = i;
+    /// Inserts `i` after the instruction `after` of this list
+    /// # Safety
+    /// `after` must be an instruction returned by this list
+    pub unsafe fn insert_after(&mut self, after: *mut Instr, i: Instr) -> *mut Instr {
+        let i = Box::into_raw(Box::new(i));
+        (*i).next = (*after).next;
+        (*i).last = after;
+        if !(*after).next.is_null() {
+            (*(*after).next).last = i;
         }
-        if i.is_null() {
+        (*after).next = i;
+        if self.back == after {
             self.back = i;
         }
+        i
+    }
+    pub fn iter(&self) -> InstrCursor {
+        InstrCursor { curr: self.front }
+    }
+}
+impl Drop for InstrList {
+    fn drop(&mut self) {
+        let mut i = self.front;
+        while !i.is_null() {
+            let instr = unsafe { Box::from_raw(i) };
+            i = instr.next;
+        }
     }
 }
 #[derive(Copy, Clone, Debug)]
-struct InstrCursor {
+pub struct InstrCursor {
     curr: *const Instr,
 }
 impl Iterator for InstrCursor {
@@ -950,23 +1169,71 @@ impl Iterator for InstrCursor {
         Some(unsafe { *i })
     }
 }
-impl IntoIterator for InstrList {
-    type Item = Instr;
-    type IntoIter = InstrCursor;
-    fn into_iter(self) -> Self::IntoIter {
-        InstrCursor { curr: self.front }
-    }
-}
-
-const GLOBAL_SIZE: usize = 32 * 1024;
-fn check_global_size(num_globals: usize, size: usize) {
+pub const GLOBAL_SIZE: usize = 32 * 1024;
+pub fn check_global_size(num_globals: usize, size: usize) {
     if num_globals + size > GLOBAL_SIZE {
         panic!("Insuficient globals space");
     }
 }
+/// The memory of the global variables and of the string constants.
+/// It is allocated once, so the addresses given by `alloc` stay valid while it lives
+#[derive(Debug)]
+pub struct Globals {
+    data: Vec<u8>,
+    num_globals: usize,
+}
+impl Default for Globals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Globals {
+    pub fn new() -> Self {
+        Self {
+            data: vec![0; GLOBAL_SIZE],
+            num_globals: 0,
+        }
+    }
+    /// Reserves `size` zeroed bytes
+    pub fn alloc(&mut self, size: usize) -> *const () {
+        self.alloc_aligned(size, 1)
+    }
+    /// Reserves `size` zeroed bytes at an offset which is a multiple of `align`
+    pub fn alloc_aligned(&mut self, size: usize, align: usize) -> *const () {
+        self.num_globals = align_to(self.num_globals, align);
+        check_global_size(self.num_globals, size);
+        let addr = unsafe { self.data.as_mut_ptr().add(self.num_globals) } as *const ();
+        self.num_globals += size;
+        addr
+    }
+    /// Stores `bytes` and returns their address
+    pub fn alloc_bytes(&mut self, bytes: &[u8]) -> *const () {
+        let addr = self.alloc(bytes.len());
+        let start = self.num_globals - bytes.len();
+        self.data[start..self.num_globals].copy_from_slice(bytes);
+        addr
+    }
+    /// Copies `bytes` at `addr`, which was given by `alloc`
+    pub fn write(&mut self, addr: *const (), bytes: &[u8]) {
+        let start = self.offset_of(addr);
+        self.data[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+    /// The offset of `addr` from the start of the globals area
+    pub fn offset_of(&self, addr: *const ()) -> usize {
+        addr as usize - self.data.as_ptr() as usize
+    }
+    /// Number of allocated bytes
+    pub fn len(&self) -> usize {
+        self.num_globals
+    }
+    pub fn is_empty(&self) -> bool {
+        self.num_globals == 0
+    }
+}
 
 #[cfg(test)]
 pub mod tests {
+    use crate::intern::Name;
     use crate::mv::*;
     use crate::symbols::{add_ext_funcs, require_symbol, Context};
     #[test]
@@ -978,97 +1245,90 @@ pub mod tests {
         let mut contexts = vec![Context::default()];
         add_ext_funcs(&mut contexts[0]);
         // Add instructions
-        instr_list.push_back(
-            Instr::new_arg(
-                Opcode::OPushCtA,
-                InstrArg::Addr(globals.as_ptr() as *const ()),
-            )
-            .as_mut_ptr(),
-        );
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(3)).as_mut_ptr());
-        instr_list.push_back(
-            Instr::new_arg(Opcode::OStore, InstrArg::Int(size_of::<isize>() as i64)).as_mut_ptr(),
-        );
-        let l1 = instr_list.push_back(
-            Instr::new_arg(
-                Opcode::OPushCtA,
-                InstrArg::Addr(globals.as_ptr() as *const ()),
-            )
-            .as_mut_ptr(),
-        );
-        instr_list.push_back(
-            Instr::new_arg(Opcode::OLoad, InstrArg::Int(size_of::<isize>() as i64)).as_mut_ptr(),
-        );
-        instr_list.push_back(
-            Instr::new_arg(
-                Opcode::OCallext,
-                InstrArg::Addr(require_symbol(&contexts, "put_i").ao.get_addr()),
-            )
-            .as_mut_ptr(),
-        );
-        instr_list.push_back(
-            Instr::new_arg(
-                Opcode::OPushCtA,
-                InstrArg::Addr(globals.as_ptr() as *const ()),
-            )
-            .as_mut_ptr(),
-        );
-        instr_list.push_back(
-            Instr::new_arg(
-                Opcode::OPushCtA,
-                InstrArg::Addr(globals.as_ptr() as *const ()),
-            )
-            .as_mut_ptr(),
-        );
-        instr_list.push_back(
-            Instr::new_arg(Opcode::OLoad, InstrArg::Int(size_of::<isize>() as i64)).as_mut_ptr(),
-        );
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(1)).as_mut_ptr());
-        instr_list.push_back(Instr::new(Opcode::OSubI).as_mut_ptr());
-        instr_list.push_back(
-            Instr::new_arg(Opcode::OStore, InstrArg::Int(size_of::<isize>() as i64)).as_mut_ptr(),
-        );
-        instr_list.push_back(
-            Instr::new_arg(
-                Opcode::OPushCtA,
-                InstrArg::Addr(globals.as_ptr() as *const ()),
-            )
-            .as_mut_ptr(),
-        );
-        instr_list.push_back(
-            Instr::new_arg(Opcode::OLoad, InstrArg::Int(size_of::<isize>() as i64)).as_mut_ptr(),
-        );
-        instr_list
-            .push_back(Instr::new_arg(Opcode::OJtI, InstrArg::Addr(l1 as *const ())).as_mut_ptr());
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OPushCtA,
+            InstrArg::Addr(globals.as_ptr() as *const ()),
+        ));
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(3)));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OStore,
+            InstrArg::Int(size_of::<isize>() as i64),
+        ));
+        let l1 = instr_list.push_back(Instr::new_arg(
+            Opcode::OPushCtA,
+            InstrArg::Addr(globals.as_ptr() as *const ()),
+        ));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OLoad,
+            InstrArg::Int(size_of::<isize>() as i64),
+        ));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OCallext,
+            InstrArg::Addr(
+                require_symbol(&contexts, Name::intern("put_i"))
+                    .ao
+                    .get_addr(),
+            ),
+        ));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OPushCtA,
+            InstrArg::Addr(globals.as_ptr() as *const ()),
+        ));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OPushCtA,
+            InstrArg::Addr(globals.as_ptr() as *const ()),
+        ));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OLoad,
+            InstrArg::Int(size_of::<isize>() as i64),
+        ));
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(1)));
+        instr_list.push_back(Instr::new(Opcode::OSubI));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OStore,
+            InstrArg::Int(size_of::<isize>() as i64),
+        ));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OPushCtA,
+            InstrArg::Addr(globals.as_ptr() as *const ()),
+        ));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OLoad,
+            InstrArg::Int(size_of::<isize>() as i64),
+        ));
+        instr_list.push_back(Instr::new_arg(
+            Opcode::OJtI,
+            InstrArg::Addr(l1 as *const ()),
+        ));
 
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(10)).as_mut_ptr());
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(5)).as_mut_ptr());
-        instr_list.push_back(Instr::new(Opcode::OSubI).as_mut_ptr());
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(10)));
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(5)));
+        instr_list.push_back(Instr::new(Opcode::OSubI));
 
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(10.)).as_mut_ptr());
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(3.)).as_mut_ptr());
-        instr_list.push_back(Instr::new(Opcode::ODivD).as_mut_ptr());
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(10.)));
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(3.)));
+        instr_list.push_back(Instr::new(Opcode::ODivD));
 
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(10.)).as_mut_ptr());
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(3.5)).as_mut_ptr());
-        instr_list.push_back(Instr::new(Opcode::OMulD).as_mut_ptr());
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(10.)));
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(3.5)));
+        instr_list.push_back(Instr::new(Opcode::OMulD));
 
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(65)).as_mut_ptr());
-        instr_list.push_back(Instr::new(Opcode::OCastIC).as_mut_ptr());
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(65)));
+        instr_list.push_back(Instr::new(Opcode::OCastIC));
 
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(10.5)).as_mut_ptr());
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(3.88)).as_mut_ptr());
-        instr_list.push_back(Instr::new(Opcode::OGreaterD).as_mut_ptr());
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(10.5)));
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtD, InstrArg::Double(3.88)));
+        instr_list.push_back(Instr::new(Opcode::OGreaterD));
 
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(10)).as_mut_ptr());
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(0)).as_mut_ptr());
-        instr_list.push_back(Instr::new(Opcode::OAndI).as_mut_ptr());
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(10)));
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(0)));
+        instr_list.push_back(Instr::new(Opcode::OAndI));
 
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(10)).as_mut_ptr());
-        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(1)).as_mut_ptr());
-        instr_list.push_back(Instr::new(Opcode::OAndI).as_mut_ptr());
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(10)));
+        instr_list.push_back(Instr::new_arg(Opcode::OPushCtI, InstrArg::Int(1)));
+        instr_list.push_back(Instr::new(Opcode::OAndI));
 
-        instr_list.push_back(Instr::new(Opcode::OHalt).as_mut_ptr());
+        instr_list.push_back(Instr::new(Opcode::OHalt));
 
         let mut mv = VirtualMachine::new();
         mv.run(&instr_list);
@@ -0,0 +1,732 @@
+use crate::lexer::{Lexer, Token, TokenKind, TokenValue};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Maximum depth of nested `#include`s. Stops files including themselves without a guard
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+/// A `#define`d macro
+#[derive(Clone, Debug, PartialEq)]
+pub struct Macro {
+    /// `None` for object-like macros, the parameter names for function-like macros
+    pub params: Option<Vec<String>>,
+    pub body: String,
+}
+
+/// An open `#ifdef` / `#ifndef` block
+struct Cond {
+    active: bool,
+    parent_active: bool,
+    seen_else: bool,
+    line: usize,
+}
+
+/// A piece of preprocessed text and where it starts
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub text: String,
+}
+
+/// The preprocessed text of a file and its includes. The tokens borrow from it
+#[derive(Clone, Debug)]
+pub struct Source {
+    pub segments: Vec<Segment>,
+    /// The file and line of the `End` token
+    pub end: (Rc<str>, usize),
+}
+impl Source {
+    /// Lexes every segment. The tokens end with `TokenKind::End`
+    pub fn tokens(&self) -> Vec<Token<'_>> {
+        let size = self.segments.iter().map(|s| s.text.len()).sum::<usize>();
+        let mut token_vec = Vec::with_capacity(size / 4 + 1);
+        for segment in &self.segments {
+            let lexer = Lexer::at_line(&segment.text, &segment.file, segment.line);
+            for t in lexer {
+                if t.kind == TokenKind::End {
+                    break;
+                }
+                token_vec.push(t);
+            }
+        }
+        let (file, line) = &self.end;
+        token_vec.push(Token::new(TokenKind::End, TokenValue::None, *line, file));
+        token_vec
+    }
+}
+
+/// Preprocessor struct. Runs in front of the `Lexer` and handles
+/// `#include "file"`, `#define`, `#undef`, `#ifdef`, `#ifndef`, `#else` and `#endif`
+#[derive(Default)]
+pub struct Preprocessor {
+    pub defines: HashMap<String, Macro>,
+    depth: usize,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Defines an object-like macro, like `#define name body`
+    pub fn define(&mut self, name: &str, body: &str) {
+        self.defines.insert(
+            name.to_string(),
+            Macro {
+                params: None,
+                body: body.to_string(),
+            },
+        );
+    }
+
+    /// Preprocesses a file. Call `tokens` on the result to get its tokens
+    /// # Arguments
+    /// * `filename` - The path of the file to be read
+    pub fn process_file(&mut self, filename: &str) -> Source {
+        let content = fs::read_to_string(filename)
+            .unwrap_or_else(|_| panic!("Error: cannot open file `{}`", filename));
+        self.process_string(&content, filename)
+    }
+
+    /// Preprocesses a string. Included files are searched relative to `file`
+    pub fn process_string(&mut self, content: &str, file: &str) -> Source {
+        let mut segments = vec![];
+        let file: Rc<str> = Rc::from(file);
+        let line = self.process_source(content, &file, &mut segments);
+        Source {
+            segments,
+            end: (file, line),
+        }
+    }
+
+    /// Processes the text of one file, appending its preprocessed text to `segments`.
+    /// Returns the last line of the file
+    fn process_source(&mut self, content: &str, file: &Rc<str>, segments: &mut Vec<Segment>) -> usize {
+        let lines = content.lines().collect::<Vec<&str>>();
+        let mut conds: Vec<Cond> = vec![];
+        // Text not yet given to the lexer and the line it starts on
+        let mut segment = String::new();
+        let mut segment_line = 1;
+        let mut in_comment = false;
+        let mut i = 0;
+        while i < lines.len() {
+            let line = i + 1;
+            let active = conds.iter().all(|c| c.active);
+            let trimmed = lines[i].trim_start();
+            if in_comment || !trimmed.starts_with('#') {
+                if active {
+                    let expanded = self.expand(lines[i], &mut in_comment, &mut vec![], file, line);
+                    segment.push_str(&expanded);
+                } else {
+                    skip_comments(lines[i], &mut in_comment);
+                }
+                segment.push('\n');
+                i += 1;
+                continue;
+            }
+
+            // Directive, joined with its continuation lines
+            let mut directive = trimmed[1..].to_string();
+            while directive.ends_with('\\') && i + 1 < lines.len() {
+                directive.pop();
+                directive.push(' ');
+                i += 1;
+                directive.push_str(lines[i]);
+                segment.push('\n');
+            }
+            let directive = strip_comments(&directive, file, line);
+            let (name, rest) = split_word(&directive);
+            let rest = rest.trim();
+            match name {
+                "ifdef" | "ifndef" => {
+                    let macro_name = require_name(rest, name, file, line);
+                    let defined = self.defines.contains_key(macro_name);
+                    conds.push(Cond {
+                        active: active && (defined == (name == "ifdef")),
+                        parent_active: active,
+                        seen_else: false,
+                        line,
+                    });
+                }
+                "else" => match conds.last_mut() {
+                    Some(c) => {
+                        if c.seen_else {
+                            pp_error(file, line, "`#else` after `#else`");
+                        }
+                        c.seen_else = true;
+                        c.active = c.parent_active && !c.active;
+                    }
+                    None => pp_error(file, line, "`#else` without `#ifdef` / `#ifndef`"),
+                },
+                "endif" => {
+                    if conds.pop().is_none() {
+                        pp_error(file, line, "`#endif` without `#ifdef` / `#ifndef`");
+                    }
+                }
+                _ if !active => {}
+                "define" => self.rule_define(rest, file, line),
+                "undef" => {
+                    let macro_name = require_name(rest, name, file, line);
+                    self.defines.remove(macro_name);
+                }
+                "include" => {
+                    // End the segment before the include, then process the included file
+                    push_segment(&mut segment, file, segment_line, segments);
+                    self.include(rest, file, line, segments);
+                    segment_line = i + 2;
+                    i += 1;
+                    continue;
+                }
+                "" => {} // null directive
+                _ => pp_error(file, line, &format!("unknown directive `#{}`", name)),
+            }
+            segment.push('\n');
+            i += 1;
+        }
+        if let Some(c) = conds.last() {
+            pp_error(file, c.line, "unterminated `#ifdef` / `#ifndef`");
+        }
+        if in_comment {
+            pp_error(file, lines.len(), "unterminated comment");
+        }
+        push_segment(&mut segment, file, segment_line, segments);
+        lines.len().max(1)
+    }
+
+    /// #include "file"
+    /// The file is searched relative to the directory of the including file
+    fn include(&mut self, rest: &str, file: &Rc<str>, line: usize, segments: &mut Vec<Segment>) {
+        if !(rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"')) {
+            pp_error(file, line, "expected `\"file\"` after `#include`");
+        }
+        let name = &rest[1..rest.len() - 1];
+        let path: PathBuf = match Path::new(&**file).parent() {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        };
+        let content = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(_) => pp_error(file, line, &format!("cannot open include file `{}`", name)),
+        };
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            pp_error(
+                file,
+                line,
+                &format!(
+                    "`#include` nested too deeply, is `{}` missing an include guard?",
+                    name
+                ),
+            );
+        }
+        self.depth += 1;
+        let included: Rc<str> = Rc::from(path.to_string_lossy().as_ref());
+        self.process_source(&content, &included, segments);
+        self.depth -= 1;
+    }
+
+    /// #define NAME body
+    /// #define NAME(param, ...) body
+    fn rule_define(&mut self, rest: &str, file: &Rc<str>, line: usize) {
+        let name_len = ident_len(rest);
+        if name_len == 0 {
+            pp_error(file, line, "expected macro name after `#define`");
+        }
+        let name = &rest[..name_len];
+        let mut body = &rest[name_len..];
+        let mut params = None;
+        // Only a `(` right after the name starts a parameter list
+        if body.starts_with('(') {
+            let close = match body.find(')') {
+                Some(c) => c,
+                None => pp_error(
+                    file,
+                    line,
+                    &format!("missing `)` in parameters of macro `{}`", name),
+                ),
+            };
+            let mut names: Vec<String> = vec![];
+            let list = body[1..close].trim();
+            if !list.is_empty() {
+                for param in list.split(',') {
+                    let param = param.trim();
+                    if param.is_empty() || ident_len(param) != param.len() {
+                        pp_error(
+                            file,
+                            line,
+                            &format!("invalid parameter in macro `{}`", name),
+                        );
+                    }
+                    if names.iter().any(|n| n == param) {
+                        pp_error(
+                            file,
+                            line,
+                            &format!("duplicate parameter `{}` in macro `{}`", param, name),
+                        );
+                    }
+                    names.push(param.to_string());
+                }
+            }
+            params = Some(names);
+            body = &body[close + 1..];
+        }
+        let m = Macro {
+            params,
+            body: body.trim().to_string(),
+        };
+        if let Some(old) = self.defines.get(name) {
+            if *old != m {
+                pp_error(
+                    file,
+                    line,
+                    &format!("macro `{}` redefined differently", name),
+                );
+            }
+        }
+        self.defines.insert(name.to_string(), m);
+    }
+
+    /// Replaces the macros found in `text`. `hidden` holds the macros being expanded,
+    /// which are not replaced again so recursive macros stop
+    fn expand(
+        &self,
+        text: &str,
+        in_comment: &mut bool,
+        hidden: &mut Vec<String>,
+        file: &Rc<str>,
+        line: usize,
+    ) -> String {
+        let chars = text.char_indices().collect::<Vec<(usize, char)>>();
+        let mut result = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i].1;
+            if *in_comment {
+                result.push(c);
+                if c == '*' && i + 1 < chars.len() && chars[i + 1].1 == '/' {
+                    result.push('/');
+                    *in_comment = false;
+                    i += 1;
+                }
+                i += 1;
+                continue;
+            }
+            match c {
+                '/' if i + 1 < chars.len() && chars[i + 1].1 == '/' => {
+                    result.push_str(&text[chars[i].0..]);
+                    break;
+                }
+                '/' if i + 1 < chars.len() && chars[i + 1].1 == '*' => {
+                    result.push_str("/*");
+                    *in_comment = true;
+                    i += 2;
+                }
+                '"' | '\'' => {
+                    let end = literal_end(&chars, i);
+                    result.push_str(&slice(text, &chars, i, end));
+                    i = end;
+                }
+                '0'..='9' => {
+                    // numbers can hold letters (0x1f, 1e5), copy them whole
+                    let mut end = i;
+                    while end < chars.len()
+                        && (chars[end].1.is_ascii_alphanumeric()
+                            || chars[end].1 == '_'
+                            || chars[end].1 == '.')
+                    {
+                        end += 1;
+                    }
+                    result.push_str(&slice(text, &chars, i, end));
+                    i = end;
+                }
+                'a'..='z' | 'A'..='Z' | '_' => {
+                    let mut end = i;
+                    while end < chars.len()
+                        && (chars[end].1.is_ascii_alphanumeric() || chars[end].1 == '_')
+                    {
+                        end += 1;
+                    }
+                    let name = slice(text, &chars, i, end);
+                    i = end;
+                    let m = match self.defines.get(&name) {
+                        Some(m) if !hidden.contains(&name) => m,
+                        _ => {
+                            result.push_str(&name);
+                            continue;
+                        }
+                    };
+                    let body = match &m.params {
+                        None => m.body.clone(),
+                        Some(params) => {
+                            // A function-like macro without arguments is left alone
+                            let mut open = i;
+                            while open < chars.len() && chars[open].1.is_whitespace() {
+                                open += 1;
+                            }
+                            if open >= chars.len() || chars[open].1 != '(' {
+                                result.push_str(&name);
+                                continue;
+                            }
+                            let (args, end) = split_args(text, &chars, open, &name, file, line);
+                            i = end;
+                            let args = if params.is_empty()
+                                && args.len() == 1
+                                && args[0].trim().is_empty()
+                            {
+                                vec![]
+                            } else {
+                                args
+                            };
+                            if args.len() != params.len() {
+                                pp_error(
+                                    file,
+                                    line,
+                                    &format!(
+                                        "macro `{}` expects {} arguments, {} given",
+                                        name,
+                                        params.len(),
+                                        args.len()
+                                    ),
+                                );
+                            }
+                            // Arguments are expanded before being substituted
+                            let args = args
+                                .iter()
+                                .map(|a| self.expand(a.trim(), &mut false, hidden, file, line))
+                                .collect::<Vec<String>>();
+                            substitute(&m.body, params, &args)
+                        }
+                    };
+                    hidden.push(name);
+                    result.push_str(&self.expand(&body, &mut false, hidden, file, line));
+                    hidden.pop();
+                }
+                _ => {
+                    result.push(c);
+                    i += 1;
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Moves the text gathered so far into a new `Segment`
+fn push_segment(segment: &mut String, file: &Rc<str>, line: usize, segments: &mut Vec<Segment>) {
+    if !segment.is_empty() {
+        segments.push(Segment {
+            file: file.clone(),
+            line,
+            text: std::mem::take(segment),
+        });
+    }
+}
+
+fn pp_error(file: &Rc<str>, line: usize, msg: &str) -> ! {
+    panic!("Error in {}:{}, {}", file, line, msg);
+}
+
+/// Returns the length of the identifier at the start of `s`, 0 if there is none
+fn ident_len(s: &str) -> usize {
+    match s.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => s
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(s.len()),
+        _ => 0,
+    }
+}
+
+/// Splits the directive name from the rest of the directive
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    let len = ident_len(s);
+    (&s[..len], &s[len..])
+}
+
+fn require_name<'a>(rest: &'a str, directive: &str, file: &Rc<str>, line: usize) -> &'a str {
+    let len = ident_len(rest);
+    if len == 0 || !rest[len..].trim().is_empty() {
+        pp_error(
+            file,
+            line,
+            &format!("expected one macro name after `#{}`", directive),
+        );
+    }
+    &rest[..len]
+}
+
+/// Returns the text between the char indices `start` and `end`
+fn slice(text: &str, chars: &[(usize, char)], start: usize, end: usize) -> String {
+    let from = chars[start].0;
+    let to = if end < chars.len() {
+        chars[end].0
+    } else {
+        text.len()
+    };
+    text[from..to].to_string()
+}
+
+/// Returns the index after the string or char literal starting at `start`
+fn literal_end(chars: &[(usize, char)], start: usize) -> usize {
+    let quote = chars[start].1;
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i].1 {
+            '\\' => i += 1,
+            c if c == quote => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// Splits the arguments of a macro call starting at the `(` at `open`.
+/// Returns the arguments and the index after the closing `)`
+fn split_args(
+    text: &str,
+    chars: &[(usize, char)],
+    open: usize,
+    name: &str,
+    file: &Rc<str>,
+    line: usize,
+) -> (Vec<String>, usize) {
+    let mut args = vec![];
+    let mut depth = 0;
+    let mut arg_start = open + 1;
+    let mut i = open + 1;
+    while i < chars.len() {
+        match chars[i].1 {
+            '"' | '\'' => {
+                i = literal_end(chars, i);
+                continue;
+            }
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => {
+                args.push(slice(text, chars, arg_start, i));
+                return (args, i + 1);
+            }
+            ',' if depth == 0 => {
+                args.push(slice(text, chars, arg_start, i));
+                arg_start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    pp_error(
+        file,
+        line,
+        &format!("unterminated argument list for macro `{}`", name),
+    );
+}
+
+/// Replaces the parameters found in `body` with their arguments
+fn substitute(body: &str, params: &[String], args: &[String]) -> String {
+    let chars = body.char_indices().collect::<Vec<(usize, char)>>();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i].1 {
+            '"' | '\'' => {
+                let end = literal_end(&chars, i);
+                result.push_str(&slice(body, &chars, i, end));
+                i = end;
+            }
+            'a'..='z' | 'A'..='Z' | '_' | '0'..='9' => {
+                let mut end = i;
+                while end < chars.len()
+                    && (chars[end].1.is_ascii_alphanumeric() || chars[end].1 == '_')
+                {
+                    end += 1;
+                }
+                let word = slice(body, &chars, i, end);
+                match params.iter().position(|p| *p == word) {
+                    Some(p) => result.push_str(&args[p]),
+                    None => result.push_str(&word),
+                }
+                i = end;
+            }
+            c => {
+                result.push(c);
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
+/// Removes the comments from a directive line
+fn strip_comments(directive: &str, file: &Rc<str>, line: usize) -> String {
+    let chars = directive.char_indices().collect::<Vec<(usize, char)>>();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i].1 {
+            '"' | '\'' => {
+                let end = literal_end(&chars, i);
+                result.push_str(&slice(directive, &chars, i, end));
+                i = end;
+            }
+            '/' if i + 1 < chars.len() && chars[i + 1].1 == '/' => break,
+            '/' if i + 1 < chars.len() && chars[i + 1].1 == '*' => {
+                match directive[chars[i].0 + 2..].find("*/") {
+                    Some(end) => {
+                        result.push(' ');
+                        let end = chars[i].0 + 2 + end + 2;
+                        while i < chars.len() && chars[i].0 < end {
+                            i += 1;
+                        }
+                    }
+                    None => pp_error(
+                        file,
+                        line,
+                        "comments in directives must end on the same line",
+                    ),
+                }
+            }
+            c => {
+                result.push(c);
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
+/// Follows the comments of a line which is skipped by `#ifdef` / `#ifndef`
+fn skip_comments(text: &str, in_comment: &mut bool) {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut i = 0;
+    while i < chars.len() {
+        if *in_comment {
+            if chars[i] == '*' && i + 1 < chars.len() && chars[i + 1] == '/' {
+                *in_comment = false;
+                i += 1;
+            }
+        } else if chars[i] == '/' && i + 1 < chars.len() {
+            match chars[i + 1] {
+                '/' => return,
+                '*' => {
+                    *in_comment = true;
+                    i += 1;
+                }
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::intern::Name;
+    use crate::lexer::{TokenKind, TokenValue};
+    use crate::preprocessor::Preprocessor;
+
+    fn ids(tokens: &[crate::lexer::Token]) -> Vec<String> {
+        tokens
+            .iter()
+            .map(|t| match &t.value {
+                TokenValue::Id(id) => id.to_string(),
+                TokenValue::Int(i) => i.to_string(),
+                TokenValue::Str(s) => format!("{:?}", s),
+                _ => format!("{:?}", t.kind),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn define_object_like() {
+        let mut pp = Preprocessor::new();
+        let source = pp.process_string("#define N 10\nint a[N];\n", "");
+        let tokens = source.tokens();
+        assert_eq!(
+            ids(&tokens),
+            vec!["Int", "a", "Lbracket", "10", "Rbracket", "Semicolon", "End"]
+        );
+        // The line is kept even if the directive is removed
+        assert_eq!(tokens[0].line, 2);
+    }
+    #[test]
+    fn define_function_like() {
+        let mut pp = Preprocessor::new();
+        let source = pp.process_string("#define SQ(x) ((x)*(x))\n#define ONE 1\nSQ(ONE+2) SQ\n", "");
+        let tokens = source.tokens();
+        assert_eq!(
+            ids(&tokens),
+            vec![
+                "Lpar", "Lpar", "1", "Add", "2", "Rpar", "Mul", "Lpar", "1", "Add", "2", "Rpar",
+                "Rpar", "SQ", "End"
+            ]
+        );
+    }
+    #[test]
+    fn define_recursive_stops() {
+        let mut pp = Preprocessor::new();
+        let source = pp.process_string("#define a b\n#define b a\na\n", "");
+        let tokens = source.tokens();
+        assert_eq!(ids(&tokens), vec!["a", "End"]);
+    }
+    #[test]
+    fn no_expansion_in_strings_and_comments() {
+        let mut pp = Preprocessor::new();
+        let source = pp.process_string("#define N 10\n\"N\" /* N\nN */ N // N\n", "");
+        let tokens = source.tokens();
+        assert_eq!(ids(&tokens), vec!["\"N\"", "10", "End"]);
+    }
+    #[test]
+    fn conditionals() {
+        let mut pp = Preprocessor::new();
+        pp.define("DEBUG", "");
+        let src = "#ifdef DEBUG\na\n#ifndef DEBUG\nb\n#else\nc\n#endif\n#else\nd\n#endif\n";
+        let source = pp.process_string(src, "");
+        let tokens = source.tokens();
+        assert_eq!(ids(&tokens), vec!["a", "c", "End"]);
+        assert_eq!(tokens[1].line, 6);
+    }
+    #[test]
+    #[should_panic(expected = "unterminated `#ifdef`")]
+    fn conditionals_unterminated() {
+        Preprocessor::new().process_string("#ifdef X\nint x;\n", "");
+    }
+    #[test]
+    #[should_panic(expected = "`#endif` without")]
+    fn conditionals_unmatched_endif() {
+        Preprocessor::new().process_string("#endif\n", "");
+    }
+    #[test]
+    #[should_panic(expected = "macro `MAX` expects 2 arguments, 1 given")]
+    fn define_wrong_arguments() {
+        Preprocessor::new().process_string("#define MAX(a, b) a\nMAX(1)\n", "");
+    }
+    #[test]
+    fn include_with_guard() {
+        let mut pp = Preprocessor::new();
+        let source = pp.process_file("tests/preprocessor/main.c");
+        let tokens = source.tokens();
+        // defs.h is included twice but its guard keeps a single copy
+        let structs = tokens
+            .iter()
+            .filter(|t| t.kind == TokenKind::Struct && t.file.ends_with("defs.h"))
+            .count();
+        assert_eq!(structs, 1);
+        let size = tokens
+            .iter()
+            .find(|t| t.kind == TokenKind::CtInt)
+            .unwrap();
+        assert_eq!(size.value.get_int(), Some(16));
+        // Tokens know the file they come from
+        assert_eq!(tokens[0].file, "tests/preprocessor/defs.h");
+        assert_eq!(tokens[0].line, 4);
+        let main = tokens
+            .iter()
+            .find(|t| t.value.get_id() == Some(Name::intern("main")))
+            .unwrap();
+        assert_eq!(main.file, "tests/preprocessor/main.c");
+        assert_eq!(main.line, 5);
+    }
+}
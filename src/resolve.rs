@@ -0,0 +1,653 @@
+use crate::ast::*;
+use crate::intern::Name;
+use crate::layout::{arg_offsets, Layout};
+use crate::mv::Globals;
+use crate::symbols::*;
+use crate::typecheck::const_int;
+use indexmap::IndexMap;
+use std::collections::HashMap;
+
+/// Name resolution. Builds the symbol tables of a `Unit`, gives every variable its memory
+/// and links every `Id` and `Call` of the tree to its symbol
+pub struct Resolver<'g> {
+    /// The global context and the contexts of the current function
+    pub symbol_tables: Vec<Context>,
+    globals: &'g mut Globals,
+    /// The locals of the current function
+    frame: Layout,
+    locals: Vec<Symbol>,
+    /// The line of the definition of every function with a body
+    defined: HashMap<Name, usize>,
+    /// The calls of functions which had only a prototype at that point
+    undefined_calls: Vec<(Name, Loc)>,
+    /// The location of the global `main` kept in the symbol table and of its arguments
+    main_locs: Option<(Loc, Vec<Loc>)>,
+}
+
+/// `int(int, char[])`, two declarations of a function must have the same signature
+fn signature(f: &Symbol) -> String {
+    let args: Vec<String> =
+        f.am.iter()
+            .flat_map(|am| am.values())
+            .map(|a| a.symbol_type.to_string())
+            .collect();
+    format!("{}({})", f.symbol_type, args.join(", "))
+}
+
+/// Converts a `TypeSpec` of the tree. Struct names are searched in the global `context`
+pub fn symbol_type(ty: &TypeSpec, context: &Context, loc: Loc) -> SymbolType {
+    let mut symbol_type = match ty.base {
+        TypeBase::Int => SymbolType::new(TypeName::TbInt, -1),
+        TypeBase::Double => SymbolType::new(TypeName::TbDouble, -1),
+        TypeBase::Char => SymbolType::new(TypeName::TbChar, -1),
+        TypeBase::Void => SymbolType::new(TypeName::TbVoid, -1),
+        TypeBase::Struct(name) => match context.find_symbol(name) {
+            Some(s) if s.class == ClassType::ClsStruct => SymbolType {
+                type_base: TypeName::TbStruct,
+                struct_symbol: Some(Box::new(s.clone())),
+                ..Default::default()
+            },
+            Some(_) => panic!("Error in {}, `{}` is not a struct", loc, name),
+            None => panic!("Error in {}, undefined struct `{}`", loc, name),
+        },
+    };
+    // Row-major: the first dimension is the outermost one
+    for (i, dim) in ty.dims.iter().enumerate() {
+        let n = match dim {
+            None if i == 0 => 0,
+            None => panic!(
+                "Error in {}, only the first dimension of an array can be left without size",
+                loc
+            ),
+            Some(size) => match const_int(size, context) {
+                Some(n) if n > 0 => n,
+                Some(n) => panic!(
+                    "Error in {}, the size of an array must be positive, not {}",
+                    size.loc, n
+                ),
+                None => panic!(
+                    "Error in {}, the size of an array must be an int constant expression",
+                    size.loc
+                ),
+            },
+        };
+        match i {
+            0 => symbol_type.num_elements = n,
+            _ => symbol_type.dims.push(n as usize),
+        }
+    }
+    symbol_type
+}
+
+impl<'g> Resolver<'g> {
+    pub fn new(globals: &'g mut Globals) -> Self {
+        let mut context = Context::default();
+        add_ext_funcs(&mut context);
+        Self {
+            symbol_tables: vec![context],
+            globals,
+            frame: Layout::default(),
+            locals: vec![],
+            defined: HashMap::new(),
+            undefined_calls: Vec::new(),
+            main_locs: None,
+        }
+    }
+    /// Resolves the whole unit. The functions can use the symbols declared before them,
+    /// a prototype declares a function which is defined later
+    pub fn resolve(&mut self, unit: &mut Unit) {
+        for item in &mut unit.items {
+            match item {
+                Item::Struct(s) => self.resolve_struct(s),
+                Item::Var(v) => {
+                    if let Some(init) = &mut v.init {
+                        self.resolve_init(init);
+                    }
+                    let symbol_type = self.var_type(v);
+                    let addr = self
+                        .globals
+                        .alloc_aligned(symbol_type.size(), symbol_type.align());
+                    self.declare(
+                        v,
+                        symbol_type,
+                        StorageType::MemGlobal,
+                        AddrOffset::Addr(addr),
+                    );
+                    if v.name.as_str() == "main" {
+                        self.main_locs = Some((v.loc, vec![]));
+                    }
+                }
+                Item::Func(f) => self.resolve_func(f),
+            }
+        }
+        for (name, loc) in &self.undefined_calls {
+            if !self.defined.contains_key(name) {
+                let line = self.symbol_tables[0].find_symbol(*name).unwrap().line;
+                panic!(
+                    "Error in {}, function `{}` is declared on line {} but never defined",
+                    loc, name, line
+                );
+            }
+        }
+    }
+
+    /// A program starts with `main`, which must be `int main()` or `void main()` and
+    /// have a body
+    pub fn check_main(&self) {
+        let name = Name::intern("main");
+        let main = match self.symbol_tables[0].find_symbol(name) {
+            Some(main) => main,
+            None => panic!("Error: the program has no `main` function"),
+        };
+        let (loc, arg_locs) = self.main_locs.as_ref().unwrap();
+        if main.class != ClassType::ClsFunc {
+            panic!("Error in {}, `main` must be a function", loc);
+        }
+        match main.symbol_type.type_base {
+            TypeName::TbInt | TypeName::TbVoid if main.symbol_type.num_elements < 0 => {}
+            _ => panic!(
+                "Error in {}, `main` must return `int` or `void`, not `{}`",
+                loc, main.symbol_type
+            ),
+        }
+        if let Some((arg, arg_loc)) = main
+            .am
+            .iter()
+            .flat_map(|am| am.values())
+            .zip(arg_locs)
+            .next()
+        {
+            panic!(
+                "Error in {}, `main` cannot have arguments, found `{}`",
+                arg_loc, arg.name
+            );
+        }
+        if !self.defined.contains_key(&name) {
+            panic!("Error in {}, `main` is declared but never defined", loc);
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.symbol_tables.last().unwrap().depth
+    }
+    fn find_symbol(&self, name: Name) -> Option<&Symbol> {
+        self.symbol_tables
+            .iter()
+            .rev()
+            .find_map(|context| context.find_symbol(name))
+    }
+    /// Adds `symbol` to the innermost context, names must be unique in a context
+    fn add_symbol(&mut self, symbol: Symbol, loc: Loc) {
+        let context = self.symbol_tables.last_mut().unwrap();
+        if let Some(s) = context.find_symbol(symbol.name) {
+            panic!(
+                "Error in {}, symbol `{}` is already defined on line {}",
+                loc, s.name, s.line
+            );
+        }
+        context.add_symbol(symbol);
+    }
+    /// Adds the variable `v` to the innermost context and links it to its symbol
+    fn declare(
+        &mut self,
+        v: &mut VarDecl,
+        symbol_type: SymbolType,
+        storage: StorageType,
+        ao: AddrOffset,
+    ) {
+        let symbol = Symbol {
+            name: v.name,
+            class: ClassType::ClsVar,
+            storage,
+            symbol_type,
+            depth: self.depth(),
+            line: v.loc.line,
+            ao,
+            ..Default::default()
+        };
+        v.sym = Some(Box::new(symbol.clone()));
+        self.add_symbol(symbol, v.loc);
+    }
+    /// The initializer is resolved before its variable is declared, it cannot use it
+    fn resolve_init(&mut self, init: &mut Init) {
+        match init {
+            Init::Expr(e) => self.resolve_expr(e),
+            Init::List(items, _) => items.iter_mut().for_each(|i| self.resolve_init(i)),
+        }
+    }
+    /// The type of a variable, which must have a size unless it is a function argument.
+    /// An array without size gets it from its initializer: `{1, 2}` has 2 elements, `"hi"` 3
+    fn var_type(&self, v: &VarDecl) -> SymbolType {
+        let mut symbol_type = symbol_type(&v.ty, &self.symbol_tables[0], v.loc);
+        if symbol_type.num_elements == 0 {
+            symbol_type.num_elements = match &v.init {
+                Some(Init::List(items, _)) => items.len() as isize,
+                Some(Init::Expr(Expr {
+                    kind: ExprKind::Str(s),
+                    ..
+                })) => s.len() as isize + 1,
+                _ => 0,
+            };
+        }
+        if symbol_type.num_elements == 0 {
+            panic!("Error in {}, the array `{}` needs a size", v.loc, v.name);
+        }
+        symbol_type
+    }
+
+    /// declStruct: the members are placed one after another, each one at its alignment
+    fn resolve_struct(&mut self, s: &StructDecl) {
+        let mut symbol = Symbol {
+            name: s.name,
+            class: ClassType::ClsStruct,
+            storage: StorageType::MemGlobal,
+            depth: self.depth(),
+            line: s.loc.line,
+            am: Some(IndexMap::new()),
+            ..Default::default()
+        };
+        let mut layout = Layout::default();
+        for m in &s.members {
+            if let TypeBase::Struct(name) = m.ty.base {
+                if name == s.name {
+                    panic!(
+                        "Error in {}, struct `{}` cannot contain itself",
+                        m.loc, name
+                    );
+                }
+            }
+            if m.init.is_some() {
+                panic!(
+                    "Error in {}, the member `{}` cannot have an initializer",
+                    m.loc, m.name
+                );
+            }
+            let symbol_type = self.var_type(m);
+            if let Some(prev) = symbol.find_symbol(m.name) {
+                panic!(
+                    "Error in {}, member `{}` is already defined on line {}",
+                    m.loc, m.name, prev.line
+                );
+            }
+            let offset = layout.place(&symbol_type);
+            symbol.add_symbol(Symbol {
+                name: m.name,
+                class: ClassType::ClsVar,
+                storage: StorageType::MemStruct,
+                symbol_type,
+                depth: self.depth() + 1,
+                line: m.loc.line,
+                ao: AddrOffset::Offset(offset as isize),
+                ..Default::default()
+            });
+        }
+        self.add_symbol(symbol, s.loc);
+    }
+
+    /// declFunc: the arguments are below the return address and the saved frame pointer,
+    /// the locals are above the frame pointer
+    fn resolve_func(&mut self, f: &mut FuncDecl) {
+        let ret = symbol_type(&f.ret, &self.symbol_tables[0], f.loc);
+        let mut symbol = Symbol {
+            name: f.name,
+            class: ClassType::ClsFunc,
+            storage: StorageType::MemGlobal,
+            symbol_type: ret,
+            depth: self.depth(),
+            line: f.loc.line,
+            am: Some(IndexMap::new()),
+            ..Default::default()
+        };
+        self.symbol_tables
+            .push(Context::new(StorageType::MemLocal, 1));
+        let arg_types: Vec<SymbolType> = f
+            .args
+            .iter()
+            .map(|a| {
+                let mut t = symbol_type(&a.ty, &self.symbol_tables[0], a.loc);
+                // An array is passed by address, the size of its first dimension is not kept
+                if t.num_elements > 0 {
+                    t.num_elements = 0;
+                }
+                t
+            })
+            .collect();
+        let offsets = arg_offsets(&arg_types);
+        for ((a, symbol_type), offset) in f.args.iter_mut().zip(arg_types).zip(offsets) {
+            self.declare(
+                a,
+                symbol_type,
+                StorageType::MemArg,
+                AddrOffset::Offset(offset),
+            );
+            symbol.add_symbol(self.symbol_tables[1].find_symbol(a.name).unwrap().clone());
+        }
+        self.declare_func(symbol, f);
+
+        if let Some(body) = &mut f.body {
+            self.frame = Layout::default();
+            // The body shares its context with the arguments
+            match &mut body.kind {
+                StmKind::Block(stms) => stms.iter_mut().for_each(|s| self.resolve_stm(s)),
+                _ => self.resolve_stm(body),
+            }
+            f.frame = self.frame.size;
+            f.locals = std::mem::take(&mut self.locals);
+        }
+        self.symbol_tables.pop();
+    }
+    /// Adds the function to the global context before its body, so it can call itself.
+    /// A previous prototype must have the same signature and is replaced by the definition
+    fn declare_func(&mut self, symbol: Symbol, f: &FuncDecl) {
+        let globals = &mut self.symbol_tables[0];
+        match globals.find_symbol(f.name) {
+            Some(prev) if prev.class == ClassType::ClsFunc => {
+                if signature(prev) != signature(&symbol) {
+                    panic!(
+                        "Error in {}, conflicting types for `{}`: `{}` here, `{}` on line {}",
+                        f.loc,
+                        f.name,
+                        signature(&symbol),
+                        signature(prev),
+                        prev.line
+                    );
+                }
+                if let (Some(line), Some(_)) = (self.defined.get(&f.name), &f.body) {
+                    panic!(
+                        "Error in {}, function `{}` is already defined on line {}",
+                        f.loc, f.name, line
+                    );
+                }
+                if f.body.is_some() {
+                    globals.update_symbol(symbol);
+                }
+            }
+            Some(prev) => panic!(
+                "Error in {}, symbol `{}` is already defined on line {}",
+                f.loc, prev.name, prev.line
+            ),
+            None => globals.add_symbol(symbol),
+        }
+        // The symbol of a function is its first declaration, replaced by its definition
+        if f.name.as_str() == "main" && (self.main_locs.is_none() || f.body.is_some()) {
+            self.main_locs = Some((f.loc, f.args.iter().map(|a| a.loc).collect()));
+        }
+        if f.body.is_some() {
+            self.defined.insert(f.name, f.loc.line);
+        }
+    }
+
+    fn resolve_stm(&mut self, stm: &mut Stm) {
+        match &mut stm.kind {
+            StmKind::Block(stms) => {
+                let depth = self.depth() + 1;
+                self.symbol_tables
+                    .push(Context::new(StorageType::MemLocal, depth));
+                stms.iter_mut().for_each(|s| self.resolve_stm(s));
+                self.symbol_tables.pop();
+            }
+            StmKind::Var(v) => {
+                if let Some(init) = &mut v.init {
+                    self.resolve_init(init);
+                }
+                let symbol_type = self.var_type(v);
+                let offset = self.frame.place(&symbol_type) as isize;
+                self.declare(
+                    v,
+                    symbol_type,
+                    StorageType::MemLocal,
+                    AddrOffset::Offset(offset),
+                );
+                self.locals.push(*v.sym.clone().unwrap());
+            }
+            StmKind::If { cond, then, els } => {
+                self.resolve_expr(cond);
+                self.resolve_stm(then);
+                if let Some(els) = els {
+                    self.resolve_stm(els);
+                }
+            }
+            StmKind::While { cond, body } | StmKind::DoWhile { body, cond } => {
+                self.resolve_expr(cond);
+                self.resolve_stm(body);
+            }
+            StmKind::Switch { value, cases } => {
+                self.resolve_expr(value);
+                let depth = self.depth() + 1;
+                self.symbol_tables
+                    .push(Context::new(StorageType::MemLocal, depth));
+                for case in cases {
+                    if let Some(value) = &mut case.value {
+                        self.resolve_expr(value);
+                    }
+                    case.stms.iter_mut().for_each(|s| self.resolve_stm(s));
+                }
+                self.symbol_tables.pop();
+            }
+            StmKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                for e in [init, cond, step].iter_mut().filter_map(|e| e.as_mut()) {
+                    self.resolve_expr(e);
+                }
+                self.resolve_stm(body);
+            }
+            StmKind::Return(Some(e)) | StmKind::Expr(e) => self.resolve_expr(e),
+            StmKind::Break | StmKind::Continue | StmKind::Return(None) | StmKind::Empty => {}
+        }
+    }
+
+    fn resolve_expr(&mut self, e: &mut Expr) {
+        match &mut e.kind {
+            ExprKind::Int(_) | ExprKind::Real(_) | ExprKind::Char(_) | ExprKind::Str(_) => {}
+            ExprKind::Id(name) | ExprKind::Call(name, _) => {
+                let s = match self.find_symbol(*name) {
+                    Some(s) => s.clone(),
+                    None => panic!("Error in {}, undefined symbol: `{}`", e.loc, name),
+                };
+                if s.class == ClassType::ClsFunc && !self.defined.contains_key(name) {
+                    self.undefined_calls.push((*name, e.loc));
+                }
+                e.sym = Some(Box::new(s));
+            }
+            ExprKind::Index(a, b)
+            | ExprKind::Binary(_, a, b)
+            | ExprKind::Assign(a, b)
+            | ExprKind::OpAssign(_, a, b) => {
+                self.resolve_expr(a);
+                self.resolve_expr(b);
+            }
+            ExprKind::Member(a, _)
+            | ExprKind::Unary(_, a)
+            | ExprKind::IncDec { operand: a, .. }
+            | ExprKind::Cast(_, a)
+            | ExprKind::Conv(a) => self.resolve_expr(a),
+        }
+        if let ExprKind::Call(_, args) = &mut e.kind {
+            args.iter_mut().for_each(|a| self.resolve_expr(a));
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::asdr::SyntaxAnalyser;
+    use crate::intern::Name;
+    use crate::lexer::Lexer;
+    use crate::mv::Globals;
+    use crate::resolve::{signature, Resolver};
+    use crate::symbols::*;
+
+    fn resolve(src: &str) -> Vec<Context> {
+        let mut unit = SyntaxAnalyser::new(Lexer::new(src, "").get_tokens()).analyse_syntax();
+        let mut globals = Globals::new();
+        let mut resolver = Resolver::new(&mut globals);
+        resolver.resolve(&mut unit);
+        resolver.symbol_tables
+    }
+    fn check_main(src: &str) {
+        check_main_in(src, "");
+    }
+    fn check_main_in(src: &str, file: &str) {
+        let mut unit = SyntaxAnalyser::new(Lexer::new(src, file).get_tokens()).analyse_syntax();
+        let mut globals = Globals::new();
+        let mut resolver = Resolver::new(&mut globals);
+        resolver.resolve(&mut unit);
+        resolver.check_main();
+    }
+    #[test]
+    fn resolve_offsets() {
+        let tables = resolve(
+            "struct P { char c; double d; }; struct P p; int f(int a, char b[]) { int x; { double y; } }",
+        );
+        let p = tables[0].find_symbol(Name::intern("P")).unwrap();
+        assert_eq!(p.find_symbol(Name::intern("d")).unwrap().ao.get_offset(), 8);
+        let f = tables[0].find_symbol(Name::intern("f")).unwrap();
+        // a, b, the return address and the frame pointer
+        assert_eq!(
+            f.find_symbol(Name::intern("a")).unwrap().ao.get_offset(),
+            -32
+        );
+        assert_eq!(
+            f.find_symbol(Name::intern("b")).unwrap().ao.get_offset(),
+            -24
+        );
+        let var = tables[0].find_symbol(Name::intern("p")).unwrap();
+        assert_eq!(var.symbol_type.size(), 16);
+    }
+    #[test]
+    #[should_panic(expected = "undefined symbol: `y`")]
+    fn resolve_block_scope() {
+        resolve("void f() { { int y; } y = 1; }");
+    }
+    #[test]
+    #[should_panic(expected = "symbol `a` is already defined on line 1")]
+    fn resolve_arg_redefined() {
+        resolve("void f(int a) { int a; }");
+    }
+    #[test]
+    fn resolve_prototype() {
+        let tables = resolve(
+            "int odd(int n);\nint even(int n) { return odd(n); }\nint odd(int n) { return even(n); }",
+        );
+        let odd = tables[0].find_symbol(Name::intern("odd")).unwrap();
+        // The definition replaces the prototype
+        assert_eq!(odd.line, 3);
+        assert!(odd.find_symbol(Name::intern("n")).is_some());
+    }
+    #[test]
+    #[should_panic(expected = "conflicting types for `f`: `int(char)` here, `int(int)` on line 1")]
+    fn resolve_prototype_mismatch() {
+        resolve("int f(int x);\nint f(char x) { return x; }");
+    }
+    #[test]
+    #[should_panic(expected = "function `f` is already defined on line 1")]
+    fn resolve_func_redefined() {
+        resolve("int f() { return 1; }\nint f(); int f() { return 2; }");
+    }
+    #[test]
+    #[should_panic(expected = "function `f` is declared on line 1 but never defined")]
+    fn resolve_prototype_undefined() {
+        resolve("int f();\nvoid g() { f(); }");
+    }
+    #[test]
+    fn resolve_main() {
+        check_main("int main() { return 0; }");
+        check_main("void main();\nvoid main() {}");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 2, `main` is declared but never defined")]
+    fn resolve_main_prototype_only() {
+        check_main("int f() { return 0; }\nint main();");
+    }
+    #[test]
+    #[should_panic(expected = "Error: the program has no `main` function")]
+    fn resolve_main_missing() {
+        check_main("int f() { return 0; }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 2, `main` must return `int` or `void`, not `double`")]
+    fn resolve_main_return() {
+        check_main("\ndouble main() { return 0; }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, `main` cannot have arguments, found `argc`")]
+    fn resolve_main_args() {
+        check_main("int main(int argc) { return 0; }");
+    }
+    #[test]
+    #[should_panic(expected = "`main` must be a function")]
+    fn resolve_main_variable() {
+        check_main("int main;");
+    }
+    #[test]
+    #[should_panic(expected = "Error in prog.c:3, `main` cannot have arguments, found `argc`")]
+    fn resolve_main_args_in_file() {
+        check_main_in(
+            "int main(int a);\nint main(\nint argc) { return 0; }",
+            "prog.c",
+        );
+    }
+    #[test]
+    #[should_panic(expected = "Error in prog.c:2, `main` is declared but never defined")]
+    fn resolve_main_prototype_in_file() {
+        check_main_in("int f();\nvoid main();", "prog.c");
+    }
+    #[test]
+    fn resolve_array_size_expr() {
+        let tables =
+            resolve("char buf[10 + 1]; int a[(int)'c' - 'a' + 2 * 3]; void f(char s[]) {}");
+        let size = |name| {
+            tables[0]
+                .find_symbol(Name::intern(name))
+                .unwrap()
+                .symbol_type
+                .num_elements
+        };
+        assert_eq!(size("buf"), 11);
+        assert_eq!(size("a"), 8);
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, the size of an array must be positive, not -2")]
+    fn resolve_array_size_negative() {
+        resolve("int a[1 - 3];");
+    }
+    #[test]
+    #[should_panic(expected = "the size of an array must be positive, not 0")]
+    fn resolve_array_size_zero() {
+        resolve("void f() { int a[2 < 1]; }");
+    }
+    #[test]
+    #[should_panic(expected = "the size of an array must be an int constant expression")]
+    fn resolve_array_size_not_constant() {
+        resolve("int n; int a[n * 2];");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, division by zero")]
+    fn resolve_array_size_division_by_zero() {
+        resolve("int a[4 / (2 - 2)];");
+    }
+    #[test]
+    fn resolve_multi_dimensional_arrays() {
+        let tables = resolve("double m[2][3 + 1][5]; int f(int a[][4], char r[8]) {}");
+        let m = &tables[0]
+            .find_symbol(Name::intern("m"))
+            .unwrap()
+            .symbol_type;
+        assert_eq!((m.num_elements, &m.dims[..]), (2, &[4, 5][..]));
+        assert_eq!(m.to_string(), "double[2][4][5]");
+        assert_eq!(m.size(), 2 * 4 * 5 * 8);
+        assert_eq!(m.elem().to_string(), "double[4][5]");
+        assert_eq!(m.elem().elem().elem().to_string(), "double");
+        let f = tables[0].find_symbol(Name::intern("f")).unwrap();
+        assert_eq!(signature(f), "int(int[][4], char[])");
+    }
+    #[test]
+    #[should_panic(expected = "only the first dimension of an array can be left without size")]
+    fn resolve_array_inner_dimension_without_size() {
+        resolve("void f(int a[3][]) {}");
+    }
+}
@@ -1,5 +1,10 @@
-use crate::lexer::Token;
+use crate::ast::Loc;
+use crate::intern::Name;
+use crate::layout::align_to;
+use crate::mv::{ExtFunc, VirtualMachine};
 use indexmap::map::IndexMap;
+use std::fmt;
+use std::mem::{align_of, size_of};
 
 /// Data types enum.
 #[derive(Clone, Debug, PartialEq)]
@@ -32,7 +37,7 @@ pub enum StorageType {
 #[derive(Clone, Debug, PartialEq)]
 pub enum CtVal {
     IntChar(isize),
-    Double(f32),
+    Double(f64),
     String(String),
 }
 impl CtVal {
@@ -42,7 +47,7 @@ impl CtVal {
         }
         0
     }
-    pub fn get_double(&self) -> f32 {
+    pub fn get_double(&self) -> f64 {
         if let CtVal::Double(d) = self {
             return *d;
         }
@@ -55,29 +60,22 @@ impl CtVal {
         String::from("")
     }
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct RetVal {
     pub symbol_type: Option<SymbolType>,
     pub is_lval: bool,
     pub is_ctval: bool,
     pub ctval: Option<CtVal>,
 }
-impl Default for RetVal {
-    fn default() -> Self {
-        Self {
-            symbol_type: None,
-            is_lval: false,
-            is_ctval: false,
-            ctval: None,
-        }
-    }
-}
 
 #[derive(Clone, Debug)]
 pub struct SymbolType {
     pub type_base: TypeName,                // Tb*
     pub struct_symbol: Option<Box<Symbol>>, // for TbStruct
     pub num_elements: isize, //  >0 for an array of given size, 0 for an array without size, <0 if it's not an array
+    /// The sizes of the other dimensions of an array of arrays: `int m[3][4]` has
+    /// `num_elements` 3 and `dims` [4]
+    pub dims: Vec<usize>,
 }
 impl Default for SymbolType {
     fn default() -> Self {
@@ -85,6 +83,7 @@ impl Default for SymbolType {
             type_base: TypeName::TbVoid,
             struct_symbol: None,
             num_elements: -1,
+            dims: vec![],
         }
     }
 }
@@ -95,65 +94,148 @@ impl SymbolType {
             type_base,
             struct_symbol: None,
             num_elements,
+            dims: vec![],
         }
     }
-    pub fn cast(&self, dst: SymbolType, token: &Token) {
+    /// The type of the elements of an array, with one dimension less
+    pub fn elem(&self) -> SymbolType {
+        SymbolType {
+            num_elements: self.dims.first().map_or(-1, |&n| n as isize),
+            dims: self.dims.iter().skip(1).copied().collect(),
+            ..self.clone()
+        }
+    }
+    /// Checks that a value of type `src` can be converted to this type
+    pub fn cast(&self, src: &SymbolType, loc: &Loc) {
         if self.num_elements > -1 {
-            if dst.num_elements > -1 {
-                if self.type_base != dst.type_base {
-                    panic!("Error at line {}: An array cannot be converted to an array of another type", token.line);
+            if src.num_elements > -1 {
+                // Only the first dimension may differ
+                if self.type_base != src.type_base || self.dims != src.dims {
+                    panic!(
+                        "Error in {}, An array cannot be converted to an array of another type",
+                        loc
+                    );
                 }
             } else {
                 panic!(
-                    "Error at line {}: An array cannot be converted to a non-array",
-                    token.line
+                    "Error in {}, A non-array cannot be converted to an array",
+                    loc
                 );
             }
-        } else if dst.num_elements > -1 {
+        } else if src.num_elements > -1 {
             panic!(
-                "Error at line {}: A non-array cannot be converted to an array",
-                token.line
+                "Error in {}, An array cannot be converted to a non-array",
+                loc
             );
         }
         match self.type_base {
-            TypeName::TbChar | TypeName::TbInt | TypeName::TbDouble => match dst.type_base {
+            TypeName::TbChar | TypeName::TbInt | TypeName::TbDouble => match src.type_base {
                 TypeName::TbChar | TypeName::TbInt | TypeName::TbDouble => return,
                 _ => {}
             },
-            TypeName::TbStruct => {
-                if dst.type_base == TypeName::TbStruct {
-                    // TODO check for None
-                    if self.struct_symbol.as_ref().unwrap().name != dst.struct_symbol.unwrap().name
-                    {
-                        panic!(
-                            "Error at line {}: A structure cannot be converted to another one",
-                            token.line
-                        );
-                    }
-                    return;
+            TypeName::TbStruct if src.type_base == TypeName::TbStruct => {
+                // TODO check for None
+                if self.struct_symbol.as_ref().unwrap().name
+                    != src.struct_symbol.as_ref().unwrap().name
+                {
+                    panic!(
+                        "Error in {}, A structure cannot be converted to another one",
+                        loc
+                    );
                 }
+                return;
             }
             _ => {}
         }
-        panic!("Error at line {}: Incompatible types", &token.line);
+        panic!("Error in {}, Incompatible types", loc);
     }
-    pub fn get_arith_type(self, t: SymbolType) -> Option<SymbolType> {
-        match t.type_base {
-            TypeName::TbChar => match t.type_base {
-                TypeName::TbChar | TypeName::TbDouble | TypeName::TbInt => Some(t),
-                _ => None,
-            },
-            TypeName::TbInt => match t.type_base {
-                TypeName::TbChar => Some(self),
-                TypeName::TbDouble | TypeName::TbInt => Some(t),
-                _ => None,
+    /// Size in bytes of a value of this type. Arrays without size are passed by address.
+    /// A struct ends after its last member, padded to its alignment
+    pub fn size(&self) -> usize {
+        match self.num_elements {
+            n if n > 0 => return self.elem().size() * n as usize,
+            0 => return size_of::<*const ()>(),
+            _ => {}
+        }
+        match self.type_base {
+            TypeName::TbInt => size_of::<i64>(),
+            TypeName::TbDouble => size_of::<f64>(),
+            TypeName::TbChar => size_of::<u8>(),
+            TypeName::TbVoid => 0,
+            TypeName::TbStruct => match self.members().and_then(|m| m.values().last()) {
+                Some(last) => align_to(
+                    last.ao.get_offset() as usize + last.symbol_type.size(),
+                    self.elem_align(),
+                ),
+                None => 0,
             },
-            TypeName::TbDouble => match t.type_base {
-                TypeName::TbChar | TypeName::TbDouble | TypeName::TbInt => Some(self),
+        }
+    }
+    /// Alignment in bytes of a value of this type
+    pub fn align(&self) -> usize {
+        match self.num_elements {
+            0 => align_of::<*const ()>(),
+            _ => self.elem_align(),
+        }
+    }
+    /// The alignment of the elements, a struct has the largest one of its members
+    fn elem_align(&self) -> usize {
+        match self.type_base {
+            TypeName::TbInt => align_of::<i64>(),
+            TypeName::TbDouble => align_of::<f64>(),
+            TypeName::TbChar | TypeName::TbVoid => align_of::<u8>(),
+            TypeName::TbStruct => self
+                .members()
+                .into_iter()
+                .flat_map(|m| m.values())
+                .map(|m| m.symbol_type.align())
+                .max()
+                .unwrap_or(1),
+        }
+    }
+    fn members(&self) -> Option<&IndexMap<Name, Symbol>> {
+        self.struct_symbol.as_ref().and_then(|s| s.am.as_ref())
+    }
+    /// The type of an arithmetic operation between `self` and `t`, the larger of the two
+    /// in `char < int < double`, with `char` promoted to `int`. `None` if one of them is
+    /// not a number
+    pub fn get_arith_type(self, t: SymbolType) -> Option<SymbolType> {
+        fn rank(t: &SymbolType) -> Option<u8> {
+            match t.type_base {
+                _ if t.num_elements > -1 => None,
+                TypeName::TbChar => Some(0),
+                TypeName::TbInt => Some(1),
+                TypeName::TbDouble => Some(2),
                 _ => None,
+            }
+        }
+        let larger = if rank(&self)? >= rank(&t)? { self } else { t };
+        match larger.type_base {
+            TypeName::TbChar => Some(SymbolType::new(TypeName::TbInt, -1)),
+            _ => Some(larger),
+        }
+    }
+}
+
+/// `int`, `char[]`, `struct Pt[10]`
+impl fmt::Display for SymbolType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.type_base {
+            TypeName::TbInt => write!(f, "int")?,
+            TypeName::TbDouble => write!(f, "double")?,
+            TypeName::TbChar => write!(f, "char")?,
+            TypeName::TbVoid => write!(f, "void")?,
+            TypeName::TbStruct => match &self.struct_symbol {
+                Some(s) => write!(f, "struct {}", s.name)?,
+                None => write!(f, "struct")?,
             },
-            _ => None,
         }
+        match self.num_elements {
+            n if n > 0 => write!(f, "[{}]", n)?,
+            0 => write!(f, "[]")?,
+            _ => {}
+        }
+        self.dims.iter().try_for_each(|n| write!(f, "[{}]", n))
     }
 }
 
@@ -187,13 +269,13 @@ impl AddrOffset {
 #[derive(Clone, Debug)]
 #[repr(C)]
 pub struct Symbol {
-    pub name: String,
+    pub name: Name,
     pub class: ClassType,
     pub storage: StorageType,
     pub symbol_type: SymbolType, // Tells us the data type and bonus info if it's a struct
     pub depth: usize,            // 0-global, 1-in function, 2... - nested blocks in function
     pub line: usize,
-    pub am: Option<IndexMap<String, Symbol>>,
+    pub am: Option<IndexMap<Name, Symbol>>,
     pub table: usize, // Index in the big table of the parent table
     //ao: AddrOffset,
     pub ao: AddrOffset,
@@ -201,7 +283,7 @@ pub struct Symbol {
 impl Default for Symbol {
     fn default() -> Self {
         Self {
-            name: String::from(""),
+            name: Name::default(),
             class: ClassType::ClsVar,
             storage: StorageType::MemGlobal,
             symbol_type: SymbolType::default(),
@@ -214,15 +296,15 @@ impl Default for Symbol {
     }
 }
 impl Symbol {
-    pub fn find_symbol(&self, symbol_name: &str) -> Option<Symbol> {
+    /// Searches for a member or argument of this symbol
+    pub fn find_symbol(&self, symbol_name: Name) -> Option<&Symbol> {
         match &self.am {
-            Some(am) => am.get(symbol_name).cloned(),
+            Some(am) => am.get(&symbol_name),
             None => None,
         }
     }
     pub fn add_symbol(&mut self, symbol: Symbol) {
-        let symbol = symbol;
-        match self.find_symbol(&symbol.name) {
+        match self.find_symbol(symbol.name) {
             Some(s) => panic!(
                 "Error at line {}: Symbol `{}` is already defined on line {}",
                 symbol.line, s.name, s.line
@@ -230,20 +312,20 @@ impl Symbol {
             None => {
                 //symbol.table = Some(Rc::new(self.clone())); // Make this a pointer?
                 if let Some(am) = &mut self.am {
-                    am.insert(String::from(&symbol.name), symbol);
+                    am.insert(symbol.name, symbol);
                 }
             }
         }
     }
     pub fn update_symbol(&mut self, symbol: Symbol) {
         if let Some(am) = &mut self.am {
-            am.insert(String::from(&symbol.name), symbol);
+            am.insert(symbol.name, symbol);
         }
     }
 }
 #[derive(Debug)]
 pub struct Context {
-    pub symbols: IndexMap<String, Symbol>,
+    pub symbols: IndexMap<Name, Symbol>,
     pub storage: StorageType,
     pub depth: usize,
 }
@@ -265,25 +347,23 @@ impl Context {
         }
     }
     pub fn add_symbol(&mut self, symbol: Symbol) {
-        let symbol = symbol;
-        match self.find_symbol(&symbol.name) {
+        match self.find_symbol(symbol.name) {
             Some(s) => panic!(
                 "Error at line {}: Symbol `{}` is already defined on line {}",
                 symbol.line, s.name, s.line
             ),
             None => {
                 //symbol.table = Some(Rc::new(self.clone())); // Make this a pointer?
-                self.symbols.insert(String::from(&symbol.name), symbol);
+                self.symbols.insert(symbol.name, symbol);
             }
         };
     }
     /// Searches for symbol in this table.
-    ///  If not found searches in the parent table
-    pub fn find_symbol(&self, symbol_name: &str) -> Option<Symbol> {
-        self.symbols.get(symbol_name).cloned()
+    pub fn find_symbol(&self, symbol_name: Name) -> Option<&Symbol> {
+        self.symbols.get(&symbol_name)
     }
     pub fn update_symbol(&mut self, symbol: Symbol) {
-        self.symbols.insert(String::from(&symbol.name), symbol);
+        self.symbols.insert(symbol.name, symbol);
     }
     pub fn clear(&mut self) {
         self.symbols.clear();
@@ -297,7 +377,7 @@ pub fn add_ext_func(
     addr: *const (),
 ) -> Symbol {
     let s = Symbol {
-        name: String::from(name),
+        name: Name::intern(name),
         symbol_type,
         am: Some(IndexMap::new()),
         class: ClassType::ClsExtFunc,
@@ -311,10 +391,10 @@ pub fn add_ext_func(
 }
 pub fn add_func_arg(func: &mut Symbol, name: &str, symbol_type: SymbolType) -> Symbol {
     let s = Symbol {
-        name: String::from(name),
+        name: Name::intern(name),
         symbol_type,
         class: ClassType::ClsVar,
-        storage: StorageType::MemLocal, // MemArg?
+        storage: StorageType::MemArg,
         ..Default::default()
     };
     func.add_symbol(s.clone());
@@ -326,7 +406,7 @@ pub fn add_ext_funcs(context: &mut Context) {
         "put_s",
         SymbolType::new(TypeName::TbVoid, -1),
         context,
-        put_s as *const (),
+        put_s as ExtFunc as *const (),
     );
     add_func_arg(&mut s, "s", SymbolType::new(TypeName::TbChar, 0));
     context.update_symbol(s);
@@ -335,7 +415,7 @@ pub fn add_ext_funcs(context: &mut Context) {
         "get_s",
         SymbolType::new(TypeName::TbVoid, -1),
         context,
-        get_s as *const (),
+        get_s as ExtFunc as *const (),
     );
     add_func_arg(&mut s, "s", SymbolType::new(TypeName::TbChar, 0));
     context.update_symbol(s);
@@ -344,7 +424,7 @@ pub fn add_ext_funcs(context: &mut Context) {
         "put_i",
         SymbolType::new(TypeName::TbVoid, -1),
         context,
-        put_i as *const (),
+        put_i as ExtFunc as *const (),
     );
     add_func_arg(&mut s, "i", SymbolType::new(TypeName::TbInt, -1));
     context.update_symbol(s);
@@ -353,7 +433,7 @@ pub fn add_ext_funcs(context: &mut Context) {
         "get_i",
         SymbolType::new(TypeName::TbInt, -1),
         context,
-        get_i as *const (),
+        get_i as ExtFunc as *const (),
     );
     context.update_symbol(_s);
 
@@ -361,7 +441,7 @@ pub fn add_ext_funcs(context: &mut Context) {
         "put_d",
         SymbolType::new(TypeName::TbVoid, -1),
         context,
-        put_d as *const (),
+        put_d as ExtFunc as *const (),
     );
     add_func_arg(&mut s, "s", SymbolType::new(TypeName::TbDouble, -1));
     context.update_symbol(s);
@@ -370,7 +450,7 @@ pub fn add_ext_funcs(context: &mut Context) {
         "get_d",
         SymbolType::new(TypeName::TbDouble, -1),
         context,
-        get_d as *const (),
+        get_d as ExtFunc as *const (),
     );
     context.update_symbol(_s);
 
@@ -378,7 +458,7 @@ pub fn add_ext_funcs(context: &mut Context) {
         "put_c",
         SymbolType::new(TypeName::TbVoid, -1),
         context,
-        put_c as *const (),
+        put_c as ExtFunc as *const (),
     );
     add_func_arg(&mut s, "c", SymbolType::new(TypeName::TbChar, -1));
     context.update_symbol(s);
@@ -387,7 +467,7 @@ pub fn add_ext_funcs(context: &mut Context) {
         "get_c",
         SymbolType::new(TypeName::TbChar, -1),
         context,
-        get_s as *const (),
+        get_c as ExtFunc as *const (),
     );
     context.update_symbol(_s);
 
@@ -395,34 +475,69 @@ pub fn add_ext_funcs(context: &mut Context) {
         "seconds",
         SymbolType::new(TypeName::TbDouble, -1),
         context,
-        seconds as *const (),
+        seconds as ExtFunc as *const (),
     );
     context.update_symbol(_s);
 }
-fn put_s() {}
-fn get_s(_s: &str) {}
-fn put_i() {
-    println!("hello from ext func");
+fn read_line() -> String {
+    let mut line = String::new();
+    std::io::stdin()
+        .read_line(&mut line)
+        .expect("Error reading the standard input");
+    line.trim_end_matches(&['\r', '\n'][..]).to_string()
+}
+/// void put_s(char s[]): prints the string which ends with `\0`
+fn put_s(vm: &mut VirtualMachine) {
+    let addr: *const u8 = vm.pop();
+    let text = unsafe { std::ffi::CStr::from_ptr(addr as *const std::os::raw::c_char) };
+    vm.print(&text.to_string_lossy());
+}
+/// void get_s(char s[]): reads a line into `s`
+fn get_s(vm: &mut VirtualMachine) {
+    let addr: *mut u8 = vm.pop();
+    let line = read_line();
+    unsafe {
+        std::ptr::copy_nonoverlapping(line.as_ptr(), addr, line.len());
+        *addr.add(line.len()) = 0;
+    }
+}
+fn put_i(vm: &mut VirtualMachine) {
+    let i: i64 = vm.pop();
+    vm.print(&i.to_string());
 }
-fn get_i() -> i64 {
-    0
+fn get_i(vm: &mut VirtualMachine) {
+    let i: i64 = read_line().trim().parse().unwrap_or(0);
+    vm.push(i);
 }
-fn put_d() {}
-fn get_d() -> f64 {
-    0.
+fn put_d(vm: &mut VirtualMachine) {
+    let d: f64 = vm.pop();
+    vm.print(&d.to_string());
 }
-fn put_c() {}
-fn get_c() -> char {
-    0 as char
+fn get_d(vm: &mut VirtualMachine) {
+    let d: f64 = read_line().trim().parse().unwrap_or(0.);
+    vm.push(d);
 }
-fn seconds() -> f64 {
-    0.
+fn put_c(vm: &mut VirtualMachine) {
+    let c: u8 = vm.pop();
+    vm.print(&(c as char).to_string());
 }
-pub fn require_symbol(contexts: &Vec<Context>, name: &str) -> Symbol {
+fn get_c(vm: &mut VirtualMachine) {
+    let c = read_line().bytes().next().unwrap_or(0);
+    vm.push(c);
+}
+/// double seconds(): the seconds since the Unix epoch
+fn seconds(vm: &mut VirtualMachine) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.);
+    vm.push(now);
+}
+/// Searches the contexts from the innermost one and panics if `name` is not found
+pub fn require_symbol(contexts: &[Context], name: Name) -> &Symbol {
     for context in contexts.iter().rev() {
-        match context.find_symbol(name) {
-            Some(s) => return s,
-            None => continue,
+        if let Some(s) = context.find_symbol(name) {
+            return s;
         }
     }
     panic!("Undefined symbol: {}", name);
@@ -433,43 +548,47 @@ pub mod tests {
     #[test]
     fn symbol_add_two() {
         let s1 = Symbol {
-            name: String::from("x"),
+            name: Name::intern("x"),
             class: ClassType::ClsVar,
             storage: StorageType::MemArg,
             symbol_type: SymbolType {
                 type_base: TypeName::TbChar,
                 struct_symbol: None,
                 num_elements: -1,
+                dims: vec![],
             },
             ..Default::default()
         };
         let s2 = Symbol {
-            name: String::from("y"),
+            name: Name::intern("y"),
             ..s1.clone()
         };
         let mut st = Context::default();
-        let _all_tables = vec![&st];
+        let _all_tables = [&st];
         st.add_symbol(s1);
         st.add_symbol(s2);
         dbg!(&st);
-        assert_eq!((&st.symbols).len(), 2);
+        assert_eq!(st.symbols.len(), 2);
+        let y = st.find_symbol(Name::intern("y")).unwrap();
+        assert_eq!(y.name.as_str(), "y");
     }
     #[test]
     #[should_panic]
     fn symbol_add_twice() {
         let s1 = Symbol {
-            name: String::from("x"),
+            name: Name::intern("x"),
             class: ClassType::ClsVar,
             storage: StorageType::MemArg,
             symbol_type: SymbolType {
                 type_base: TypeName::TbChar,
                 struct_symbol: None,
                 num_elements: -1,
+                dims: vec![],
             },
             ..Default::default()
         };
         let s2 = Symbol {
-            name: String::from("x"),
+            name: Name::intern("x"),
             ..s1.clone()
         };
         let mut st = Context::default();
@@ -477,4 +596,28 @@ pub mod tests {
         st.add_symbol(s2);
         dbg!(&st);
     }
+    #[test]
+    fn symbol_arith_type() {
+        use TypeName::*;
+        let t = |base| SymbolType::new(base, -1);
+        let cases = [
+            (TbChar, TbChar, Some(TbInt)),
+            (TbChar, TbInt, Some(TbInt)),
+            (TbChar, TbDouble, Some(TbDouble)),
+            (TbInt, TbChar, Some(TbInt)),
+            (TbInt, TbInt, Some(TbInt)),
+            (TbInt, TbDouble, Some(TbDouble)),
+            (TbDouble, TbChar, Some(TbDouble)),
+            (TbDouble, TbInt, Some(TbDouble)),
+            (TbDouble, TbDouble, Some(TbDouble)),
+            (TbInt, TbVoid, None),
+            (TbStruct, TbInt, None),
+        ];
+        for (l, r, expected) in cases {
+            let result = t(l.clone()).get_arith_type(t(r.clone()));
+            assert_eq!(result.map(|t| t.type_base), expected, "{:?} {:?}", l, r);
+        }
+        let array = SymbolType::new(TbInt, 3);
+        assert!(array.get_arith_type(t(TbInt)).is_none());
+    }
 }
@@ -0,0 +1,874 @@
+use crate::ast::*;
+use crate::cfg::Cfg;
+use crate::resolve::symbol_type;
+use crate::symbols::*;
+
+/// Type checking. Runs after name resolution and sets the `RetVal` of every expression
+pub struct TypeChecker<'s> {
+    /// The global context built by name resolution
+    globals: &'s Context,
+    /// The return type of the current function
+    ret: SymbolType,
+    /// How many loops contain the current statement
+    loops: usize,
+    /// How many `switch`es contain the current statement, `break` also leaves them
+    switches: usize,
+}
+
+fn is_struct(t: &SymbolType) -> bool {
+    t.type_base == TypeName::TbStruct
+}
+fn is_array(t: &SymbolType) -> bool {
+    t.num_elements > -1
+}
+fn rvalue(symbol_type: SymbolType) -> RetVal {
+    RetVal {
+        symbol_type: Some(symbol_type),
+        ..Default::default()
+    }
+}
+fn lvalue(symbol_type: SymbolType) -> RetVal {
+    RetVal {
+        symbol_type: Some(symbol_type),
+        is_lval: true,
+        ..Default::default()
+    }
+}
+fn constant(type_base: TypeName, num_elements: isize, ctval: CtVal) -> RetVal {
+    RetVal {
+        symbol_type: Some(SymbolType::new(type_base, num_elements)),
+        is_ctval: true,
+        ctval: Some(ctval),
+        ..Default::default()
+    }
+}
+fn int() -> SymbolType {
+    SymbolType::new(TypeName::TbInt, -1)
+}
+
+// Constant folding: the operators applied to constants are computed here, like the
+// instructions of the virtual machine would do it
+
+/// The value of `e` if it is known at compile time
+fn ctval(e: &Expr) -> Option<&CtVal> {
+    if e.rv.is_ctval {
+        e.rv.ctval.as_ref()
+    } else {
+        None
+    }
+}
+fn is_number(t: &SymbolType) -> bool {
+    !is_array(t)
+        && matches!(
+            t.type_base,
+            TypeName::TbChar | TypeName::TbInt | TypeName::TbDouble
+        )
+}
+/// `int` and `char`, the types of the modulo, bitwise and shift operators
+fn is_integer(t: &SymbolType) -> bool {
+    !is_array(t) && matches!(t.type_base, TypeName::TbChar | TypeName::TbInt)
+}
+/// Wraps `e` in an implicit conversion to `t` if both are numbers of different types,
+/// codegen emits the `CAST` instructions for them
+fn coerce(e: &mut Expr, t: &SymbolType) {
+    if !is_number(e.ty()) || !is_number(t) || e.ty().type_base == t.type_base {
+        return;
+    }
+    let loc = e.loc;
+    let operand = std::mem::replace(e, Expr::new(ExprKind::Int(0), loc));
+    let value = ctval(&operand).and_then(|v| convert(v, t));
+    let rv = folded(rvalue(t.clone()), value);
+    *e = Expr {
+        rv,
+        ..Expr::new(ExprKind::Conv(Box::new(operand)), loc)
+    };
+}
+/// Marks `rv` as a constant if it has a `value`
+fn folded(rv: RetVal, value: Option<CtVal>) -> RetVal {
+    match value {
+        Some(value) => RetVal {
+            is_ctval: true,
+            ctval: Some(value),
+            ..rv
+        },
+        None => rv,
+    }
+}
+/// Whether the constant is not zero. A string is an address, which is never zero
+fn truth(v: &CtVal) -> bool {
+    match v {
+        CtVal::IntChar(i) => *i != 0,
+        CtVal::Double(d) => *d != 0.,
+        CtVal::String(_) => true,
+    }
+}
+/// The constant converted to the type `t`, as the `CAST` instructions do
+fn convert(v: &CtVal, t: &SymbolType) -> Option<CtVal> {
+    if is_array(t) {
+        return None;
+    }
+    let v = match (v, &t.type_base) {
+        (CtVal::IntChar(i), TypeName::TbInt) => CtVal::IntChar(*i),
+        (CtVal::IntChar(i), TypeName::TbChar) => CtVal::IntChar(*i as u8 as isize),
+        (CtVal::IntChar(i), TypeName::TbDouble) => CtVal::Double(*i as f64),
+        (CtVal::Double(d), TypeName::TbInt) => CtVal::IntChar(*d as isize),
+        (CtVal::Double(d), TypeName::TbChar) => CtVal::IntChar(*d as u8 as isize),
+        (CtVal::Double(d), TypeName::TbDouble) => CtVal::Double(*d),
+        _ => return None,
+    };
+    Some(v)
+}
+/// Folds `op v`, a char operand of `-` and `~` is already converted to int
+fn fold_unary(op: UnaryOp, v: &CtVal) -> Option<CtVal> {
+    match op {
+        UnaryOp::Not => Some(CtVal::IntChar(!truth(v) as isize)),
+        UnaryOp::BitNot => match v {
+            CtVal::IntChar(i) => Some(CtVal::IntChar(!i)),
+            _ => None,
+        },
+        UnaryOp::Neg => match v {
+            CtVal::IntChar(i) => Some(CtVal::IntChar(i.wrapping_neg())),
+            CtVal::Double(d) => Some(CtVal::Double(-d)),
+            CtVal::String(_) => None,
+        },
+    }
+}
+/// Folds `l op r`, the operands are first converted to their common type
+fn fold_binary(op: BinOp, l: (&CtVal, &SymbolType), r: (&CtVal, &SymbolType)) -> Option<CtVal> {
+    let bool_val = |b: bool| Some(CtVal::IntChar(b as isize));
+    match op {
+        BinOp::Or => return bool_val(truth(l.0) || truth(r.0)),
+        BinOp::And => return bool_val(truth(l.0) && truth(r.0)),
+        _ if is_array(l.1) || is_array(r.1) => return None,
+        _ => {}
+    }
+    let t = l.1.clone().get_arith_type(r.1.clone())?;
+    match (convert(l.0, &t)?, convert(r.0, &t)?) {
+        (CtVal::IntChar(a), CtVal::IntChar(b)) => {
+            let value = match op {
+                BinOp::Equal => return bool_val(a == b),
+                BinOp::NotEq => return bool_val(a != b),
+                BinOp::Less => return bool_val(a < b),
+                BinOp::LessEq => return bool_val(a <= b),
+                BinOp::Greater => return bool_val(a > b),
+                BinOp::GreaterEq => return bool_val(a >= b),
+                BinOp::Add => a.wrapping_add(b),
+                BinOp::Sub => a.wrapping_sub(b),
+                BinOp::Mul => a.wrapping_mul(b),
+                BinOp::Div => a.wrapping_div(b),
+                BinOp::Mod => a.wrapping_rem(b),
+                BinOp::BitAnd => a & b,
+                BinOp::BitOr => a | b,
+                BinOp::BitXor => a ^ b,
+                BinOp::Shl => a.wrapping_shl(b as u32),
+                BinOp::Shr => a.wrapping_shr(b as u32),
+                BinOp::Or | BinOp::And => unreachable!(),
+            };
+            // The chars wrap around
+            convert(&CtVal::IntChar(value), &t)
+        }
+        (CtVal::Double(a), CtVal::Double(b)) => {
+            let value = match op {
+                BinOp::Equal => return bool_val(a == b),
+                BinOp::NotEq => return bool_val(a != b),
+                BinOp::Less => return bool_val(a < b),
+                BinOp::LessEq => return bool_val(a <= b),
+                BinOp::Greater => return bool_val(a > b),
+                BinOp::GreaterEq => return bool_val(a >= b),
+                BinOp::Add => a + b,
+                BinOp::Sub => a - b,
+                BinOp::Mul => a * b,
+                BinOp::Div => a / b,
+                // Only the integers have the other operators
+                _ => unreachable!(),
+            };
+            Some(CtVal::Double(value))
+        }
+        _ => None,
+    }
+}
+
+/// Whether `e` is made only of constants and operators on them
+fn only_constants(e: &Expr) -> bool {
+    match &e.kind {
+        ExprKind::Int(_) | ExprKind::Real(_) | ExprKind::Char(_) | ExprKind::Str(_) => true,
+        ExprKind::Unary(_, operand) | ExprKind::Cast(_, operand) => only_constants(operand),
+        ExprKind::Binary(_, left, right) => only_constants(left) && only_constants(right),
+        _ => false,
+    }
+}
+/// The value of an int expression made only of constants: `10 * 2 + 1`, `'z' - 'a'`.
+/// `None` if it uses something else. The expression is folded by the type checker, before
+/// the names are resolved, so the array sizes follow the rules of the other constants
+pub fn const_int(e: &Expr, globals: &Context) -> Option<isize> {
+    if !only_constants(e) {
+        return None;
+    }
+    let mut e = e.clone();
+    TypeChecker::new(globals).check_expr(&mut e);
+    match ctval(&e) {
+        Some(CtVal::IntChar(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+impl<'s> TypeChecker<'s> {
+    pub fn new(globals: &'s Context) -> Self {
+        Self {
+            globals,
+            ret: SymbolType::default(),
+            loops: 0,
+            switches: 0,
+        }
+    }
+    pub fn check(&mut self, unit: &mut Unit) {
+        for item in &mut unit.items {
+            if let Item::Var(v) = item {
+                self.check_var(v, true);
+            }
+            if let Item::Func(FuncDecl {
+                name,
+                ret,
+                body: Some(body),
+                loc,
+                ..
+            }) = item
+            {
+                self.ret = symbol_type(ret, self.globals, *loc);
+                self.check_stm(body);
+                if self.ret.type_base != TypeName::TbVoid && Cfg::new(*name, body).falls_off_end() {
+                    panic!(
+                        "Error in {}, not all paths of the function `{}` return a value",
+                        loc, name
+                    );
+                }
+            }
+        }
+    }
+
+    /// The initializers of the globals are written in the globals image, they must be
+    /// constants
+    fn check_var(&mut self, v: &mut VarDecl, global: bool) {
+        let t = v.sym.as_ref().unwrap().symbol_type.clone();
+        if let Some(init) = &mut v.init {
+            self.check_init(init, &t, global);
+        }
+    }
+    /// A list gives the first elements of an array or the first members of a struct,
+    /// the others are zero
+    fn check_init(&mut self, init: &mut Init, t: &SymbolType, global: bool) {
+        match init {
+            Init::Expr(e) if is_array(t) => {
+                self.check_expr(e);
+                let s = match &e.kind {
+                    ExprKind::Str(s) if t.type_base == TypeName::TbChar && t.dims.is_empty() => s,
+                    _ => panic!(
+                        "Error in {}, an array of type `{}` must be initialized with a list",
+                        e.loc, t
+                    ),
+                };
+                if s.len() as isize >= t.num_elements {
+                    panic!(
+                        "Error in {}, the string needs {} chars, `{}` has only {}",
+                        e.loc,
+                        s.len() + 1,
+                        t,
+                        t.num_elements
+                    );
+                }
+            }
+            Init::Expr(e) => {
+                self.check_expr(e);
+                t.cast(e.ty(), &e.loc);
+                coerce(e, t);
+                if global && !e.rv.is_ctval {
+                    panic!(
+                        "Error in {}, the initializer of a global must be a constant",
+                        e.loc
+                    );
+                }
+            }
+            Init::List(items, loc) => {
+                let types = match &t.struct_symbol {
+                    _ if is_array(t) => vec![t.elem(); t.num_elements as usize],
+                    Some(s) => {
+                        s.am.iter()
+                            .flat_map(|am| am.values())
+                            .map(|m| m.symbol_type.clone())
+                            .collect()
+                    }
+                    None => panic!(
+                        "Error in {}, a value of type `{}` cannot be initialized with a list",
+                        loc, t
+                    ),
+                };
+                if items.len() > types.len() {
+                    panic!("Error in {}, too many initializers for `{}`", loc, t);
+                }
+                for (item, t) in items.iter_mut().zip(&types) {
+                    self.check_init(item, t, global);
+                }
+            }
+        }
+    }
+
+    /// Conditions can be numbers or arrays
+    fn check_cond(&mut self, cond: &mut Expr) {
+        self.check_expr(cond);
+        if is_struct(cond.ty()) {
+            panic!(
+                "Error in {}, a structure cannot be logically tested",
+                cond.loc
+            );
+        }
+    }
+
+    fn check_stm(&mut self, stm: &mut Stm) {
+        match &mut stm.kind {
+            StmKind::Block(stms) => stms.iter_mut().for_each(|s| self.check_stm(s)),
+            StmKind::Var(v) => self.check_var(v, false),
+            StmKind::Empty => {}
+            StmKind::Break => {
+                if self.loops + self.switches == 0 {
+                    panic!(
+                        "Error in {}, `break` outside of a loop or a `switch`",
+                        stm.loc
+                    );
+                }
+            }
+            StmKind::Continue => {
+                if self.loops == 0 {
+                    panic!("Error in {}, `continue` outside of a loop", stm.loc);
+                }
+            }
+            StmKind::If { cond, then, els } => {
+                self.check_cond(cond);
+                self.check_stm(then);
+                if let Some(els) = els {
+                    self.check_stm(els);
+                }
+            }
+            StmKind::While { cond, body } | StmKind::DoWhile { body, cond } => {
+                self.check_cond(cond);
+                self.check_loop(body);
+            }
+            StmKind::Switch { value, cases } => self.check_switch(value, cases),
+            StmKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.check_expr(init);
+                }
+                if let Some(cond) = cond {
+                    self.check_cond(cond);
+                }
+                if let Some(step) = step {
+                    self.check_expr(step);
+                }
+                self.check_loop(body);
+            }
+            StmKind::Return(Some(value)) => {
+                self.check_expr(value);
+                if self.ret.type_base == TypeName::TbVoid {
+                    panic!(
+                        "Error in {}, a void function cannot return a value",
+                        stm.loc
+                    );
+                }
+                self.ret.cast(value.ty(), &value.loc);
+                coerce(value, &self.ret);
+            }
+            StmKind::Return(None) => {
+                if self.ret.type_base != TypeName::TbVoid {
+                    panic!(
+                        "Error in {}, the function must return a value of type `{}`",
+                        stm.loc, self.ret
+                    );
+                }
+            }
+            StmKind::Expr(e) => self.check_expr(e),
+        }
+    }
+
+    fn check_loop(&mut self, body: &mut Stm) {
+        self.loops += 1;
+        self.check_stm(body);
+        self.loops -= 1;
+    }
+
+    /// The value is an int or a char, the cases are distinct constants converted to its type
+    fn check_switch(&mut self, value: &mut Expr, cases: &mut [Case]) {
+        self.check_expr(value);
+        if !is_integer(value.ty()) {
+            panic!(
+                "Error in {}, the `switch` value must be an int or a char, not `{}`",
+                value.loc,
+                value.ty()
+            );
+        }
+        let mut seen = vec![];
+        let mut default = false;
+        for case in cases.iter_mut() {
+            match &mut case.value {
+                Some(e) => {
+                    self.check_expr(e);
+                    if !is_integer(e.ty()) {
+                        panic!(
+                            "Error in {}, a `case` value must be an int or a char, not `{}`",
+                            e.loc,
+                            e.ty()
+                        );
+                    }
+                    coerce(e, value.ty());
+                    let v = match ctval(e) {
+                        Some(CtVal::IntChar(v)) => *v,
+                        _ => panic!("Error in {}, a `case` value must be a constant", e.loc),
+                    };
+                    if seen.contains(&v) {
+                        panic!("Error in {}, duplicate `case` value {}", e.loc, v);
+                    }
+                    seen.push(v);
+                }
+                None if default => {
+                    panic!(
+                        "Error in {}, a `switch` can have only one `default`",
+                        case.loc
+                    )
+                }
+                None => default = true,
+            }
+        }
+        self.switches += 1;
+        for case in cases {
+            case.stms.iter_mut().for_each(|s| self.check_stm(s));
+        }
+        self.switches -= 1;
+    }
+
+    fn check_expr(&mut self, e: &mut Expr) {
+        let loc = e.loc;
+        e.rv = match &mut e.kind {
+            ExprKind::Int(i) => constant(TypeName::TbInt, -1, CtVal::IntChar(*i)),
+            ExprKind::Char(c) => constant(TypeName::TbChar, -1, CtVal::IntChar(*c as isize)),
+            ExprKind::Real(d) => constant(TypeName::TbDouble, -1, CtVal::Double(*d as f64)),
+            ExprKind::Str(s) => constant(TypeName::TbChar, 0, CtVal::String(s.clone())),
+            ExprKind::Id(name) => {
+                let s = e.sym.as_ref().expect("the names were not resolved");
+                match s.class {
+                    ClassType::ClsVar => lvalue(s.symbol_type.clone()),
+                    ClassType::ClsFunc | ClassType::ClsExtFunc => {
+                        panic!("Error in {}, missing call for function `{}`", loc, name)
+                    }
+                    ClassType::ClsStruct => {
+                        panic!("Error in {}, `{}` is a struct, not a variable", loc, name)
+                    }
+                }
+            }
+            ExprKind::Call(name, args) => {
+                let s = e.sym.as_ref().expect("the names were not resolved");
+                if s.class != ClassType::ClsFunc && s.class != ClassType::ClsExtFunc {
+                    panic!("Error in {}, `{}` is not a function", loc, name)
+                }
+                let params = s.am.as_ref().unwrap();
+                if args.len() > params.len() {
+                    panic!(
+                        "Error in {}, too many arguments in function `{}` call",
+                        loc, name
+                    );
+                }
+                if args.len() < params.len() {
+                    panic!(
+                        "Error in {}, too few arguments in function `{}` call",
+                        loc, name
+                    );
+                }
+                for (arg, param) in args.iter_mut().zip(params.values()) {
+                    self.check_expr(arg);
+                    param.symbol_type.cast(arg.ty(), &arg.loc);
+                    coerce(arg, &param.symbol_type);
+                }
+                rvalue(s.symbol_type.clone())
+            }
+            ExprKind::Index(array, index) => {
+                self.check_expr(array);
+                self.check_expr(index);
+                if !is_array(array.ty()) {
+                    panic!("Error in {}, only an array can be indexed", loc);
+                }
+                int().cast(index.ty(), &index.loc);
+                coerce(index, &int());
+                // The elements of an array member of a struct value cannot be assigned,
+                // the other arrays are addresses of elements which can
+                let is_lval = match &array.kind {
+                    ExprKind::Member(..) | ExprKind::Index(..) => array.rv.is_lval,
+                    _ => true,
+                };
+                RetVal {
+                    is_lval,
+                    ..lvalue(array.ty().elem())
+                }
+            }
+            ExprKind::Member(object, member) => {
+                self.check_expr(object);
+                let s_struct = match &object.ty().struct_symbol {
+                    Some(s) if !is_array(object.ty()) => s,
+                    _ => panic!("Error in {}, `{}`'s parent is not a struct", loc, member),
+                };
+                match s_struct.find_symbol(*member) {
+                    Some(m) => RetVal {
+                        is_lval: object.rv.is_lval,
+                        ..lvalue(m.symbol_type.clone())
+                    },
+                    None => panic!(
+                        "Error in {}, struct {} does not have the member {}",
+                        loc, s_struct.name, member
+                    ),
+                }
+            }
+            ExprKind::Unary(op, operand) => {
+                self.check_expr(operand);
+                // A char operand of `-` and `~` is promoted to int, like in the binary operations
+                if *op != UnaryOp::Not && operand.ty().type_base == TypeName::TbChar {
+                    coerce(operand, &int());
+                }
+                let t = operand.ty();
+                if is_struct(t) {
+                    panic!(
+                        "Error in {}, unary `{}` cannot be applied to structures",
+                        loc,
+                        op.text()
+                    )
+                }
+                let rv = match op {
+                    UnaryOp::Neg if is_array(t) => {
+                        panic!("Error in {}, unary `-` cannot be applied to arrays", loc)
+                    }
+                    UnaryOp::Neg => rvalue(t.clone()),
+                    UnaryOp::Not => rvalue(int()),
+                    UnaryOp::BitNot if !is_integer(t) => panic!(
+                        "Error in {}, unary `~` needs an int or char operand, not `{}`",
+                        loc, t
+                    ),
+                    UnaryOp::BitNot => rvalue(t.clone()),
+                };
+                let value = ctval(operand).and_then(|v| fold_unary(*op, v));
+                folded(rv, value)
+            }
+            ExprKind::Binary(op, left, right) => {
+                self.check_expr(left);
+                self.check_expr(right);
+                let rv = self.check_binary(*op, left.ty(), right.ty(), loc);
+                if *op != BinOp::And && *op != BinOp::Or {
+                    if let Some(t) = left.ty().clone().get_arith_type(right.ty().clone()) {
+                        coerce(left, &t);
+                        coerce(right, &t);
+                    }
+                }
+                let divides = matches!(op, BinOp::Div | BinOp::Mod);
+                if divides && ctval(right).map(truth) == Some(false) {
+                    panic!("Error in {}, division by zero", loc);
+                }
+                let value = match (ctval(left), ctval(right)) {
+                    (Some(l), Some(r)) => fold_binary(*op, (l, left.ty()), (r, right.ty())),
+                    _ => None,
+                };
+                folded(rv, value)
+            }
+            ExprKind::Assign(dst, src) => {
+                self.check_expr(dst);
+                self.check_expr(src);
+                if !dst.rv.is_lval {
+                    panic!("Error in {}, cannot assign to a non-lval", loc);
+                }
+                if is_array(dst.ty()) || is_array(src.ty()) {
+                    panic!("Error in {}, the arrays cannot be assigned", loc);
+                }
+                dst.ty().cast(src.ty(), &loc);
+                coerce(src, dst.ty());
+                rvalue(dst.ty().clone())
+            }
+            // The operation is done in the arithmetic type of the operands, the codegen
+            // converts `dst` to it and the result back to the type of `dst`
+            ExprKind::OpAssign(op, dst, src) => {
+                self.check_expr(dst);
+                self.check_expr(src);
+                if !dst.rv.is_lval {
+                    panic!("Error in {}, cannot assign to a non-lval", loc);
+                }
+                let t = self
+                    .check_binary(*op, dst.ty(), src.ty(), loc)
+                    .symbol_type
+                    .unwrap();
+                coerce(src, &t);
+                if *op == BinOp::Div && ctval(src).map(truth) == Some(false) {
+                    panic!("Error in {}, division by zero", loc);
+                }
+                rvalue(dst.ty().clone())
+            }
+            ExprKind::IncDec { op, operand, .. } => {
+                self.check_expr(operand);
+                if !operand.rv.is_lval {
+                    panic!("Error in {}, cannot assign to a non-lval", loc);
+                }
+                self.check_binary(*op, operand.ty(), &int(), loc);
+                rvalue(operand.ty().clone())
+            }
+            ExprKind::Cast(ty, operand) => {
+                self.check_expr(operand);
+                let t = symbol_type(ty, self.globals, loc);
+                t.cast(operand.ty(), &loc);
+                let value = ctval(operand).and_then(|v| convert(v, &t));
+                folded(rvalue(t), value)
+            }
+            ExprKind::Conv(_) => unreachable!("the conversions are added by the type checker"),
+        };
+    }
+
+    fn check_binary(&self, op: BinOp, l: &SymbolType, r: &SymbolType, loc: Loc) -> RetVal {
+        let what = match op {
+            BinOp::Or | BinOp::And => "logically tested",
+            BinOp::Equal | BinOp::NotEq => "compared",
+            BinOp::Less | BinOp::LessEq | BinOp::Greater | BinOp::GreaterEq => "compared",
+            BinOp::Add | BinOp::Sub => "added / subtracted",
+            BinOp::Mul | BinOp::Div | BinOp::Mod => "multiplied / divided",
+            BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => "used in a bitwise operation",
+            BinOp::Shl | BinOp::Shr => "shifted",
+        };
+        if is_struct(l) || is_struct(r) {
+            panic!("Error in {}, a structure cannot be {}", loc, what);
+        }
+        match op {
+            BinOp::Or | BinOp::And | BinOp::Equal | BinOp::NotEq => rvalue(int()),
+            _ if is_array(l) || is_array(r) => {
+                panic!("Error in {}, an array cannot be {}", loc, what)
+            }
+            BinOp::Less | BinOp::LessEq | BinOp::Greater | BinOp::GreaterEq => rvalue(int()),
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div => {
+                match l.clone().get_arith_type(r.clone()) {
+                    Some(t) => rvalue(t),
+                    None => panic!("Error in {}, invalid operands for `{}`", loc, op.text()),
+                }
+            }
+            _ => match [l, r].iter().find(|t| !is_integer(t)) {
+                Some(t) => panic!(
+                    "Error in {}, `{}` needs int or char operands, not `{}`",
+                    loc,
+                    op.text(),
+                    t
+                ),
+                None => rvalue(l.clone().get_arith_type(r.clone()).unwrap()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::asdr::SyntaxAnalyser;
+    use crate::ast::*;
+    use crate::compile::{compile, CompileOptions, Stage};
+    use crate::lexer::Lexer;
+    use crate::symbols::{Context, TypeName};
+    use crate::typecheck::const_int;
+
+    fn check(src: &str) {
+        let options = CompileOptions {
+            stage: Stage::Typecheck,
+            library: true,
+            ..Default::default()
+        };
+        compile(Lexer::new(src, "").get_tokens(), &options);
+    }
+    #[test]
+    fn typecheck_ok() {
+        check("struct P { int x; }; struct P p[2]; double f(char s[]) { return s[0] + p[1].x; }");
+    }
+    #[test]
+    #[should_panic(expected = "cannot assign to a non-lval")]
+    fn typecheck_assign_rvalue() {
+        check("int f() { f() = 2; }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, `%` needs int or char operands, not `double`")]
+    fn typecheck_mod_double() {
+        check("void f() { int x; x = 7 % 2.0; }");
+    }
+    #[test]
+    #[should_panic(expected = "unary `~` needs an int or char operand, not `double`")]
+    fn typecheck_bit_not_double() {
+        check("void f() { double d; ~d; }");
+    }
+    #[test]
+    #[should_panic(expected = "a structure cannot be shifted")]
+    fn typecheck_shift_struct() {
+        check("struct P { int x; }; void f() { struct P p; p << 1; }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, division by zero")]
+    fn typecheck_mod_by_zero() {
+        check("void f() { int x; x = x % (1 - 1); }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, cannot assign to a non-lval")]
+    fn typecheck_assign_element_of_struct_value() {
+        check("struct P { int v[2]; }; struct P mk() { struct P p; return p; } void f() { mk().v[1] = 3; }");
+    }
+    #[test]
+    fn typecheck_fold_char_unary() {
+        // The size of `int a[...]`, folded as a constant
+        let fold = |size: &str| {
+            let src = format!("int a[{}];", size);
+            let unit = SyntaxAnalyser::new(Lexer::new(&src, "").get_tokens()).analyse_syntax();
+            match &unit.items[0] {
+                Item::Var(v) => const_int(v.ty.dims[0].as_ref().unwrap(), &Context::default()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(fold("-'a'"), Some(-97));
+        assert_eq!(fold("~'a'"), Some(-98));
+        assert_eq!(fold("-((char)200)"), Some(-200));
+        assert_eq!(fold("!'a'"), Some(0));
+    }
+    #[test]
+    fn typecheck_assign_element_of_returned_array() {
+        check("int g[3]; int *f() { return g; } void h() { int i; f()[i] = 5; f()[1]++; }");
+    }
+    #[test]
+    #[should_panic(expected = "cannot assign to a non-lval")]
+    fn typecheck_inc_rvalue() {
+        check("void f() { int x; (x + 1)++; }");
+    }
+    #[test]
+    #[should_panic(expected = "an array cannot be added / subtracted")]
+    fn typecheck_compound_assign_array() {
+        check("void f() { int a[2]; a += 1; }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, division by zero")]
+    fn typecheck_compound_division_by_zero() {
+        check("void f() { double d; d /= 0; }");
+    }
+    #[test]
+    #[should_panic(expected = "too few arguments in function `put_i` call")]
+    fn typecheck_call_args() {
+        check("void f() { put_i(); }");
+    }
+    #[test]
+    #[should_panic(expected = "struct P does not have the member y")]
+    fn typecheck_member() {
+        check("struct P { int x; }; void f() { struct P p; p.y = 1; }");
+    }
+    #[test]
+    fn typecheck_conversions() {
+        let options = CompileOptions {
+            stage: Stage::Typecheck,
+            library: true,
+            ..Default::default()
+        };
+        let src = "double f(int i, char c) { return i + c * 2.5; }";
+        let c = compile(Lexer::new(src, "").get_tokens(), &options);
+        let body = match &c.unit.items[0] {
+            Item::Func(f) => f.body.as_ref().unwrap(),
+            _ => unreachable!(),
+        };
+        let value = match &body.kind {
+            StmKind::Block(stms) => match &stms[0].kind {
+                StmKind::Return(Some(value)) => value,
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        // (double)i + ((double)c * 2.5)
+        let (left, right) = match &value.kind {
+            ExprKind::Binary(BinOp::Add, left, right) => (left, right),
+            _ => panic!("{}", value),
+        };
+        assert!(matches!(left.kind, ExprKind::Conv(_)));
+        assert_eq!(left.ty().type_base, TypeName::TbDouble);
+        match &right.kind {
+            ExprKind::Binary(BinOp::Mul, c, _) => assert!(matches!(c.kind, ExprKind::Conv(_))),
+            _ => panic!("{}", right),
+        }
+        assert_eq!(value.ty().type_base, TypeName::TbDouble);
+    }
+    #[test]
+    fn typecheck_folded_loop_returns() {
+        // The condition is folded, so the end of the function cannot be reached
+        check("int f() { while (2 > 1) { } }");
+    }
+    #[test]
+    fn typecheck_returns() {
+        check(
+            "int f(int x) { if (x) return 1; else { return 2; } }
+            int g() { for (;;) { if (g()) return 1; } }
+            int h() { while (1) { return 1; } }
+            void v() { while (1) break; return; }",
+        );
+    }
+    #[test]
+    #[should_panic(expected = "An array cannot be converted to an array of another type")]
+    fn typecheck_array_dimensions() {
+        check("int m[3][4]; int f(int a[][3]) { return a[0][0]; } void g() { f(m); }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, `break` outside of a loop")]
+    fn typecheck_break_outside_loop() {
+        check("void f() { break; while (1) break; }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, `continue` outside of a loop")]
+    fn typecheck_continue_outside_loop() {
+        check("void f(int x) { do continue; while (x); switch (x) { case 1: break; continue; } }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, duplicate `case` value 97")]
+    fn typecheck_duplicate_case() {
+        check("void f(char c) { switch (c) { case 'a': case 'b': break; case 96 + 1: ; } }");
+    }
+    #[test]
+    #[should_panic(expected = "a `case` value must be a constant")]
+    fn typecheck_case_not_constant() {
+        check("void f(int x, int y) { switch (x) { case 1: case y: ; } }");
+    }
+    #[test]
+    #[should_panic(expected = "the `switch` value must be an int or a char, not `double`")]
+    fn typecheck_switch_double() {
+        check("void f(double d) { switch (d) { } }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 2, a `switch` can have only one `default`")]
+    fn typecheck_switch_two_defaults() {
+        check("void f(int x) { switch (x) { default: break;\n default: ; } }");
+    }
+    #[test]
+    #[should_panic(expected = "the function must return a value of type `double`")]
+    fn typecheck_return_without_value() {
+        check("double f() { return; }");
+    }
+    #[test]
+    #[should_panic(expected = "not all paths of the function `f` return a value")]
+    fn typecheck_missing_return() {
+        check("int f(int x) { if (x) return 1; while (1) { break; } }");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 1, the initializer of a global must be a constant")]
+    fn typecheck_global_init_constant() {
+        check("int f() { return 1; } int g = f();");
+    }
+    #[test]
+    #[should_panic(expected = "Error in line 2, too many initializers for `struct P`")]
+    fn typecheck_init_too_many() {
+        check(
+            "struct P { int x; char s[3]; };
+            void f() { struct P ok[2] = {{1, \"ab\"}, {2}}; struct P p = {1, \"ab\", 3}; }",
+        );
+    }
+    #[test]
+    #[should_panic(expected = "the string needs 4 chars, `char[3]` has only 3")]
+    fn typecheck_init_string_too_long() {
+        check("char s[3] = \"abc\";");
+    }
+}
@@ -0,0 +1,20 @@
+struct S{
+	int n;
+	char text[16];
+	};
+
+struct S s;
+
+void f(int v){
+	s.n=v;
+	}
+
+void main(){
+	double r;
+	r=3.14;
+	f((int)r);
+	/* nested * stars ** in a comment */
+	put_i(s.n);
+	put_c('\'');
+	put_s("\\end\n");
+	}
@@ -0,0 +1,8 @@
+#ifndef DEFS_H
+#define DEFS_H
+#define SIZE 16
+struct Pt {
+    int x;
+    int y;
+};
+#endif
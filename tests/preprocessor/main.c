@@ -0,0 +1,8 @@
+#include "defs.h"
+#include "defs.h"
+int v[SIZE];
+
+void main() {
+    struct Pt p;
+    p.x = v[0];
+}
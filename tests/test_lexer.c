@@ -0,0 +1,13 @@
+// line comment
+/* block
+   comment */
+int main(){
+	int i, x[10];
+	double d = 1.5e-3 + 0.25 + 2E3;
+	char c = '\n';
+	char s[] = "tab\there \"quoted\"";
+	x[0] = 0x1F + 017 + 0 + 42;
+	if (i <= 10 && i >= 0 || !(i != 5) && i == 3) i = i / 2 * 3 - 1;
+	while (i < x[0] || i > 100) break;
+	return 0;
+}
@@ -0,0 +1,34 @@
+struct Pt{
+	int x;
+	int y;
+	};
+struct Pt points[10];
+
+double max(double a,double b){
+	if(a>b)return a;
+		else return b;
+	}
+
+int len(char s[]){
+	int i;
+	i=0;
+	while(s[i])i=i+1;
+	return i;
+	}
+
+void main(){
+	int i;
+	i=10;
+	while(i!=0){
+		put_i(i);
+		i=i-1;
+		}
+	for(i=0;i<10;i=i+1){
+		points[i].x=i;
+		points[i].y=i*2;
+		}
+	put_d(max(2.5,3));
+	put_i(len("hello"));
+	put_c('a');
+	if(!(i>=10)&&points[0].x==0||i<=0)put_s("done");
+	}